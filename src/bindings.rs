@@ -0,0 +1,454 @@
+use std::collections::HashMap;
+use std::fs;
+use std::hash::Hash;
+use std::path::Path;
+
+use sdl2::controller::{Axis, Button};
+use sdl2::keyboard::Keycode;
+
+use crate::input::Macro;
+use crate::joypad::Key;
+
+// Emulator functions that can be bound like buttons
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Hotkey {
+    Pause,
+    // Held rather than toggled
+    FastForward,
+    Screenshot,
+    Reset,
+    RecordMovie,
+    PlayMovie,
+    ToggleReadOnly,
+    RecordGif,
+    RecordVideo,
+    NextFilter,
+    Menu,
+    NextPalette,
+    NextColorCorrection,
+    VramViewer,
+    Inspector,
+    MemoryEditor,
+    ToggleCheats,
+    RamSearch,
+    Trace,
+    Debugger,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Binding {
+    Button(Key),
+    // Pressed and released every few frames while held
+    Turbo(Key),
+    // Index into Bindings::macros
+    Macro(usize),
+    Hotkey(Hotkey),
+}
+
+// Names used in bindings.toml
+pub const ACTIONS: [(&str, Binding); 30] = [
+    ("up", Binding::Button(Key::Up)),
+    ("down", Binding::Button(Key::Down)),
+    ("left", Binding::Button(Key::Left)),
+    ("right", Binding::Button(Key::Right)),
+    ("a", Binding::Button(Key::A)),
+    ("b", Binding::Button(Key::B)),
+    ("start", Binding::Button(Key::Start)),
+    ("select", Binding::Button(Key::Select)),
+    ("turbo_a", Binding::Turbo(Key::A)),
+    ("turbo_b", Binding::Turbo(Key::B)),
+    ("pause", Binding::Hotkey(Hotkey::Pause)),
+    ("fast_forward", Binding::Hotkey(Hotkey::FastForward)),
+    ("screenshot", Binding::Hotkey(Hotkey::Screenshot)),
+    ("reset", Binding::Hotkey(Hotkey::Reset)),
+    ("record_movie", Binding::Hotkey(Hotkey::RecordMovie)),
+    ("play_movie", Binding::Hotkey(Hotkey::PlayMovie)),
+    ("read_only", Binding::Hotkey(Hotkey::ToggleReadOnly)),
+    ("record_gif", Binding::Hotkey(Hotkey::RecordGif)),
+    ("record_video", Binding::Hotkey(Hotkey::RecordVideo)),
+    ("next_filter", Binding::Hotkey(Hotkey::NextFilter)),
+    ("menu", Binding::Hotkey(Hotkey::Menu)),
+    ("next_palette", Binding::Hotkey(Hotkey::NextPalette)),
+    (
+        "color_correction",
+        Binding::Hotkey(Hotkey::NextColorCorrection),
+    ),
+    ("vram_viewer", Binding::Hotkey(Hotkey::VramViewer)),
+    ("inspector", Binding::Hotkey(Hotkey::Inspector)),
+    ("memory_editor", Binding::Hotkey(Hotkey::MemoryEditor)),
+    ("cheats", Binding::Hotkey(Hotkey::ToggleCheats)),
+    ("ram_search", Binding::Hotkey(Hotkey::RamSearch)),
+    ("trace", Binding::Hotkey(Hotkey::Trace)),
+    ("debugger", Binding::Hotkey(Hotkey::Debugger)),
+];
+
+const DEFAULT_KEYS: [(Keycode, Binding); 30] = [
+    (Keycode::Up, Binding::Button(Key::Up)),
+    (Keycode::Down, Binding::Button(Key::Down)),
+    (Keycode::Left, Binding::Button(Key::Left)),
+    (Keycode::Right, Binding::Button(Key::Right)),
+    (Keycode::Z, Binding::Button(Key::A)),
+    (Keycode::X, Binding::Button(Key::B)),
+    (Keycode::Return, Binding::Button(Key::Start)),
+    (Keycode::RShift, Binding::Button(Key::Select)),
+    (Keycode::A, Binding::Turbo(Key::A)),
+    (Keycode::S, Binding::Turbo(Key::B)),
+    (Keycode::P, Binding::Hotkey(Hotkey::Pause)),
+    (Keycode::Space, Binding::Hotkey(Hotkey::FastForward)),
+    (Keycode::F3, Binding::Hotkey(Hotkey::Screenshot)),
+    (Keycode::F4, Binding::Hotkey(Hotkey::Reset)),
+    (Keycode::F10, Binding::Hotkey(Hotkey::RecordMovie)),
+    (Keycode::M, Binding::Hotkey(Hotkey::PlayMovie)),
+    (Keycode::Q, Binding::Hotkey(Hotkey::ToggleReadOnly)),
+    (Keycode::G, Binding::Hotkey(Hotkey::RecordGif)),
+    (Keycode::V, Binding::Hotkey(Hotkey::RecordVideo)),
+    (Keycode::F, Binding::Hotkey(Hotkey::NextFilter)),
+    (Keycode::Tab, Binding::Hotkey(Hotkey::Menu)),
+    (Keycode::F1, Binding::Hotkey(Hotkey::NextPalette)),
+    (Keycode::F2, Binding::Hotkey(Hotkey::NextColorCorrection)),
+    (Keycode::F5, Binding::Hotkey(Hotkey::VramViewer)),
+    (Keycode::F6, Binding::Hotkey(Hotkey::Inspector)),
+    (Keycode::F7, Binding::Hotkey(Hotkey::MemoryEditor)),
+    (Keycode::F8, Binding::Hotkey(Hotkey::ToggleCheats)),
+    (Keycode::F9, Binding::Hotkey(Hotkey::RamSearch)),
+    (Keycode::F11, Binding::Hotkey(Hotkey::Trace)),
+    (Keycode::F12, Binding::Hotkey(Hotkey::Debugger)),
+];
+
+const DEFAULT_BUTTONS: [(Button, Binding); 12] = [
+    (Button::DPadUp, Binding::Button(Key::Up)),
+    (Button::DPadDown, Binding::Button(Key::Down)),
+    (Button::DPadLeft, Binding::Button(Key::Left)),
+    (Button::DPadRight, Binding::Button(Key::Right)),
+    (Button::A, Binding::Button(Key::A)),
+    (Button::B, Binding::Button(Key::B)),
+    (Button::Start, Binding::Button(Key::Start)),
+    (Button::Back, Binding::Button(Key::Select)),
+    (Button::Y, Binding::Turbo(Key::A)),
+    (Button::X, Binding::Turbo(Key::B)),
+    (Button::Guide, Binding::Hotkey(Hotkey::Menu)),
+    (Button::RightShoulder, Binding::Hotkey(Hotkey::FastForward)),
+];
+
+// Half way, so a resting stick never presses anything
+const DEFAULT_STICK_THRESHOLD: i16 = 16384;
+const DEFAULT_TURBO_FRAMES: u32 = 2;
+
+pub struct Bindings {
+    keys: HashMap<Keycode, Binding>,
+    buttons: HashMap<Button, Binding>,
+    // How far the left stick has to be pushed to press a direction
+    pub stick_threshold: i16,
+    // Frames a turbo button stays pressed, then as many released
+    pub turbo_frames: u32,
+    pub macros: Vec<Macro>,
+}
+
+impl Bindings {
+    pub fn new() -> Bindings {
+        Bindings {
+            keys: HashMap::from(DEFAULT_KEYS),
+            buttons: HashMap::from(DEFAULT_BUTTONS),
+            stick_threshold: DEFAULT_STICK_THRESHOLD,
+            turbo_frames: DEFAULT_TURBO_FRAMES,
+            macros: vec![],
+        }
+    }
+
+    // A [keyboard] and a [controller] table of `action = ["name", ...]` using SDL key and button
+    // names. Listed actions replace their default bindings, the others keep them. [turbo] sets
+    // the turbo speed and [macros.name] tables define macros
+    pub fn load(path: &Path) -> Bindings {
+        let mut bindings = Bindings::new();
+
+        let Ok(contents) = fs::read_to_string(path) else {
+            return bindings;
+        };
+        let table = match contents.parse::<toml::Table>() {
+            Ok(table) => table,
+            Err(error) => {
+                println!("Bindings: failed to parse {}: {}", path.display(), error);
+                return bindings;
+            }
+        };
+
+        for (section, values) in table.iter() {
+            let Some(values) = values.as_table() else {
+                println!("Bindings: `{}` is not a table", section);
+                continue;
+            };
+            match section.as_str() {
+                "keyboard" | "controller" => bindings.load_inputs(section, values),
+                "turbo" => bindings.load_turbo(values),
+                "macros" => {
+                    for (name, value) in values.iter() {
+                        bindings.load_macro(name, value);
+                    }
+                }
+                _ => println!("Bindings: unknown section `{}`", section),
+            }
+        }
+
+        println!("Loaded bindings from {}", path.display());
+        bindings
+    }
+
+    fn load_inputs(&mut self, section: &str, values: &toml::Table) {
+        for (name, value) in values.iter() {
+            if section == "controller" && name == "stick_threshold" {
+                match value
+                    .as_integer()
+                    .and_then(|value| i16::try_from(value).ok())
+                    .filter(|&threshold| threshold > 0)
+                {
+                    Some(threshold) => self.stick_threshold = threshold,
+                    None => println!("Bindings: invalid stick_threshold {}", value),
+                }
+                continue;
+            }
+            let Some(action) = parse_action(name) else {
+                println!("Bindings: unknown action `{}`", name);
+                continue;
+            };
+            let Some(names) = names(value) else {
+                println!("Bindings: `{}` needs a list of names", name);
+                continue;
+            };
+            if section == "keyboard" {
+                rebind(&mut self.keys, action, &names, Keycode::from_name);
+            } else {
+                rebind(&mut self.buttons, action, &names, Button::from_string);
+            }
+        }
+    }
+
+    fn load_turbo(&mut self, values: &toml::Table) {
+        for (name, value) in values.iter() {
+            match name.as_str() {
+                "frames" => match value
+                    .as_integer()
+                    .and_then(|value| u32::try_from(value).ok())
+                    .filter(|&frames| frames > 0)
+                {
+                    Some(frames) => self.turbo_frames = frames,
+                    None => println!("Bindings: invalid turbo frames {}", value),
+                },
+                _ => println!("Bindings: unknown turbo setting `{}`", name),
+            }
+        }
+    }
+
+    // `[macros.name]` with the `input` steps and the `keys` and `buttons` that start it
+    fn load_macro(&mut self, name: &str, value: &toml::Value) {
+        let Some(values) = value.as_table() else {
+            println!("Bindings: macro `{}` is not a table", name);
+            return;
+        };
+        let steps = values.get("input").and_then(names).unwrap_or_default();
+        let Some(input_macro) = Macro::parse(name, &steps) else {
+            return;
+        };
+
+        let binding = Binding::Macro(self.macros.len());
+        self.macros.push(input_macro);
+        if let Some(keys) = values.get("keys").and_then(names) {
+            rebind(&mut self.keys, binding, &keys, Keycode::from_name);
+        }
+        if let Some(buttons) = values.get("buttons").and_then(names) {
+            rebind(&mut self.buttons, binding, &buttons, Button::from_string);
+        }
+    }
+
+    pub fn key(&self, keycode: Keycode) -> Option<Binding> {
+        self.keys.get(&keycode).copied()
+    }
+
+    pub fn button(&self, button: Button) -> Option<Binding> {
+        self.buttons.get(&button).copied()
+    }
+
+    // The keys, then the controller buttons bound to an action
+    pub fn describe(&self, action: Binding) -> String {
+        let keys = bound_names(&self.keys, action, Keycode::name);
+        let buttons = bound_names(&self.buttons, action, Button::string);
+        let list = |names: Vec<String>| match names.is_empty() {
+            true => "-".to_string(),
+            false => names.join(", "),
+        };
+        format!("{} / {}", list(keys), list(buttons))
+    }
+
+    // A single key or button in place of the ones the action had. One bound to another action is
+    // refused rather than taken from it, which also keeps the menu reachable
+    pub fn bind_key(&mut self, action: Binding, keycode: Keycode) -> Result<(), String> {
+        match bind(&mut self.keys, action, keycode) {
+            Some(other) => Err(format!(
+                "{} is already bound to {}",
+                keycode.name(),
+                self.action_name(other)
+            )),
+            None => Ok(()),
+        }
+    }
+
+    pub fn bind_button(&mut self, action: Binding, button: Button) -> Result<(), String> {
+        match bind(&mut self.buttons, action, button) {
+            Some(other) => Err(format!(
+                "{} is already bound to {}",
+                button.string(),
+                self.action_name(other)
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn action_name(&self, action: Binding) -> String {
+        match action {
+            Binding::Macro(index) => format!("macro {}", self.macros[index].name),
+            _ => ACTIONS
+                .iter()
+                .find(|(_, bound)| *bound == action)
+                .map_or("?", |(name, _)| name)
+                .to_string(),
+        }
+    }
+
+    // Writes an action's keys and buttons to bindings.toml, leaving the rest of the file as it was
+    pub fn save_action(&self, path: &Path, action: Binding) {
+        let Some((name, _)) = ACTIONS.iter().find(|(_, bound)| *bound == action) else {
+            return;
+        };
+        let mut table = match fs::read_to_string(path) {
+            Ok(contents) => match contents.parse::<toml::Table>() {
+                Ok(table) => table,
+                Err(error) => {
+                    println!(
+                        "Bindings: not saving, failed to parse {}: {}",
+                        path.display(),
+                        error
+                    );
+                    return;
+                }
+            },
+            Err(_) => toml::Table::new(),
+        };
+
+        let sections = [
+            ("keyboard", bound_names(&self.keys, action, Keycode::name)),
+            (
+                "controller",
+                bound_names(&self.buttons, action, Button::string),
+            ),
+        ];
+        for (section, names) in sections {
+            let section = table
+                .entry(section)
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+            if let Some(section) = section.as_table_mut() {
+                let names = names.into_iter().map(toml::Value::String).collect();
+                section.insert(name.to_string(), toml::Value::Array(names));
+            }
+        }
+
+        if let Err(error) = fs::write(path, table.to_string()) {
+            println!("Bindings: failed to write {}: {}", path.display(), error);
+        }
+    }
+}
+
+fn bound_names<T: Copy>(
+    bindings: &HashMap<T, Binding>,
+    action: Binding,
+    name: impl Fn(T) -> String,
+) -> Vec<String> {
+    let mut names: Vec<String> = bindings
+        .iter()
+        .filter(|(_, bound)| **bound == action)
+        .map(|(input, _)| name(*input))
+        .collect();
+    names.sort();
+    names
+}
+
+// A single name doesn't need a list
+fn names(value: &toml::Value) -> Option<Vec<&str>> {
+    match value {
+        toml::Value::String(name) => Some(vec![name.as_str()]),
+        toml::Value::Array(names) => Some(names.iter().filter_map(toml::Value::as_str).collect()),
+        _ => None,
+    }
+}
+
+fn parse_action(name: &str) -> Option<Binding> {
+    ACTIONS
+        .iter()
+        .find(|(action_name, _)| *action_name == name)
+        .map(|(_, action)| *action)
+}
+
+// Returns the action the input is bound to instead, leaving both alone
+fn bind<T: Eq + Hash>(
+    bindings: &mut HashMap<T, Binding>,
+    action: Binding,
+    input: T,
+) -> Option<Binding> {
+    match bindings.get(&input) {
+        Some(&other) if other != action => Some(other),
+        _ => {
+            bindings.retain(|_, bound| *bound != action);
+            bindings.insert(input, action);
+            None
+        }
+    }
+}
+
+// Replaces the keys or buttons bound to an action
+fn rebind<T: Eq + Hash>(
+    bindings: &mut HashMap<T, Binding>,
+    action: Binding,
+    names: &[&str],
+    parse: impl Fn(&str) -> Option<T>,
+) {
+    bindings.retain(|_, bound| *bound != action);
+    for name in names {
+        match parse(name) {
+            Some(input) => {
+                bindings.insert(input, action);
+            }
+            None => println!("Bindings: unknown key or button `{}`", name),
+        }
+    }
+}
+
+// The left stick acting as a D-pad, one direction per axis at a time
+pub struct Stick {
+    x: Option<Key>,
+    y: Option<Key>,
+}
+
+impl Stick {
+    pub fn new() -> Stick {
+        Stick { x: None, y: None }
+    }
+
+    // Returns the direction released and the one pressed when the axis crosses the threshold
+    pub fn update(&mut self, axis: Axis, value: i16, threshold: i16) -> (Option<Key>, Option<Key>) {
+        let (held, negative, positive) = match axis {
+            Axis::LeftX => (&mut self.x, Key::Left, Key::Right),
+            Axis::LeftY => (&mut self.y, Key::Up, Key::Down),
+            _ => return (None, None),
+        };
+        let direction = if value <= -threshold {
+            Some(negative)
+        } else if value >= threshold {
+            Some(positive)
+        } else {
+            None
+        };
+        if direction == *held {
+            return (None, None);
+        }
+        let released = std::mem::replace(held, direction);
+        (released, direction)
+    }
+}
@@ -0,0 +1,263 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// One frame of the LCD, 4194304 / 70224 = 59.73 frames a second
+const CYCLES_PER_FRAME: usize = 70224;
+const CYCLES_PER_SECOND: u64 = 4194304;
+
+// rom-file-name-YYYYMMDD-HHMMSS.extension next to the ROM, in UTC
+pub fn capture_path(rom_path: &Path, extension: &str) -> PathBuf {
+    let stem = rom_path.file_stem().unwrap().to_string_lossy();
+    let name = format!("{}-{}", stem, timestamp());
+    (1..)
+        .map(|number| match number {
+            1 => rom_path.with_file_name(format!("{}.{}", name, extension)),
+            _ => rom_path.with_file_name(format!("{}-{}.{}", name, number, extension)),
+        })
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+fn timestamp() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let (days, time) = (seconds / 86400, seconds % 86400);
+
+    // Days since 1970 to a civil date, from Howard Hinnant's date algorithms
+    let days = days as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+// Saves an RGBA frame as a PNG, each pixel blown up to scale x scale. Returns whether it worked
+pub fn save_png(path: &Path, pixels: &[u8], width: u32, height: u32, scale: u32) -> bool {
+    let scaled = scale_pixels(pixels, width, scale);
+    let result = File::create(path)
+        .map_err(png::EncodingError::from)
+        .and_then(|file| {
+            let mut encoder =
+                png::Encoder::new(BufWriter::new(file), width * scale, height * scale);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.write_header()?.write_image_data(&scaled)
+        });
+    match result {
+        Ok(()) => {
+            println!("Screenshot saved to {}", path.display());
+            true
+        }
+        Err(error) => {
+            println!("Failed to save {}: {}", path.display(), error);
+            false
+        }
+    }
+}
+
+fn scale_pixels(pixels: &[u8], width: u32, scale: u32) -> Vec<u8> {
+    if scale == 1 {
+        return pixels.to_vec();
+    }
+    let mut scaled = Vec::with_capacity(pixels.len() * (scale * scale) as usize);
+    for row in pixels.chunks(width as usize * 4) {
+        let mut scaled_row = Vec::with_capacity(row.len() * scale as usize);
+        for pixel in row.chunks(4) {
+            for _ in 0..scale {
+                scaled_row.extend_from_slice(pixel);
+            }
+        }
+        for _ in 0..scale {
+            scaled.extend_from_slice(&scaled_row);
+        }
+    }
+    scaled
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Format {
+    // Every other frame, GIF players can't keep up with 60 frames a second
+    Gif,
+    // Every frame, uncompressed
+    Y4m,
+}
+
+enum Output {
+    Gif(gif::Encoder<BufWriter<File>>),
+    Y4m(BufWriter<File>),
+}
+
+// Records frames as the emulator produces them, timed by emulated cycles so the video plays at
+// the console's speed whatever the host did
+pub struct Recorder {
+    path: PathBuf,
+    output: Output,
+    width: u32,
+    height: u32,
+    last_frame: u64,
+    cycles: usize,
+    frames: u64,
+}
+
+impl Recorder {
+    pub fn start(path: &Path, format: Format, width: u32, height: u32) -> Option<Recorder> {
+        let output = File::create(path)
+            .map_err(|error| error.to_string())
+            .and_then(|file| {
+                let mut writer = BufWriter::new(file);
+                match format {
+                    Format::Gif => {
+                        let mut encoder =
+                            gif::Encoder::new(writer, width as u16, height as u16, &[])
+                                .map_err(|error| error.to_string())?;
+                        encoder
+                            .set_repeat(gif::Repeat::Infinite)
+                            .map_err(|error| error.to_string())?;
+                        Ok(Output::Gif(encoder))
+                    }
+                    Format::Y4m => {
+                        // Full resolution chroma so nothing is lost to subsampling
+                        writeln!(
+                            writer,
+                            "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444",
+                            width, height, CYCLES_PER_SECOND, CYCLES_PER_FRAME
+                        )
+                        .map_err(|error| error.to_string())?;
+                        Ok(Output::Y4m(writer))
+                    }
+                }
+            });
+        match output {
+            Ok(output) => {
+                println!("Recording video to {}", path.display());
+                Some(Recorder {
+                    path: path.to_path_buf(),
+                    output,
+                    width,
+                    height,
+                    last_frame: 0,
+                    cycles: 0,
+                    frames: 0,
+                })
+            }
+            Err(error) => {
+                println!("Failed to create {}: {}", path.display(), error);
+                None
+            }
+        }
+    }
+
+    // Takes a frame whenever the GPU finishes one, or once a frame's worth of cycles has passed
+    // with the LCD off
+    pub fn update(&mut self, frame: u64, cycles: usize, pixels: impl FnOnce() -> Vec<u8>) {
+        self.cycles += cycles;
+        if frame == self.last_frame && self.cycles < CYCLES_PER_FRAME {
+            return;
+        }
+        self.last_frame = frame;
+        self.cycles = 0;
+
+        let result = match &mut self.output {
+            Output::Gif(encoder) => {
+                self.frames += 1;
+                if self.frames.is_multiple_of(2) {
+                    return;
+                }
+                // Hundredths of a second since the start, so the delays add up without drifting
+                let centiseconds =
+                    |frames: u64| frames * CYCLES_PER_FRAME as u64 * 100 / CYCLES_PER_SECOND;
+                let mut frame = gif_frame(&pixels(), self.width as u16, self.height as u16);
+                frame.delay =
+                    (centiseconds(self.frames + 1) - centiseconds(self.frames - 1)) as u16;
+                encoder
+                    .write_frame(&frame)
+                    .map_err(|error| error.to_string())
+            }
+            Output::Y4m(writer) => {
+                self.frames += 1;
+                writer
+                    .write_all(b"FRAME\n")
+                    .and_then(|()| writer.write_all(&y4m_planes(&pixels())))
+                    .map_err(|error| error.to_string())
+            }
+        };
+        if let Err(error) = result {
+            println!("Failed to write {}: {}", self.path.display(), error);
+        }
+    }
+
+    pub fn stop(self) {
+        let result = match self.output {
+            Output::Gif(encoder) => encoder
+                .into_inner()
+                .map_err(|error| error.to_string())
+                .and_then(|mut writer| writer.flush().map_err(|error| error.to_string())),
+            Output::Y4m(mut writer) => writer.flush().map_err(|error| error.to_string()),
+        };
+        match result {
+            Ok(()) => println!(
+                "Saved {:.1} seconds of video to {}",
+                (self.frames * CYCLES_PER_FRAME as u64) as f64 / CYCLES_PER_SECOND as f64,
+                self.path.display()
+            ),
+            Err(error) => println!("Failed to write {}: {}", self.path.display(), error),
+        }
+    }
+}
+
+// Exact colours while a frame has at most 256 of them, which Game Boy frames nearly always do
+fn gif_frame(pixels: &[u8], width: u16, height: u16) -> gif::Frame<'static> {
+    let mut palette: Vec<[u8; 3]> = vec![];
+    let mut indices = Vec::with_capacity(pixels.len() / 4);
+    for pixel in pixels.chunks(4) {
+        let color = [pixel[0], pixel[1], pixel[2]];
+        let index = match palette.iter().position(|&entry| entry == color) {
+            Some(index) => index,
+            None if palette.len() < 256 => {
+                palette.push(color);
+                palette.len() - 1
+            }
+            None => {
+                let mut pixels = pixels.to_vec();
+                return gif::Frame::from_rgba_speed(width, height, &mut pixels, 10);
+            }
+        };
+        indices.push(index as u8);
+    }
+    gif::Frame::from_palette_pixels(width, height, indices, palette.concat(), None)
+}
+
+// RGB to BT.601 studio swing YCbCr, one full plane each
+fn y4m_planes(pixels: &[u8]) -> Vec<u8> {
+    let count = pixels.len() / 4;
+    let mut planes = vec![0; count * 3];
+    for (index, pixel) in pixels.chunks(4).enumerate() {
+        let (r, g, b) = (pixel[0] as i32, pixel[1] as i32, pixel[2] as i32);
+        planes[index] = (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8;
+        planes[count + index] = (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8;
+        planes[count * 2 + index] = (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8;
+    }
+    planes
+}
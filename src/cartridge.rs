@@ -13,24 +13,44 @@ use rom_only::RomOnlyCartridge;
 pub trait Cartridge {
     fn read(&self, address: u16) -> u8;
     fn write(&mut self, address: u16, value: u8);
+    // Puts the mapper's registers back to how they power on, keeping the RAM
+    fn reset(&mut self);
     fn read_ram(&self, address: u16) -> u8;
     fn write_ram(&mut self, address: u16, value: u8);
     fn get_cgb_flag(&self) -> u8;
+    fn rom_bank(&self) -> u16;
+    fn rom(&self) -> &[u8];
+    // External RAM, empty when the cartridge has none
+    fn ram(&self) -> &[u8];
+    fn ram_mut(&mut self) -> &mut [u8];
 }
 
-pub fn new_cartridge(path: &Path) -> Box<dyn Cartridge> {
-    let rom = fs::read(path).expect("failed to open rom file");
+// Battery-backed RAM is kept in the file at save_path
+pub fn new_cartridge(path: &Path, save_path: &Path) -> Result<Box<dyn Cartridge>, String> {
+    let rom =
+        fs::read(path).map_err(|error| format!("Failed to open {}: {}", path.display(), error))?;
+    if rom.len() < 0x150 {
+        return Err(format!("{} is too small to be a ROM", path.display()));
+    }
     let cartridge_type = rom[0x147];
     println!("Cartridge type: {:#04x}", cartridge_type);
     println!("CGB: {:#04x}", rom[0x143]);
     match cartridge_type {
-        0x00 => Box::new(RomOnlyCartridge::new(rom)),
-        0x01..=0x03 => Box::new(MBC1::new(rom, path)),
-        0x0F..=0x13 => Box::new(MBC3::new(rom, path)),
-        _ => panic!("Cartridge type not implemented: {:#04x}", cartridge_type),
+        0x00 => Ok(Box::new(RomOnlyCartridge::new(rom))),
+        0x01..=0x03 => Ok(Box::new(MBC1::new(rom, save_path))),
+        0x0F..=0x13 => Ok(Box::new(MBC3::new(rom, save_path))),
+        _ => Err(format!(
+            "Cartridge type not implemented: {:#04x}",
+            cartridge_type
+        )),
     }
 }
 
+// An empty slot reading as zeros, stands in while a cartridge is moved to a new console
+pub fn empty() -> Box<dyn Cartridge> {
+    Box::new(RomOnlyCartridge::new(vec![0; 0x8000]))
+}
+
 pub fn get_ram_size(rom: &[u8]) -> Option<usize> {
     println!("RAM size: {:#04x}", rom[0x149]);
     match rom[0x149] {
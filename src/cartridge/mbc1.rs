@@ -15,14 +15,14 @@ pub struct MBC1 {
 }
 
 impl MBC1 {
-    pub fn new(rom: Vec<u8>, path: &Path) -> MBC1 {
+    pub fn new(rom: Vec<u8>, save_path: &Path) -> MBC1 {
         let cartridge_type = rom[0x147];
         let rom_size = rom[0x148];
         let cgb_flag = rom[0x143];
         let has_ram = matches!(cartridge_type, 0x02 | 0x03);
         let ram_size = get_ram_size(&rom);
         let ram = if has_ram {
-            ram_size.map(|size| Save::new(path, size))
+            ram_size.map(|size| Save::new(save_path, size))
         } else {
             None
         };
@@ -84,6 +84,13 @@ impl Cartridge for MBC1 {
         }
     }
 
+    fn reset(&mut self) {
+        self.ram_bank = 0;
+        self.rom_bank = 1;
+        self.ram_enabled = false;
+        self.mode = 0;
+    }
+
     fn read_ram(&self, address: u16) -> u8 {
         if !self.ram_enabled {
             return 0xFF;
@@ -123,4 +130,23 @@ impl Cartridge for MBC1 {
     fn get_cgb_flag(&self) -> u8 {
         self.cgb_flag
     }
+
+    fn rom_bank(&self) -> u16 {
+        self.rom_bank as u16
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn ram(&self) -> &[u8] {
+        self.ram.as_ref().map_or(&[], |save| &save.ram[..])
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        match self.ram.as_mut() {
+            Some(save) => &mut save.ram[..],
+            None => &mut [],
+        }
+    }
 }
@@ -22,6 +22,8 @@ impl Cartridge for RomOnlyCartridge {
 
     fn write(&mut self, _address: u16, _value: u8) {}
 
+    fn reset(&mut self) {}
+
     fn read_ram(&self, _address: u16) -> u8 {
         0
     }
@@ -31,4 +33,20 @@ impl Cartridge for RomOnlyCartridge {
     fn get_cgb_flag(&self) -> u8 {
         self.cgb_flag
     }
+
+    fn rom_bank(&self) -> u16 {
+        1
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn ram(&self) -> &[u8] {
+        &[]
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut []
+    }
 }
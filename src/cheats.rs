@@ -0,0 +1,317 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Code {
+    // `ttVVAAAA`, written every VBlank. A bank targets SRAM or WRAMX regardless of what is mapped
+    GameShark {
+        bank: Option<u8>,
+        value: u8,
+        address: u16,
+    },
+    // `VVA-AAA-CCC`, replaces ROM reads, only while the original byte matches `compare`
+    GameGenie {
+        address: u16,
+        value: u8,
+        compare: Option<u8>,
+    },
+}
+
+impl Code {
+    pub fn parse(text: &str) -> Option<Code> {
+        let digits: Vec<u8> = text
+            .chars()
+            .filter(|&c| c != '-')
+            .map(|c| c.to_digit(16).map(|digit| digit as u8))
+            .collect::<Option<_>>()?;
+        let byte = |index: usize| digits[index] << 4 | digits[index + 1];
+
+        match digits.len() {
+            // Type, value, then the address low byte first
+            8 => {
+                let bank = match byte(0) {
+                    0x00 | 0x01 => None,
+                    kind @ 0x80..=0x8F => Some(kind & 0x0F),
+                    kind @ 0x90..=0x97 => Some(kind & 0x07),
+                    _ => return None,
+                };
+                Some(Code::GameShark {
+                    bank,
+                    value: byte(2),
+                    address: (byte(6) as u16) << 8 | byte(4) as u16,
+                })
+            }
+            // The address's top nibble is stored inverted and last
+            6 | 9 => {
+                let address = ((digits[5] ^ 0x0F) as u16) << 12
+                    | (digits[2] as u16) << 8
+                    | (digits[3] as u16) << 4
+                    | digits[4] as u16;
+                if address >= 0x8000 {
+                    return None;
+                }
+                let compare = (digits.len() == 9)
+                    .then(|| (digits[6] << 4 | digits[8]).rotate_right(2) ^ 0xBA);
+                Some(Code::GameGenie {
+                    address,
+                    value: byte(0),
+                    compare,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+pub struct Cheat {
+    pub code: Code,
+    // As typed, so the file keeps the original spelling
+    pub text: String,
+    pub name: String,
+    pub enabled: bool,
+}
+
+impl fmt::Display for Cheat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = if self.enabled { "on" } else { "off" };
+        write!(f, "{:<12} [{}] {}", self.text, state, self.name)
+    }
+}
+
+// The cheats for the loaded ROM, kept in rom-file-name.cht
+pub struct Cheats {
+    list: Vec<Cheat>,
+    // Switches every cheat off without forgetting which ones are enabled
+    pub enabled: bool,
+    // Enabled Game Genie codes as (address, value, compare), checked on every ROM read
+    patches: Vec<(u16, u8, Option<u8>)>,
+    path: Option<PathBuf>,
+}
+
+impl Cheats {
+    pub fn new() -> Cheats {
+        Cheats {
+            list: vec![],
+            enabled: true,
+            patches: vec![],
+            path: None,
+        }
+    }
+
+    // One cheat per line as `code name`, a leading '!' disables it and '#' starts a comment
+    pub fn load(path: &Path) -> Cheats {
+        let mut cheats = Cheats::new();
+        cheats.path = Some(path.to_path_buf());
+
+        let Ok(contents) = fs::read_to_string(path) else {
+            return cheats;
+        };
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (enabled, line) = match line.strip_prefix('!') {
+                Some(line) => (false, line.trim_start()),
+                None => (true, line),
+            };
+            let (text, name) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            match Code::parse(text) {
+                Some(code) => cheats.list.push(Cheat {
+                    code,
+                    text: text.to_uppercase(),
+                    name: name.trim().to_string(),
+                    enabled,
+                }),
+                None => println!("Cheats: ignoring invalid code `{}`", text),
+            }
+        }
+
+        println!(
+            "Loaded {} cheats from {}",
+            cheats.list.len(),
+            path.display()
+        );
+        cheats.update_patches();
+        cheats
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let mut contents = String::from("# code name, a leading ! disables the cheat\n");
+        for cheat in self.list.iter() {
+            let prefix = if cheat.enabled { "" } else { "!" };
+            contents.push_str(&format!("{}{} {}\n", prefix, cheat.text, cheat.name));
+        }
+        if let Err(error) = fs::write(path, contents) {
+            println!("Cheats: failed to write {}: {}", path.display(), error);
+        }
+    }
+
+    pub fn list(&self) -> &[Cheat] {
+        &self.list
+    }
+
+    pub fn add(&mut self, text: &str, name: &str) -> Option<&Cheat> {
+        let code = Code::parse(text)?;
+        self.list.push(Cheat {
+            code,
+            text: text.to_uppercase(),
+            name: name.to_string(),
+            enabled: true,
+        });
+        self.changed();
+        self.list.last()
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Cheat> {
+        if index >= self.list.len() {
+            return None;
+        }
+        let cheat = self.list.remove(index);
+        self.changed();
+        Some(cheat)
+    }
+
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) -> Option<&Cheat> {
+        self.list.get_mut(index)?.enabled = enabled;
+        self.changed();
+        self.list.get(index)
+    }
+
+    pub fn toggle_all(&mut self) {
+        self.enabled = !self.enabled;
+        self.update_patches();
+    }
+
+    fn changed(&mut self) {
+        self.update_patches();
+        self.save();
+    }
+
+    fn update_patches(&mut self) {
+        self.patches = self
+            .active()
+            .filter_map(|cheat| match cheat.code {
+                Code::GameGenie {
+                    address,
+                    value,
+                    compare,
+                } => Some((address, value, compare)),
+                Code::GameShark { .. } => None,
+            })
+            .collect();
+    }
+
+    fn active(&self) -> impl Iterator<Item = &Cheat> {
+        self.list
+            .iter()
+            .filter(move |cheat| self.enabled && cheat.enabled)
+    }
+
+    // Enabled GameShark codes as (bank, address, value)
+    pub fn writes(&self) -> impl Iterator<Item = (Option<u8>, u16, u8)> + '_ {
+        self.active().filter_map(|cheat| match cheat.code {
+            Code::GameShark {
+                bank,
+                value,
+                address,
+            } => Some((bank, address, value)),
+            Code::GameGenie { .. } => None,
+        })
+    }
+
+    // The value a ROM read returns with Game Genie codes applied
+    #[inline]
+    pub fn patch(&self, address: u16, value: u8) -> u8 {
+        if self.patches.is_empty() {
+            return value;
+        }
+        self.patches
+            .iter()
+            .find(|(patch_address, _, compare)| {
+                *patch_address == address && compare.is_none_or(|compare| compare == value)
+            })
+            .map_or(value, |(_, patched, _)| *patched)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gameshark() {
+        assert_eq!(
+            Code::parse("010F34C1"),
+            Some(Code::GameShark {
+                bank: None,
+                value: 0x0F,
+                address: 0xC134,
+            })
+        );
+        assert_eq!(
+            Code::parse("880100D0"),
+            Some(Code::GameShark {
+                bank: Some(8),
+                value: 0x01,
+                address: 0xD000,
+            })
+        );
+        assert_eq!(
+            Code::parse("9302FFA0"),
+            Some(Code::GameShark {
+                bank: Some(3),
+                value: 0x02,
+                address: 0xA0FF,
+            })
+        );
+        assert_eq!(Code::parse("420F34C1"), None);
+    }
+
+    #[test]
+    fn game_genie() {
+        assert_eq!(
+            Code::parse("00A-17B"),
+            Some(Code::GameGenie {
+                address: 0x4A17,
+                value: 0x00,
+                compare: None,
+            })
+        );
+        // C and 9 make 0xC9, rotated right twice and XORed with 0xBA
+        assert_eq!(
+            Code::parse("00A-17B-C49"),
+            Some(Code::GameGenie {
+                address: 0x4A17,
+                value: 0x00,
+                compare: Some(0xC8),
+            })
+        );
+        assert_eq!(
+            Code::parse("3EF-23F"),
+            Some(Code::GameGenie {
+                address: 0x0F23,
+                value: 0x3E,
+                compare: None,
+            })
+        );
+        // Outside of ROM
+        assert_eq!(Code::parse("00A-177"), None);
+        assert_eq!(Code::parse("00A-17B-C"), None);
+        assert_eq!(Code::parse("00A-17G"), None);
+    }
+
+    #[test]
+    fn patch_checks_compare() {
+        let mut cheats = Cheats::new();
+        cheats.add("00A-17B-C49", "");
+        assert_eq!(cheats.patch(0x4A17, 0xC8), 0x00);
+        assert_eq!(cheats.patch(0x4A17, 0x12), 0x12);
+        assert_eq!(cheats.patch(0x4A18, 0xC8), 0xC8);
+        cheats.toggle_all();
+        assert_eq!(cheats.patch(0x4A17, 0xC8), 0xC8);
+    }
+}
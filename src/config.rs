@@ -0,0 +1,245 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::filter::Scaler;
+use crate::model::Model;
+
+pub const USAGE: &str = "Usage: gameboy-emulator [rom] [options]
+       gameboy-emulator disasm <rom> [bank]
+       gameboy-emulator bk2 <movie> [bk2]
+
+Without a ROM a file dialog asks for one. Defaults for the options can be set in config.toml
+next to the executable. There is no audio or save states yet, so no options for them.
+
+  --boot <file>        Boot ROM to run first (default: boot.bin next to the executable)
+  --model <model>      Console to emulate: dmg0, dmg, mgb, sgb, sgb2, cgb or agb (default: cgb)
+  --scale <n>          Window size as a multiple of 160x144 (default: 5)
+  --fullscreen         Start in fullscreen
+  --stretch            Fill the window instead of scaling by whole numbers
+  --filter <name>      Scaler: nearest, scale2x, scale3x or hq2x (default: nearest)
+  --lcd-grid           Darken the gaps between the LCD's dots
+  --ghosting           Blend each frame with the last, like the slow LCD
+  --save-dir <dir>     Keep .sav files in this directory instead of next to the ROM
+  --palette <name>     DMG palette: hardware, grey, green, pocket, light or custom
+  --paused             Start paused
+  --show-fps           Show the frame rate and emulation speed
+  --screenshot-scale <n>
+                       Save screenshots this many times larger than the screen (default: 1)
+  --gdb [port]         Start the GDB remote debugging server
+  --trace [file]       Log every instruction in the Gameboy Doctor format
+  --trace-ring <n>     Keep the last n instructions and write them out on a crash
+  --record [file]      Record a movie from power on
+  --play [file]        Play a movie back";
+
+const DEFAULT_SCALE: u32 = 5;
+
+// Defaults from config.toml, overridden by the command line
+pub struct Config {
+    pub rom: Option<PathBuf>,
+    pub boot_rom: Option<PathBuf>,
+    pub model: Option<Model>,
+    pub scale: u32,
+    pub fullscreen: bool,
+    pub stretch: bool,
+    pub filter: Scaler,
+    pub lcd_grid: bool,
+    pub ghosting: bool,
+    pub save_dir: Option<PathBuf>,
+    pub palette: Option<String>,
+    pub paused: bool,
+    pub show_fps: bool,
+    pub screenshot_scale: u32,
+}
+
+impl Config {
+    pub fn new() -> Config {
+        Config {
+            rom: None,
+            boot_rom: None,
+            model: None,
+            scale: DEFAULT_SCALE,
+            fullscreen: false,
+            stretch: false,
+            filter: Scaler::Nearest,
+            lcd_grid: false,
+            ghosting: false,
+            save_dir: None,
+            palette: None,
+            paused: false,
+            show_fps: false,
+            screenshot_scale: 1,
+        }
+    }
+
+    // The same settings as the options, e.g. `scale = 3` or `save_dir = "saves"`. Relative paths
+    // are relative to the config file
+    pub fn load(path: &Path) -> Config {
+        let mut config = Config::new();
+
+        let Ok(contents) = fs::read_to_string(path) else {
+            return config;
+        };
+        let table = match contents.parse::<toml::Table>() {
+            Ok(table) => table,
+            Err(error) => {
+                println!("Config: failed to parse {}: {}", path.display(), error);
+                return config;
+            }
+        };
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        for (key, value) in table.iter() {
+            let valid = match (key.as_str(), value) {
+                ("boot_rom", toml::Value::String(boot_rom)) => {
+                    config.boot_rom = Some(dir.join(boot_rom));
+                    true
+                }
+                ("model", toml::Value::String(model)) => config.set_model(model),
+                ("scale", toml::Value::Integer(scale)) => match u32::try_from(*scale) {
+                    Ok(scale) if scale > 0 => {
+                        config.scale = scale;
+                        true
+                    }
+                    _ => false,
+                },
+                ("fullscreen", toml::Value::Boolean(fullscreen)) => {
+                    config.fullscreen = *fullscreen;
+                    true
+                }
+                ("stretch", toml::Value::Boolean(stretch)) => {
+                    config.stretch = *stretch;
+                    true
+                }
+                ("filter", toml::Value::String(filter)) => config.set_filter(filter),
+                ("lcd_grid", toml::Value::Boolean(lcd_grid)) => {
+                    config.lcd_grid = *lcd_grid;
+                    true
+                }
+                ("ghosting", toml::Value::Boolean(ghosting)) => {
+                    config.ghosting = *ghosting;
+                    true
+                }
+                ("save_dir", toml::Value::String(save_dir)) => {
+                    config.save_dir = Some(dir.join(save_dir));
+                    true
+                }
+                ("palette", toml::Value::String(palette)) => {
+                    config.palette = Some(palette.clone());
+                    true
+                }
+                ("paused", toml::Value::Boolean(paused)) => {
+                    config.paused = *paused;
+                    true
+                }
+                ("show_fps", toml::Value::Boolean(show_fps)) => {
+                    config.show_fps = *show_fps;
+                    true
+                }
+                ("screenshot_scale", toml::Value::Integer(scale)) => match u32::try_from(*scale) {
+                    Ok(scale) if scale > 0 => {
+                        config.screenshot_scale = scale;
+                        true
+                    }
+                    _ => false,
+                },
+                _ => false,
+            };
+            if !valid {
+                println!("Config: ignoring `{} = {}`", key, value);
+            }
+        }
+
+        println!("Loaded config from {}", path.display());
+        config
+    }
+
+    // The ROM is the first argument, other options the debugger and movies read are skipped
+    pub fn apply_args(&mut self, args: &[String]) {
+        if let Some(rom) = args.get(1).filter(|arg| !arg.starts_with("--")) {
+            self.rom = Some(PathBuf::from(rom));
+        }
+        if let Some(boot_rom) = value(args, "--boot") {
+            self.boot_rom = Some(PathBuf::from(boot_rom));
+        }
+        if let Some(model) = value(args, "--model") {
+            if !self.set_model(model) {
+                println!(
+                    "Unknown model `{}`, expected dmg0, dmg, mgb, sgb, sgb2, cgb or agb",
+                    model
+                );
+            }
+        }
+        if let Some(scale) = value(args, "--scale") {
+            match scale.parse() {
+                Ok(scale) if scale > 0 => self.scale = scale,
+                _ => println!("Invalid scale `{}`", scale),
+            }
+        }
+        if let Some(scale) = value(args, "--screenshot-scale") {
+            match scale.parse() {
+                Ok(scale) if scale > 0 => self.screenshot_scale = scale,
+                _ => println!("Invalid screenshot scale `{}`", scale),
+            }
+        }
+        if let Some(filter) = value(args, "--filter") {
+            if !self.set_filter(filter) {
+                println!(
+                    "Unknown filter `{}`, expected nearest, scale2x, scale3x or hq2x",
+                    filter
+                );
+            }
+        }
+        if let Some(save_dir) = value(args, "--save-dir") {
+            self.save_dir = Some(PathBuf::from(save_dir));
+        }
+        if let Some(palette) = value(args, "--palette") {
+            self.palette = Some(palette.to_string());
+        }
+        self.fullscreen |= args.iter().any(|arg| arg == "--fullscreen");
+        self.paused |= args.iter().any(|arg| arg == "--paused");
+        self.show_fps |= args.iter().any(|arg| arg == "--show-fps");
+        self.stretch |= args.iter().any(|arg| arg == "--stretch");
+        self.lcd_grid |= args.iter().any(|arg| arg == "--lcd-grid");
+        self.ghosting |= args.iter().any(|arg| arg == "--ghosting");
+    }
+
+    fn set_model(&mut self, model: &str) -> bool {
+        match Model::from_name(model) {
+            Some(model) => {
+                self.model = Some(model);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn set_filter(&mut self, filter: &str) -> bool {
+        match Scaler::from_name(filter) {
+            Some(scaler) => {
+                self.filter = scaler;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // rom-file-name.sav, in the save directory if there is one
+    pub fn save_path(&self, rom: &Path) -> PathBuf {
+        let path = rom.with_extension("sav");
+        match &self.save_dir {
+            Some(dir) => dir.join(path.file_name().unwrap()),
+            None => path,
+        }
+    }
+}
+
+fn value<'a>(args: &'a [String], option: &str) -> Option<&'a str> {
+    let index = args.iter().position(|arg| arg == option)?;
+    match args.get(index + 1) {
+        Some(value) => Some(value),
+        None => {
+            println!("{} needs a value", option);
+            None
+        }
+    }
+}
@@ -1,12 +1,9 @@
-mod flags_register;
-mod instructions;
-mod registers;
+pub mod flags_register;
+pub mod instructions;
+pub mod registers;
 
 use crate::mmu::Memory;
 
-use std::io::BufWriter;
-use std::io::Write;
-
 use instructions::{
     ADDHLTarget, ArithmeticTarget, BitPosition, IncDecTarget, IndirectTarget, Instruction,
     JumpCondition, LoadByteSource, LoadByteTarget, LoadType, LoadWordTarget, PrefixTarget,
@@ -19,6 +16,7 @@ pub struct Cpu {
     pub mem: Memory,
     ime: bool,
     is_halted: bool,
+    is_stopped: bool,
     ime_next: bool,
 }
 
@@ -31,71 +29,102 @@ impl Cpu {
             mem,
             ime: false,
             is_halted: false,
+            is_stopped: false,
             ime_next: false,
         }
     }
 
-    pub fn boot(&mut self) {
-        self.registers.a = 0x01;
-        self.registers.f.z = true;
-        self.registers.f.n = true;
-        self.registers.f.h = true;
-        self.registers.f.c = true;
-        self.registers.set_bc(0x0013);
-        self.registers.set_de(0x00D8);
-        self.registers.set_hl(0x014D);
-        self.sp = 0xFFFE;
-        self.pc = 0x0100;
-    }
-
-    pub fn boot_cgb(&mut self) {
-        self.registers.a = 0x11;
-        self.registers.f.z = true;
-        self.registers.f.n = false;
-        self.registers.f.h = false;
-        self.registers.f.c = false;
-        self.registers.set_bc(0x0100);
-        self.registers.set_de(0xFF56);
-        self.registers.set_hl(0x000D);
+    fn boot(&mut self) {
+        let registers = self.mem.model.boot_registers(&self.mem.header());
+        self.registers.set_af(registers.af);
+        self.registers.set_bc(registers.bc);
+        self.registers.set_de(registers.de);
+        self.registers.set_hl(registers.hl);
         self.sp = 0xFFFE;
         self.pc = 0x0100;
     }
 
     fn get_instruction(&self) -> Instruction {
-        let instruction_byte = self.mem.read_byte(self.pc);
+        let instruction_byte = self.mem.peek_byte(self.pc);
         let prefixed = instruction_byte == 0xCB;
         if prefixed {
-            let instruction_byte = self.mem.read_byte(self.pc.wrapping_add(1));
+            let instruction_byte = self.mem.peek_byte(self.pc.wrapping_add(1));
             Instruction::get_instruction(instruction_byte, prefixed)
         } else {
             Instruction::get_instruction(instruction_byte, prefixed)
         }
     }
 
-    #[inline(always)]
-    pub fn log(&self, file: &mut BufWriter<&std::fs::File>) {
-        let string = format!(
-            "AF: {:04X?} BC: {:04X?} DE: {:04X?} HL: {:04X?} SP: {:04X?} PC: {:04X?} INST: {:04X?} ({:02X?} {:02X?}) JOY: {:04X?}\n",
-            self.registers.get_af(), self.registers.get_bc(), self.registers.get_de(), self.registers.get_hl(), self.sp, self.pc, self.get_instruction(), self.mem.read_byte(self.pc.wrapping_add(1)), self.mem.read_byte(self.pc.wrapping_add(2)), self.mem.joypad.read_input());
-
-        // let string = format!(
-        //     "PC: {:04X?} INST: {:04X?} ({:02X?} {:02X?})\n",
-        //     self.pc,
-        //     self.get_instruction(),
-        //     self.mem.read_byte(self.pc.wrapping_add(1)),
-        //     self.mem.read_byte(self.pc.wrapping_add(2))
-        // );
-
-        file.write_all(string.as_bytes())
-            .expect("failed to write to log");
+    // Like turning the console off and on again, the cartridge stays in
+    pub fn reset(&mut self) {
+        self.mem.reset();
+        self.registers = registers::Registers::new();
+        self.sp = 0x0000;
+        self.pc = 0x0000;
+        self.ime = false;
+        self.is_halted = false;
+        self.is_stopped = false;
+        self.ime_next = false;
+        self.skip_boot_rom();
+    }
+
+    // Without a boot ROM to run, starts with the registers the model's boot ROM would have left
+    pub fn skip_boot_rom(&mut self) {
+        if !self.mem.boot_active {
+            self.boot();
+        }
+    }
+
+    pub fn registers(&self) -> &registers::Registers {
+        &self.registers
+    }
+
+    pub fn registers_mut(&mut self) -> &mut registers::Registers {
+        &mut self.registers
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    pub fn sp(&self) -> u16 {
+        self.sp
+    }
+
+    pub fn set_sp(&mut self, sp: u16) {
+        self.sp = sp;
+    }
+
+    pub fn ime(&self) -> bool {
+        self.ime
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.is_halted
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.is_stopped
     }
 
     pub fn step(&mut self) -> u8 {
-        if self.mem.read_byte(0xFF02) == 0x81 {
-            print!("{}", self.mem.read_byte(0xFF01) as char);
+        if self.mem.peek_byte(0xFF02) == 0x81 {
+            print!("{}", self.mem.peek_byte(0xFF01) as char);
             self.mem.write_byte(0xFF02, 0);
         }
 
+        // STOP halts the clock, the timers and the LCD until a selected button is pressed
+        if self.is_stopped {
+            if self.mem.joypad.lines() == 0x0F {
+                return 4;
+            }
+            self.is_stopped = false;
+        }
+
         if self.ime_next {
             self.ime = true;
             self.ime_next = false;
@@ -559,6 +588,17 @@ impl Cpu {
                 }
             },
             Instruction::Nop => (self.pc.wrapping_add(1), 4),
+            Instruction::Stop => {
+                // An armed CGB speed switch finishes straight away, games write P1=0x30 first so
+                // no button could ever end it. Without a button group selected nothing could either
+                if self.mem.gpu.speed & 0x01 != 0 {
+                    self.mem.gpu.speed = (self.mem.gpu.speed ^ 0x80) & 0x80;
+                } else if self.mem.joypad.selected() {
+                    self.is_stopped = true;
+                }
+                self.mem.poke_byte(0xFF04, 0);
+                (self.pc.wrapping_add(2), 4)
+            }
             Instruction::Or(target) => {
                 match target {
                     ArithmeticTarget::A => self.or(self.registers.a),
@@ -1032,11 +1072,11 @@ impl Cpu {
     }
 
     fn read_next_byte(&self) -> u8 {
-        self.mem.read_byte(self.pc + 1)
+        self.mem.peek_byte(self.pc + 1)
     }
 
     fn read_next_word(&self) -> u16 {
-        (self.mem.read_byte(self.pc + 2) as u16) << 8 | self.mem.read_byte(self.pc + 1) as u16
+        (self.mem.peek_byte(self.pc + 2) as u16) << 8 | self.mem.peek_byte(self.pc + 1) as u16
     }
 
     fn adc(&mut self, value: u8) {
@@ -223,7 +223,7 @@ pub enum Instruction {
     Halt,
     Nop,
     Scf,
-    // STOP,
+    Stop,
 }
 
 impl Instruction {
@@ -235,6 +235,46 @@ impl Instruction {
         }
     }
 
+    // Size in bytes, including the 0xCB prefix and any immediate operand
+    pub fn length(&self) -> u16 {
+        match self {
+            Instruction::Adc(ArithmeticTarget::D8)
+            | Instruction::Add(ArithmeticTarget::D8)
+            | Instruction::And(ArithmeticTarget::D8)
+            | Instruction::Cp(ArithmeticTarget::D8)
+            | Instruction::Or(ArithmeticTarget::D8)
+            | Instruction::Sbc(ArithmeticTarget::D8)
+            | Instruction::Sub(ArithmeticTarget::D8)
+            | Instruction::Xor(ArithmeticTarget::D8) => 2,
+            Instruction::Bit(_, _)
+            | Instruction::Res(_, _)
+            | Instruction::Set(_, _)
+            | Instruction::Swap(_)
+            | Instruction::Rl(_)
+            | Instruction::Rlc(_)
+            | Instruction::Rr(_)
+            | Instruction::Rrc(_)
+            | Instruction::Sla(_)
+            | Instruction::Sra(_)
+            | Instruction::Srl(_) => 2,
+            Instruction::Ld(load_type) => match load_type {
+                LoadType::Byte(_, LoadByteSource::D8) => 2,
+                LoadType::Byte(_, _) => 1,
+                LoadType::Word(_) => 3,
+                LoadType::IndirectFromA(IndirectTarget::Word)
+                | LoadType::AFromIndirect(IndirectTarget::Word) => 3,
+                LoadType::IndirectFromA(_) | LoadType::AFromIndirect(_) => 1,
+                LoadType::ByteAddressFromA | LoadType::AFromByteAddress => 2,
+                LoadType::IndirectFromSP => 3,
+                LoadType::SPFromHL => 1,
+                LoadType::HLFromSP => 2,
+            },
+            Instruction::Jp(_) | Instruction::Call(_) => 3,
+            Instruction::Jr(_) | Instruction::AddSp | Instruction::Stop => 2,
+            _ => 1,
+        }
+    }
+
     fn from_byte_prefixed(byte: u8) -> Instruction {
         match byte {
             0x00 => Instruction::Rlc(PrefixTarget::B),
@@ -514,7 +554,7 @@ impl Instruction {
             0x0D => Instruction::Dec(IncDecTarget::C),
             0x0E => Instruction::Ld(LoadType::Byte(LoadByteTarget::C, LoadByteSource::D8)),
             0x0F => Instruction::Rrca,
-            // 0x10 => Instruction::STOP,
+            0x10 => Instruction::Stop,
             0x11 => Instruction::Ld(LoadType::Word(LoadWordTarget::DE)),
             0x12 => Instruction::Ld(LoadType::IndirectFromA(IndirectTarget::DE)),
             0x13 => Instruction::Inc(IncDecTarget::DE),
@@ -0,0 +1,267 @@
+mod inspector;
+mod memory;
+mod search;
+mod vram;
+
+pub use inspector::InspectorView;
+pub use memory::MemoryView;
+pub use search::SearchView;
+pub use vram::VramView;
+
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::{Canvas, Texture, TextureAccess};
+use sdl2::video::Window;
+use sdl2::VideoSubsystem;
+
+use crate::cpu::Cpu;
+use crate::font;
+
+const SCALE: usize = 2;
+
+pub type Color = [u8; 3];
+
+pub const BACKGROUND: Color = [0x20, 0x20, 0x28];
+pub const PANEL: Color = [0x38, 0x38, 0x44];
+pub const TEXT: Color = [0xE0, 0xE0, 0xE0];
+pub const DIM: Color = [0x80, 0x80, 0x90];
+pub const HIGHLIGHT: Color = [0xFF, 0xD0, 0x40];
+pub const OVERLAY: Color = [0xFF, 0x40, 0x40];
+
+// RGBA pixels a view draws into, shown at SCALE times its size
+pub struct Surface {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Surface {
+    fn new(width: usize, height: usize) -> Surface {
+        Surface {
+            width,
+            height,
+            pixels: vec![255; width * height * 4],
+        }
+    }
+
+    pub fn clear(&mut self, color: Color) {
+        self.fill(0, 0, self.width, self.height, color);
+    }
+
+    // Anything outside the surface is clipped
+    pub fn pixel(&mut self, x: usize, y: usize, color: Color) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        let offset = (y * self.width + x) * 4;
+        self.pixels[offset..offset + 3].copy_from_slice(&color);
+        self.pixels[offset + 3] = 255;
+    }
+
+    pub fn fill(&mut self, x: usize, y: usize, width: usize, height: usize, color: Color) {
+        for row in y..y + height {
+            for column in x..x + width {
+                self.pixel(column, row, color);
+            }
+        }
+    }
+
+    pub fn outline(&mut self, x: usize, y: usize, width: usize, height: usize, color: Color) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        self.fill(x, y, width, 1, color);
+        self.fill(x, y + height - 1, width, 1, color);
+        self.fill(x, y, 1, height, color);
+        self.fill(x + width - 1, y, 1, height, color);
+    }
+
+    pub fn text(&mut self, x: usize, y: usize, text: &str, color: Color) {
+        font::draw_text(&mut self.pixels, self.width, x, y, text, color);
+    }
+
+    // Bottom line of the window
+    pub fn status(&mut self, text: &str) {
+        let y = self.height - font::LINE_HEIGHT;
+        self.fill(0, y - 1, self.width, font::LINE_HEIGHT + 1, PANEL);
+        self.text(4, y, text, TEXT);
+    }
+}
+
+// Contents of a debug window, redrawn once per frame
+pub trait View {
+    fn title(&self) -> &'static str;
+    fn size(&self) -> (usize, usize);
+    // Mouse position in surface pixels while the pointer is over the window
+    fn draw(&mut self, cpu: &Cpu, surface: &mut Surface, mouse: Option<(usize, usize)>);
+    fn key_down(&mut self, _cpu: &mut Cpu, _keycode: Keycode) {}
+    fn click(&mut self, _cpu: &mut Cpu, _x: usize, _y: usize) {}
+}
+
+pub struct DebugWindow {
+    canvas: Canvas<Window>,
+    // Made to the surface's size, freed with the canvas
+    texture: Texture,
+    surface: Surface,
+    view: Box<dyn View>,
+    mouse: Option<(usize, usize)>,
+}
+
+impl DebugWindow {
+    pub fn new(video: &VideoSubsystem, view: Box<dyn View>) -> DebugWindow {
+        let (width, height) = view.size();
+        let window = video
+            .window(
+                view.title(),
+                (width * SCALE) as u32,
+                (height * SCALE) as u32,
+            )
+            .build()
+            .unwrap();
+
+        let canvas = window.into_canvas().build().unwrap();
+        DebugWindow {
+            texture: create_texture(&canvas, width, height),
+            canvas,
+            surface: Surface::new(width, height),
+            view,
+            mouse: None,
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.canvas.window().id()
+    }
+
+    // Returns false once the window has been closed
+    pub fn handle_event(&mut self, cpu: &mut Cpu, event: &Event) -> bool {
+        match *event {
+            Event::Window {
+                win_event: WindowEvent::Close,
+                ..
+            }
+            | Event::KeyDown {
+                keycode: Some(Keycode::Escape),
+                ..
+            } => return false,
+            Event::Window {
+                win_event: WindowEvent::Leave,
+                ..
+            } => self.mouse = None,
+            Event::KeyDown {
+                keycode: Some(keycode),
+                ..
+            } => self.view.key_down(cpu, keycode),
+            Event::MouseMotion { x, y, .. } => self.mouse = Some(to_surface(x, y)),
+            Event::MouseButtonDown {
+                mouse_btn: MouseButton::Left,
+                x,
+                y,
+                ..
+            } => {
+                let (x, y) = to_surface(x, y);
+                self.view.click(cpu, x, y);
+            }
+            _ => {}
+        }
+        true
+    }
+
+    pub fn render(&mut self, cpu: &Cpu) {
+        self.surface.clear(BACKGROUND);
+        self.view.draw(cpu, &mut self.surface, self.mouse);
+
+        let (width, height) = (self.surface.width, self.surface.height);
+        let query = self.texture.query();
+        if (query.width as usize, query.height as usize) != (width, height) {
+            let old = std::mem::replace(
+                &mut self.texture,
+                create_texture(&self.canvas, width, height),
+            );
+            // Not freed on drop with unsafe_textures, and nothing refers to it any more
+            unsafe { old.destroy() };
+        }
+        self.texture
+            .update(None, &self.surface.pixels, width * 4)
+            .unwrap();
+        self.canvas.copy(&self.texture, None, None).unwrap();
+        self.canvas.present();
+    }
+}
+
+fn create_texture(canvas: &Canvas<Window>, width: usize, height: usize) -> Texture {
+    canvas
+        .texture_creator()
+        .create_texture(
+            PixelFormatEnum::RGBA32,
+            TextureAccess::Streaming,
+            width as u32,
+            height as u32,
+        )
+        .unwrap()
+}
+
+// Hex digit typed on the main or keypad keys
+fn hex_digit(keycode: Keycode) -> Option<char> {
+    let name = keycode.name();
+    let digit = name.strip_prefix("Keypad ").unwrap_or(&name);
+    match digit.chars().collect::<Vec<_>>()[..] {
+        [c] if c.is_ascii_hexdigit() => Some(c.to_ascii_uppercase()),
+        _ => None,
+    }
+}
+
+fn to_surface(x: i32, y: i32) -> (usize, usize) {
+    (x.max(0) as usize / SCALE, y.max(0) as usize / SCALE)
+}
+
+// The open debug windows, at most one per kind of view
+pub struct DebugWindows {
+    windows: Vec<DebugWindow>,
+}
+
+impl DebugWindows {
+    pub fn new() -> DebugWindows {
+        DebugWindows { windows: vec![] }
+    }
+
+    // Opens a window for the view, or closes it if one is already open
+    pub fn toggle(&mut self, video: &VideoSubsystem, view: Box<dyn View>) {
+        match self
+            .windows
+            .iter()
+            .position(|window| window.view.title() == view.title())
+        {
+            Some(index) => {
+                self.windows.remove(index);
+            }
+            None => self.windows.push(DebugWindow::new(video, view)),
+        }
+    }
+
+    // Returns true if the event belonged to one of the debug windows
+    pub fn handle_event(&mut self, cpu: &mut Cpu, event: &Event) -> bool {
+        let Some(window_id) = event.get_window_id() else {
+            return false;
+        };
+        let Some(index) = self
+            .windows
+            .iter()
+            .position(|window| window.id() == window_id)
+        else {
+            return false;
+        };
+        if !self.windows[index].handle_event(cpu, event) {
+            self.windows.remove(index);
+        }
+        true
+    }
+
+    pub fn render(&mut self, cpu: &Cpu) {
+        for window in self.windows.iter_mut() {
+            window.render(cpu);
+        }
+    }
+}
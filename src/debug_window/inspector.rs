@@ -0,0 +1,438 @@
+use sdl2::keyboard::Keycode;
+
+use super::{hex_digit, Surface, View, DIM, HIGHLIGHT, PANEL, TEXT};
+use crate::cpu::Cpu;
+use crate::font::LINE_HEIGHT;
+use crate::gpu::stat::Mode;
+use crate::mmu::Memory;
+
+const WIDTH: usize = 480;
+const HEIGHT: usize = 284;
+const TOP: usize = 16;
+
+const PALETTE_ROW: usize = 9;
+const SWATCH_X: usize = 28;
+const COLOR_WIDTH: usize = 40;
+const OBJECT_TOP: usize = TOP + 8 * PALETTE_ROW + 12;
+const DMG_TOP: usize = OBJECT_TOP + 8 * PALETTE_ROW + 12;
+
+const TABLE_X: usize = 200;
+
+const REGISTERS: [(u16, &str); 36] = [
+    (0xFF00, "P1"),
+    (0xFF01, "SB"),
+    (0xFF02, "SC"),
+    (0xFF04, "DIV"),
+    (0xFF05, "TIMA"),
+    (0xFF06, "TMA"),
+    (0xFF07, "TAC"),
+    (0xFF0F, "IF"),
+    (0xFF40, "LCDC"),
+    (0xFF41, "STAT"),
+    (0xFF42, "SCY"),
+    (0xFF43, "SCX"),
+    (0xFF44, "LY"),
+    (0xFF45, "LYC"),
+    (0xFF46, "DMA"),
+    (0xFF47, "BGP"),
+    (0xFF48, "OBP0"),
+    (0xFF49, "OBP1"),
+    (0xFF4A, "WY"),
+    (0xFF4B, "WX"),
+    (0xFF4C, "KEY0"),
+    (0xFF4D, "KEY1"),
+    (0xFF4F, "VBK"),
+    (0xFF50, "BANK"),
+    (0xFF51, "HDMA1"),
+    (0xFF52, "HDMA2"),
+    (0xFF53, "HDMA3"),
+    (0xFF54, "HDMA4"),
+    (0xFF55, "HDMA5"),
+    (0xFF68, "BCPS"),
+    (0xFF69, "BCPD"),
+    (0xFF6A, "OCPS"),
+    (0xFF6B, "OCPD"),
+    (0xFF6C, "OPRI"),
+    (0xFF70, "SVBK"),
+    (0xFFFF, "IE"),
+];
+
+#[derive(Copy, Clone, PartialEq)]
+enum Selection {
+    Register(usize),
+    // Object palettes, palette number, colour
+    Color(bool, usize, usize),
+}
+
+impl Selection {
+    fn digits(&self) -> usize {
+        match self {
+            Selection::Register(_) => 2,
+            Selection::Color(..) => 4,
+        }
+    }
+}
+
+// CGB palette RAM, DMG palettes and a decoded table of the I/O registers
+pub struct InspectorView {
+    selection: Selection,
+    // Hex digits typed so far for the selection
+    input: String,
+}
+
+impl InspectorView {
+    pub fn new() -> InspectorView {
+        InspectorView {
+            selection: Selection::Register(0),
+            input: String::new(),
+        }
+    }
+
+    fn draw_palettes(&self, memory: &Memory, surface: &mut Surface) {
+        let gpu = &memory.gpu;
+
+        for (object, top, name) in [(false, TOP, "BG"), (true, OBJECT_TOP, "OBJ")] {
+            let (ram, index) = if object {
+                (&gpu.object_palette, gpu.obpi)
+            } else {
+                (&gpu.bg_palette, gpu.bgpi)
+            };
+            surface.text(
+                8,
+                top - 8,
+                &format!("CGB {} PALETTES (INDEX {:02X})", name, index),
+                TEXT,
+            );
+
+            for palette in 0..8 {
+                let y = top + palette * PALETTE_ROW;
+                surface.text(8, y + 1, &format!("{}{}", name, palette), DIM);
+                let colors = gpu.cgb_colors(object, palette);
+                for (color, pixel) in colors.iter().enumerate() {
+                    let x = SWATCH_X + color * COLOR_WIDTH;
+                    let offset = palette * 8 + color * 2;
+                    let value = ram[offset] as u16 | (ram[offset + 1] as u16) << 8;
+
+                    surface.fill(x, y, 10, 7, pixel.rgb());
+                    let selection = Selection::Color(object, palette, color);
+                    let (text, text_color) = if selection == self.selection {
+                        (self.pending(format!("{:04X}", value)), HIGHLIGHT)
+                    } else {
+                        (format!("{:04X}", value), TEXT)
+                    };
+                    surface.text(x + 12, y + 1, &text, text_color);
+                }
+            }
+        }
+
+        surface.text(8, DMG_TOP - 8, "DMG PALETTES", TEXT);
+        for (index, name) in ["BGP", "OBP0", "OBP1"].into_iter().enumerate() {
+            let y = DMG_TOP + index * PALETTE_ROW;
+            surface.text(
+                8,
+                y + 1,
+                &format!("{:4} {:02X}", name, gpu.palettes[index]),
+                DIM,
+            );
+            for (color, pixel) in gpu.dmg_colors(index).iter().enumerate() {
+                surface.fill(SWATCH_X + 12 + color * 12, y, 10, 7, pixel.rgb());
+            }
+        }
+    }
+
+    fn draw_registers(&self, memory: &Memory, surface: &mut Surface) {
+        surface.text(TABLE_X, TOP - 8, "I/O REGISTERS", TEXT);
+
+        for (row, (address, name)) in REGISTERS.iter().enumerate() {
+            let y = TOP + row * LINE_HEIGHT;
+            let value = memory.peek_byte(*address);
+            let selected = self.selection == Selection::Register(row);
+            if selected {
+                surface.fill(TABLE_X - 2, y - 1, WIDTH - TABLE_X, LINE_HEIGHT, PANEL);
+            }
+
+            let value_text = if selected {
+                self.pending(format!("{:02X}", value))
+            } else {
+                format!("{:02X}", value)
+            };
+            surface.text(TABLE_X, y, &format!("{:04X} {}", address, name), DIM);
+            surface.text(
+                TABLE_X + 44,
+                y,
+                &value_text,
+                if selected { HIGHLIGHT } else { TEXT },
+            );
+            surface.text(TABLE_X + 60, y, &decode(memory, *address, value), TEXT);
+        }
+    }
+
+    // The value with the digits typed so far in place of its first digits
+    fn pending(&self, value: String) -> String {
+        if self.input.is_empty() {
+            return value;
+        }
+        format!("{:_<width$}", self.input, width = value.len())
+    }
+
+    fn select(&mut self, selection: Selection) {
+        self.selection = selection;
+        self.input.clear();
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        let selection = match self.selection {
+            Selection::Register(row) => Selection::Register(
+                (row as isize + delta).rem_euclid(REGISTERS.len() as isize) as usize,
+            ),
+            Selection::Color(object, palette, color) => {
+                let index = (object as isize * 32 + palette as isize * 4 + color as isize + delta)
+                    .rem_euclid(64) as usize;
+                Selection::Color(index >= 32, index % 32 / 4, index % 4)
+            }
+        };
+        self.select(selection);
+    }
+
+    fn commit(&mut self, memory: &mut Memory) {
+        let Ok(value) = u16::from_str_radix(&self.input, 16) else {
+            return;
+        };
+        match self.selection {
+            Selection::Register(row) => memory.poke_byte(REGISTERS[row].0, value as u8),
+            Selection::Color(object, palette, color) => {
+                let offset = palette * 8 + color * 2;
+                memory.gpu.write_palette_ram(object, offset, value as u8);
+                memory
+                    .gpu
+                    .write_palette_ram(object, offset + 1, (value >> 8) as u8 & 0x7F);
+            }
+        }
+        self.input.clear();
+    }
+}
+
+impl View for InspectorView {
+    fn title(&self) -> &'static str {
+        "Palettes and I/O"
+    }
+
+    fn size(&self) -> (usize, usize) {
+        (WIDTH, HEIGHT)
+    }
+
+    fn draw(&mut self, cpu: &Cpu, surface: &mut Surface, _mouse: Option<(usize, usize)>) {
+        self.draw_palettes(&cpu.mem, surface);
+        self.draw_registers(&cpu.mem, surface);
+
+        let status = match self.selection {
+            _ if !self.input.is_empty() => format!("EDITING: {}", self.input),
+            Selection::Register(row) => format!(
+                "{:04X} {}: TYPE HEX TO WRITE, ARROWS OR CLICK TO SELECT, TAB SWITCHES TABLE",
+                REGISTERS[row].0, REGISTERS[row].1
+            ),
+            Selection::Color(object, palette, color) => format!(
+                "{} {} COLOUR {}: TYPE A 15-BIT BGR555 VALUE",
+                if object { "OBJ" } else { "BG" },
+                palette,
+                color
+            ),
+        };
+        surface.status(&status);
+    }
+
+    fn key_down(&mut self, cpu: &mut Cpu, keycode: Keycode) {
+        match keycode {
+            Keycode::Up => self.move_selection(-1),
+            Keycode::Down => self.move_selection(1),
+            Keycode::Left => self.move_selection(-1),
+            Keycode::Right => self.move_selection(1),
+            Keycode::Tab => match self.selection {
+                Selection::Register(_) => self.select(Selection::Color(false, 0, 0)),
+                Selection::Color(..) => self.select(Selection::Register(0)),
+            },
+            Keycode::Backspace => {
+                self.input.pop();
+            }
+            _ => {
+                let Some(digit) = hex_digit(keycode) else {
+                    return;
+                };
+                self.input.push(digit);
+                if self.input.len() == self.selection.digits() {
+                    self.commit(&mut cpu.mem);
+                }
+            }
+        }
+    }
+
+    fn click(&mut self, _cpu: &mut Cpu, x: usize, y: usize) {
+        if x >= TABLE_X {
+            if let Some(row) = y.checked_sub(TOP).map(|y| y / LINE_HEIGHT) {
+                if row < REGISTERS.len() {
+                    self.select(Selection::Register(row));
+                }
+            }
+            return;
+        }
+
+        if x < SWATCH_X {
+            return;
+        }
+        let color = (x - SWATCH_X) / COLOR_WIDTH;
+        for (object, top) in [(false, TOP), (true, OBJECT_TOP)] {
+            let palette = y.wrapping_sub(top) / PALETTE_ROW;
+            if palette < 8 && color < 4 {
+                self.select(Selection::Color(object, palette, color));
+            }
+        }
+    }
+}
+
+fn flags(value: u8, names: &[&str]) -> String {
+    names
+        .iter()
+        .enumerate()
+        .filter(|(bit, _)| value & (1 << bit) != 0)
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn shades(value: u8) -> String {
+    format!(
+        "SHADES {} {} {} {}",
+        value & 3,
+        (value >> 2) & 3,
+        (value >> 4) & 3,
+        value >> 6
+    )
+}
+
+fn decode(memory: &Memory, address: u16, value: u8) -> String {
+    let gpu = &memory.gpu;
+    match address {
+        0xFF00 => {
+            let group = match (value >> 4) & 0x03 {
+                0b00 => "BOTH",
+                0b01 => "BUTTONS",
+                0b10 => "DIRECTIONS",
+                _ => "NONE",
+            };
+            format!("SELECT {} LOW {:04b}", group, value & 0x0F)
+        }
+        0xFF02 => flags(value, &["CLOCK", "", "", "", "", "", "", "START"]),
+        0xFF07 => format!(
+            "{} {} HZ",
+            if value & 0x04 != 0 { "ON" } else { "OFF" },
+            [4096, 262144, 65536, 16384][(value & 0x03) as usize]
+        ),
+        0xFF0F | 0xFFFF => flags(value, &["VBLANK", "STAT", "TIMER", "SERIAL", "JOYPAD"]),
+        0xFF40 => {
+            let lcdc = &gpu.lcdc;
+            let mut fields = vec![];
+            fields.push(if lcdc.display_enabled {
+                "LCD"
+            } else {
+                "LCD OFF"
+            });
+            if lcdc.window_display_enabled {
+                fields.push("WIN");
+            }
+            fields.push(if lcdc.window_tile_map {
+                "WIN 9C00"
+            } else {
+                "WIN 9800"
+            });
+            fields.push(if lcdc.bg_window_tile_data {
+                "DATA 8000"
+            } else {
+                "DATA 8800"
+            });
+            fields.push(if lcdc.bg_tile_map {
+                "BG 9C00"
+            } else {
+                "BG 9800"
+            });
+            fields.push(if lcdc.sprite_size {
+                "OBJ 8X16"
+            } else {
+                "OBJ 8X8"
+            });
+            if lcdc.object_display_enabled {
+                fields.push("OBJ");
+            }
+            if lcdc.bg_window_enabled {
+                fields.push("BG");
+            }
+            fields.join(" ")
+        }
+        0xFF41 => {
+            let stat = &gpu.stat;
+            let mode = match stat.mode {
+                Mode::HorizontalBlank => "HBLANK",
+                Mode::VerticalBlank => "VBLANK",
+                Mode::OAMAccess => "OAM",
+                Mode::VRAMAccess => "DRAW",
+            };
+            let mut fields = vec![format!("MODE {}", mode)];
+            for (enabled, name) in [
+                (stat.coincidence_flag, "LY=LYC"),
+                (stat.h_blank_interrupt, "INT HBLANK"),
+                (stat.v_blank_interrupt, "INT VBLANK"),
+                (stat.oam_interrupt, "INT OAM"),
+                (stat.coincidence_interrupt, "INT LYC"),
+            ] {
+                if enabled {
+                    fields.push(name.to_string());
+                }
+            }
+            fields.join(" ")
+        }
+        0xFF47..=0xFF49 => shades(value),
+        0xFF4A | 0xFF4B | 0xFF42..=0xFF45 => format!("{}", value),
+        0xFF4C => if value & 0x04 != 0 {
+            "DMG COMPATIBILITY"
+        } else {
+            "CGB"
+        }
+        .to_string(),
+        0xFF4D => format!(
+            "{} SPEED{}",
+            if value & 0x80 != 0 {
+                "DOUBLE"
+            } else {
+                "NORMAL"
+            },
+            if value & 0x01 != 0 {
+                ", SWITCH ARMED"
+            } else {
+                ""
+            }
+        ),
+        0xFF4F => format!("BANK {}", value & 0x01),
+        0xFF50 => if memory.boot_active {
+            "BOOT ROM MAPPED"
+        } else {
+            "BOOT ROM OFF"
+        }
+        .to_string(),
+        0xFF68 | 0xFF6A => format!(
+            "INDEX {:02X}{}",
+            value & 0x3F,
+            if value & 0x80 != 0 {
+                " AUTO INCREMENT"
+            } else {
+                ""
+            }
+        ),
+        0xFF6C => if value & 0x01 != 0 {
+            "PRIORITY BY X"
+        } else {
+            "PRIORITY BY OAM"
+        }
+        .to_string(),
+        0xFF70 => format!("BANK {}", (value & 0x07).max(1)),
+        _ => String::new(),
+    }
+}
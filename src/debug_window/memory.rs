@@ -0,0 +1,437 @@
+use sdl2::keyboard::Keycode;
+
+use super::{hex_digit, Surface, View, DIM, HIGHLIGHT, OVERLAY, PANEL, TEXT};
+use crate::cpu::Cpu;
+use crate::font::{CHAR_WIDTH, LINE_HEIGHT};
+use crate::mmu::Memory;
+
+const WIDTH: usize = 440;
+const HEIGHT: usize = 264;
+const TOP: usize = 16;
+const ROWS: usize = 32;
+
+const HEX_X: usize = 8 + 6 * CHAR_WIDTH;
+const CELL_WIDTH: usize = 3 * CHAR_WIDTH;
+const ASCII_X: usize = HEX_X + 16 * CELL_WIDTH + CHAR_WIDTH;
+const FREEZE_X: usize = ASCII_X + 17 * CHAR_WIDTH + 8;
+
+const ROM_BANK_SIZE: usize = 0x4000;
+const SRAM_BANK_SIZE: usize = 0x2000;
+const WRAM_BANK_SIZE: usize = 0x1000;
+const VRAM_BANK_SIZE: usize = 0x2000;
+
+// What the editor shows: the CPU's view of memory or one bank of a memory
+#[derive(Copy, Clone, PartialEq)]
+enum Region {
+    Bus,
+    Rom(usize),
+    Sram(usize),
+    Wram(usize),
+    Vram(usize),
+}
+
+impl Region {
+    fn name(&self) -> String {
+        match *self {
+            Region::Bus => "BUS".to_string(),
+            Region::Rom(bank) => format!("ROM BANK {:02X}", bank),
+            Region::Sram(bank) => format!("SRAM BANK {:X}", bank),
+            Region::Wram(bank) => format!("WRAM BANK {}", bank),
+            Region::Vram(bank) => format!("VRAM BANK {}", bank),
+        }
+    }
+
+    // Address the bank would appear at on the bus
+    fn base(&self) -> usize {
+        match *self {
+            Region::Bus | Region::Rom(0) => 0x0000,
+            Region::Rom(_) => 0x4000,
+            Region::Sram(_) => 0xA000,
+            Region::Wram(0) => 0xC000,
+            Region::Wram(_) => 0xD000,
+            Region::Vram(_) => 0x8000,
+        }
+    }
+
+    fn len(&self, memory: &Memory) -> usize {
+        match *self {
+            Region::Bus => 0x10000,
+            Region::Rom(_) => ROM_BANK_SIZE,
+            Region::Sram(_) => memory.sram().len().min(SRAM_BANK_SIZE),
+            Region::Wram(_) => WRAM_BANK_SIZE,
+            Region::Vram(_) => VRAM_BANK_SIZE,
+        }
+    }
+
+    fn banks(&self, memory: &Memory) -> usize {
+        match *self {
+            Region::Bus => 1,
+            Region::Rom(_) => memory.rom().len().div_ceil(ROM_BANK_SIZE),
+            Region::Sram(_) => memory.sram().len().div_ceil(SRAM_BANK_SIZE),
+            Region::Wram(_) => memory.wram().len() / WRAM_BANK_SIZE,
+            Region::Vram(_) => 2,
+        }
+    }
+
+    fn bank(&self) -> usize {
+        match *self {
+            Region::Bus => 0,
+            Region::Rom(bank) | Region::Sram(bank) | Region::Wram(bank) | Region::Vram(bank) => {
+                bank
+            }
+        }
+    }
+
+    fn with_bank(&self, bank: usize) -> Region {
+        match *self {
+            Region::Bus => Region::Bus,
+            Region::Rom(_) => Region::Rom(bank),
+            Region::Sram(_) => Region::Sram(bank),
+            Region::Wram(_) => Region::Wram(bank),
+            Region::Vram(_) => Region::Vram(bank),
+        }
+    }
+
+    // Cycles bus, ROM, SRAM, WRAM, VRAM, skipping SRAM when the cartridge has none
+    fn next(&self, memory: &Memory) -> Region {
+        let next = match *self {
+            Region::Bus => Region::Rom(0),
+            Region::Rom(_) => Region::Sram(0),
+            Region::Sram(_) => Region::Wram(0),
+            Region::Wram(_) => Region::Vram(0),
+            Region::Vram(_) => Region::Bus,
+        };
+        if next.len(memory) == 0 {
+            return next.next(memory);
+        }
+        next
+    }
+
+    fn read(&self, memory: &Memory, offset: usize) -> u8 {
+        match *self {
+            Region::Bus => memory.peek_byte(offset as u16),
+            Region::Rom(bank) => memory
+                .rom()
+                .get(bank * ROM_BANK_SIZE + offset)
+                .copied()
+                .unwrap_or(0xFF),
+            Region::Sram(bank) => memory.sram()[bank * SRAM_BANK_SIZE + offset],
+            Region::Wram(bank) => memory.wram()[bank * WRAM_BANK_SIZE + offset],
+            Region::Vram(bank) => memory.gpu.read_vram_bank(bank as u8, offset),
+        }
+    }
+
+    // False for ROM, which can't be edited
+    fn write(&self, memory: &mut Memory, offset: usize, value: u8) -> bool {
+        match *self {
+            // Writes to the ROM area would go to the MBC registers instead
+            Region::Bus if offset < 0x8000 => return false,
+            Region::Bus => memory.poke_byte(offset as u16, value),
+            Region::Rom(_) => return false,
+            Region::Sram(bank) => memory.sram_mut()[bank * SRAM_BANK_SIZE + offset] = value,
+            Region::Wram(bank) => memory.wram_mut()[bank * WRAM_BANK_SIZE + offset] = value,
+            Region::Vram(bank) => memory.gpu.write_vram_bank(bank as u8, offset, value),
+        }
+        true
+    }
+}
+
+#[derive(PartialEq)]
+enum Input {
+    None,
+    // First digit of the byte being typed over
+    Edit(char),
+    Search(String),
+    Jump(String),
+}
+
+// Hex viewer and editor with search, freezing and jump-to-address
+pub struct MemoryView {
+    region: Region,
+    cursor: usize,
+    // First row shown
+    scroll: usize,
+    input: Input,
+    last_search: Vec<u8>,
+    message: String,
+}
+
+impl MemoryView {
+    pub fn new() -> MemoryView {
+        MemoryView {
+            region: Region::Bus,
+            cursor: 0xC000,
+            scroll: 0xC000 / 16,
+            input: Input::None,
+            last_search: vec![],
+            message: String::new(),
+        }
+    }
+
+    fn move_cursor(&mut self, memory: &Memory, cursor: isize) {
+        let len = self.region.len(memory);
+        self.cursor = cursor.clamp(0, len as isize - 1) as usize;
+
+        let row = self.cursor / 16;
+        if row < self.scroll {
+            self.scroll = row;
+        } else if row >= self.scroll + ROWS {
+            self.scroll = row + 1 - ROWS;
+        }
+    }
+
+    fn set_region(&mut self, memory: &Memory, region: Region) {
+        self.region = region;
+        self.input = Input::None;
+        self.move_cursor(memory, self.cursor as isize);
+    }
+
+    fn type_digit(&mut self, memory: &mut Memory, digit: char) {
+        match &mut self.input {
+            Input::Search(text) | Input::Jump(text) => text.push(digit),
+            Input::Edit(first) => {
+                let value = u8::from_str_radix(&format!("{}{}", first, digit), 16).unwrap();
+                self.input = Input::None;
+                if self.region.write(memory, self.cursor, value) {
+                    self.move_cursor(memory, self.cursor as isize + 1);
+                } else {
+                    self.message = "ROM IS READ ONLY".to_string();
+                }
+            }
+            Input::None => self.input = Input::Edit(digit),
+        }
+    }
+
+    fn submit(&mut self, memory: &Memory) {
+        match std::mem::replace(&mut self.input, Input::None) {
+            Input::Jump(text) => match usize::from_str_radix(&text, 16) {
+                Ok(address) => {
+                    let offset = address.wrapping_sub(self.region.base());
+                    if offset < self.region.len(memory) {
+                        self.move_cursor(memory, offset as isize);
+                    } else {
+                        self.message = format!("{:04X} IS OUTSIDE {}", address, self.region.name());
+                    }
+                }
+                Err(_) => self.message = "INVALID ADDRESS".to_string(),
+            },
+            Input::Search(text) => match parse_bytes(&text) {
+                Some(pattern) => {
+                    self.last_search = pattern;
+                    self.find_next(memory);
+                }
+                None => self.message = "SEARCH NEEDS WHOLE BYTES, E.G. 3E01".to_string(),
+            },
+            Input::Edit(_) | Input::None => {}
+        }
+    }
+
+    // Searches forward from after the cursor, wrapping around the region
+    fn find_next(&mut self, memory: &Memory) {
+        if self.last_search.is_empty() {
+            return;
+        }
+        let len = self.region.len(memory);
+        let bytes: Vec<u8> = (0..len)
+            .map(|offset| self.region.read(memory, offset))
+            .collect();
+
+        let found = (1..=len)
+            .map(|step| (self.cursor + step) % len)
+            .find(|&start| {
+                self.last_search
+                    .iter()
+                    .enumerate()
+                    .all(|(index, &byte)| bytes.get(start + index) == Some(&byte))
+            });
+        match found {
+            Some(offset) => {
+                self.move_cursor(memory, offset as isize);
+                self.message = format!("FOUND AT {:04X}", self.region.base() + offset);
+            }
+            None => self.message = "NOT FOUND".to_string(),
+        }
+    }
+
+    // Freezes apply to the bus address, so in a bank view they follow whichever bank is mapped
+    fn toggle_freeze(&mut self, memory: &mut Memory) {
+        let address = self.region.base() + self.cursor;
+        if address < 0x8000 {
+            self.message = "ROM CAN'T BE FROZEN".to_string();
+            return;
+        }
+        let address = address as u16;
+        if memory.freezes.remove(&address).is_none() {
+            let value = self.region.read(memory, self.cursor);
+            memory.freezes.insert(address, value);
+        }
+    }
+
+    fn draw_rows(&self, memory: &Memory, surface: &mut Surface) {
+        let len = self.region.len(memory);
+
+        for row in 0..ROWS {
+            let start = (self.scroll + row) * 16;
+            if start >= len {
+                break;
+            }
+            let y = TOP + row * LINE_HEIGHT;
+            surface.text(8, y, &format!("{:04X}", self.region.base() + start), DIM);
+
+            let mut ascii = String::new();
+            for column in 0..16 {
+                let offset = start + column;
+                let value = self.region.read(memory, offset);
+                let x = HEX_X + column * CELL_WIDTH;
+
+                let mut text = format!("{:02X}", value);
+                let mut color = TEXT;
+                let address = self.region.base() + offset;
+                if address >= 0x8000 && memory.freezes.contains_key(&(address as u16)) {
+                    color = OVERLAY;
+                }
+                if offset == self.cursor {
+                    surface.fill(x - 1, y - 1, 2 * CHAR_WIDTH + 1, LINE_HEIGHT, PANEL);
+                    color = HIGHLIGHT;
+                    if let Input::Edit(digit) = self.input {
+                        text = format!("{}_", digit);
+                    }
+                }
+                surface.text(x, y, &text, color);
+
+                ascii.push(match value {
+                    0x20..=0x5F => value as char,
+                    0x61..=0x7A => (value - 0x20) as char,
+                    _ => '.',
+                });
+            }
+            surface.text(ASCII_X, y, &ascii, DIM);
+        }
+    }
+
+    fn draw_freezes(&self, memory: &Memory, surface: &mut Surface) {
+        surface.text(FREEZE_X, TOP - 8, "FROZEN", TEXT);
+        for (index, (address, value)) in memory.freezes.iter().take(ROWS).enumerate() {
+            let y = TOP + index * LINE_HEIGHT;
+            surface.text(
+                FREEZE_X,
+                y,
+                &format!("{:04X}={:02X}", address, value),
+                OVERLAY,
+            );
+        }
+    }
+}
+
+impl View for MemoryView {
+    fn title(&self) -> &'static str {
+        "Memory"
+    }
+
+    fn size(&self) -> (usize, usize) {
+        (WIDTH, HEIGHT)
+    }
+
+    fn draw(&mut self, cpu: &Cpu, surface: &mut Surface, _mouse: Option<(usize, usize)>) {
+        let memory = &cpu.mem;
+        let banks = self.region.banks(memory);
+        let header = match self.region {
+            Region::Bus => "BUS (TAB FOR BANK VIEWS)".to_string(),
+            region => format!("{} OF {} ([ ] CHANGE BANK)", region.name(), banks),
+        };
+        surface.text(8, 4, &header, HIGHLIGHT);
+
+        self.draw_rows(memory, surface);
+        self.draw_freezes(memory, surface);
+
+        let status = match &self.input {
+            Input::Search(text) => format!("SEARCH BYTES: {}_", text),
+            Input::Jump(text) => format!("JUMP TO: {}_", text),
+            _ if !self.message.is_empty() => self.message.clone(),
+            _ => format!(
+                "{:04X}  HEX TO EDIT, / SEARCH, N NEXT, G JUMP, SPACE FREEZE",
+                self.region.base() + self.cursor
+            ),
+        };
+        surface.status(&status);
+    }
+
+    fn key_down(&mut self, cpu: &mut Cpu, keycode: Keycode) {
+        let memory = &mut cpu.mem;
+        let cursor = self.cursor as isize;
+        self.message.clear();
+
+        if matches!(self.input, Input::Search(_) | Input::Jump(_)) {
+            match keycode {
+                Keycode::Return | Keycode::KpEnter => self.submit(memory),
+                Keycode::Backspace => match &mut self.input {
+                    Input::Search(text) | Input::Jump(text) if !text.is_empty() => {
+                        text.pop();
+                    }
+                    _ => self.input = Input::None,
+                },
+                _ => {
+                    if let Some(digit) = hex_digit(keycode) {
+                        self.type_digit(memory, digit);
+                    }
+                }
+            }
+            return;
+        }
+
+        // Any other key abandons a half typed byte
+        if hex_digit(keycode).is_none() {
+            self.input = Input::None;
+        }
+
+        match keycode {
+            Keycode::Left => self.move_cursor(memory, cursor - 1),
+            Keycode::Right => self.move_cursor(memory, cursor + 1),
+            Keycode::Up => self.move_cursor(memory, cursor - 16),
+            Keycode::Down => self.move_cursor(memory, cursor + 16),
+            Keycode::PageUp => self.move_cursor(memory, cursor - 16 * ROWS as isize),
+            Keycode::PageDown => self.move_cursor(memory, cursor + 16 * ROWS as isize),
+            Keycode::Tab => self.set_region(memory, self.region.next(memory)),
+            Keycode::LeftBracket | Keycode::RightBracket => {
+                let banks = self.region.banks(memory);
+                let bank = if keycode == Keycode::LeftBracket {
+                    (self.region.bank() + banks - 1) % banks
+                } else {
+                    (self.region.bank() + 1) % banks
+                };
+                self.set_region(memory, self.region.with_bank(bank));
+            }
+            Keycode::Slash => self.input = Input::Search(String::new()),
+            Keycode::G => self.input = Input::Jump(String::new()),
+            Keycode::N => self.find_next(memory),
+            Keycode::Space => self.toggle_freeze(memory),
+            _ => {
+                if let Some(digit) = hex_digit(keycode) {
+                    self.type_digit(memory, digit);
+                }
+            }
+        }
+    }
+
+    fn click(&mut self, cpu: &mut Cpu, x: usize, y: usize) {
+        let (Some(column), Some(row)) = (x.checked_sub(HEX_X), y.checked_sub(TOP)) else {
+            return;
+        };
+        let (column, row) = (column / CELL_WIDTH, row / LINE_HEIGHT);
+        if column < 16 && row < ROWS {
+            self.input = Input::None;
+            self.move_cursor(&cpu.mem, ((self.scroll + row) * 16 + column) as isize);
+        }
+    }
+}
+
+// "3E01" is the two bytes 3E, 01
+fn parse_bytes(text: &str) -> Option<Vec<u8>> {
+    if text.is_empty() || !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&text[index..index + 2], 16).ok())
+        .collect()
+}
@@ -0,0 +1,218 @@
+use sdl2::keyboard::Keycode;
+
+use super::{hex_digit, Surface, View, DIM, HIGHLIGHT, OVERLAY, PANEL, TEXT};
+use crate::cpu::Cpu;
+use crate::font::LINE_HEIGHT;
+use crate::ram_search::{RamSearch, Relation, Size};
+
+const WIDTH: usize = 300;
+const HEIGHT: usize = 272;
+const TOP: usize = 40;
+const ROWS: usize = 32;
+
+// RAM search over SRAM, WRAM and HRAM, with freezing of the results
+pub struct SearchView {
+    search: RamSearch,
+    // Selected candidate and first one shown
+    cursor: usize,
+    scroll: usize,
+    // Decimal value being typed for an "equal to" search
+    value: Option<String>,
+    message: String,
+}
+
+impl SearchView {
+    pub fn new() -> SearchView {
+        SearchView {
+            search: RamSearch::new(),
+            cursor: 0,
+            scroll: 0,
+            value: None,
+            message: "PRESS R TO TAKE THE FIRST SNAPSHOT".to_string(),
+        }
+    }
+
+    fn move_cursor(&mut self, cursor: isize) {
+        let len = self.search.candidates().len();
+        self.cursor = cursor.clamp(0, len.max(1) as isize - 1) as usize;
+        if self.cursor < self.scroll {
+            self.scroll = self.cursor;
+        } else if self.cursor >= self.scroll + ROWS {
+            self.scroll = self.cursor + 1 - ROWS;
+        }
+    }
+
+    fn filter(&mut self, cpu: &Cpu, relation: Relation) {
+        if self.search.searches == 0 && self.search.candidates().is_empty() {
+            self.search.reset(&cpu.mem);
+        }
+        self.search.filter(&cpu.mem, relation);
+        self.cursor = 0;
+        self.scroll = 0;
+        self.message = format!("{} CANDIDATES LEFT", self.search.candidates().len());
+    }
+
+    fn reset(&mut self, cpu: &Cpu) {
+        self.search.reset(&cpu.mem);
+        self.cursor = 0;
+        self.scroll = 0;
+        self.message = format!("SNAPSHOT OF {} ADDRESSES", self.search.candidates().len());
+    }
+
+    fn submit_value(&mut self, cpu: &Cpu) {
+        let Some(text) = self.value.take() else {
+            return;
+        };
+        match text.parse() {
+            Ok(value) => self.filter(cpu, Relation::EqualTo(value)),
+            Err(_) => self.message = "INVALID VALUE".to_string(),
+        }
+    }
+}
+
+impl View for SearchView {
+    fn title(&self) -> &'static str {
+        "RAM Search"
+    }
+
+    fn size(&self) -> (usize, usize) {
+        (WIDTH, HEIGHT)
+    }
+
+    fn draw(&mut self, cpu: &Cpu, surface: &mut Surface, _mouse: Option<(usize, usize)>) {
+        let search = &self.search;
+        let memory = &cpu.mem;
+
+        let size = match search.size() {
+            Size::Byte => "8-BIT",
+            Size::Word => "16-BIT",
+        };
+        let signed = if search.signed { "SIGNED" } else { "UNSIGNED" };
+        surface.text(
+            8,
+            4,
+            &format!(
+                "{} (B)  {} (U)  {} CANDIDATES",
+                size,
+                signed,
+                search.candidates().len()
+            ),
+            HIGHLIGHT,
+        );
+        surface.text(
+            8,
+            4 + LINE_HEIGHT,
+            "R SNAPSHOT  1 UNCHANGED  2 CHANGED",
+            DIM,
+        );
+        surface.text(
+            8,
+            4 + 2 * LINE_HEIGHT,
+            "3 INCREASED  4 DECREASED  5 EQUAL TO VALUE",
+            DIM,
+        );
+        surface.text(8, TOP - 8, "ADDRESS   PREVIOUS    CURRENT", TEXT);
+
+        let rows = search.candidates().iter().enumerate().skip(self.scroll);
+        for (row, (index, candidate)) in rows.take(ROWS).enumerate() {
+            let y = TOP + row * LINE_HEIGHT;
+            let current = search.raw(memory, candidate.address);
+            if index == self.cursor {
+                surface.fill(4, y - 1, WIDTH - 8, LINE_HEIGHT, PANEL);
+            }
+            let color = if memory.freezes.contains_key(&candidate.address) {
+                OVERLAY
+            } else if current != candidate.previous {
+                HIGHLIGHT
+            } else {
+                TEXT
+            };
+            surface.text(
+                8,
+                y,
+                &format!(
+                    "{:04X}  {:>10} {:>10}",
+                    candidate.address,
+                    search.value(candidate.previous),
+                    search.value(current)
+                ),
+                color,
+            );
+        }
+
+        let status = match &self.value {
+            Some(text) => format!("EQUAL TO: {}_ (ENTER TO SEARCH)", text),
+            None if !self.message.is_empty() => self.message.clone(),
+            None => "ARROWS SELECT, SPACE FREEZES AT THE CURRENT VALUE".to_string(),
+        };
+        surface.status(&status);
+    }
+
+    fn key_down(&mut self, cpu: &mut Cpu, keycode: Keycode) {
+        self.message.clear();
+
+        if let Some(text) = self.value.as_mut() {
+            match keycode {
+                Keycode::Return | Keycode::KpEnter => self.submit_value(cpu),
+                Keycode::Backspace if text.is_empty() => self.value = None,
+                Keycode::Backspace => {
+                    text.pop();
+                }
+                Keycode::Minus | Keycode::KpMinus if text.is_empty() => text.push('-'),
+                _ => {
+                    if let Some(digit) = hex_digit(keycode).filter(char::is_ascii_digit) {
+                        text.push(digit);
+                    }
+                }
+            }
+            return;
+        }
+
+        let cursor = self.cursor as isize;
+        match keycode {
+            Keycode::R => self.reset(cpu),
+            Keycode::Num1 => self.filter(cpu, Relation::Unchanged),
+            Keycode::Num2 => self.filter(cpu, Relation::Changed),
+            Keycode::Num3 => self.filter(cpu, Relation::Increased),
+            Keycode::Num4 => self.filter(cpu, Relation::Decreased),
+            Keycode::Num5 => self.value = Some(String::new()),
+            // Snapshots hold raw values of one size, so a new size starts over
+            Keycode::B => {
+                self.search.set_size(match self.search.size() {
+                    Size::Byte => Size::Word,
+                    Size::Word => Size::Byte,
+                });
+                self.reset(cpu);
+            }
+            Keycode::U => self.search.signed = !self.search.signed,
+            Keycode::Up => self.move_cursor(cursor - 1),
+            Keycode::Down => self.move_cursor(cursor + 1),
+            Keycode::PageUp => self.move_cursor(cursor - ROWS as isize),
+            Keycode::PageDown => self.move_cursor(cursor + ROWS as isize),
+            Keycode::Space => {
+                if let Some(candidate) = self.search.candidates().get(self.cursor) {
+                    let address = candidate.address;
+                    if cpu.mem.freezes.contains_key(&address) {
+                        cpu.mem.freezes.remove(&address);
+                        if self.search.size() == Size::Word {
+                            cpu.mem.freezes.remove(&(address + 1));
+                        }
+                        self.message = format!("UNFROZE {:04X}", address);
+                    } else {
+                        self.search.freeze(&mut cpu.mem, address);
+                        self.message = format!("FROZE {:04X}", address);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn click(&mut self, _cpu: &mut Cpu, _x: usize, y: usize) {
+        if let Some(row) = y.checked_sub(TOP).map(|y| y / LINE_HEIGHT) {
+            if row < ROWS && self.scroll + row < self.search.candidates().len() {
+                self.move_cursor((self.scroll + row) as isize);
+            }
+        }
+    }
+}
@@ -0,0 +1,504 @@
+use sdl2::keyboard::Keycode;
+
+use super::{Color, Surface, View, DIM, HIGHLIGHT, OVERLAY, PANEL, TEXT};
+use crate::cpu::Cpu;
+use crate::font;
+use crate::gpu::{GameBoyMode, Gpu};
+
+const WIDTH: usize = 536;
+const HEIGHT: usize = 300;
+const TOP: usize = 24;
+
+const TILES_PER_BANK: usize = 384;
+const TILES_PER_ROW: usize = 16;
+const BANK_X: [usize; 2] = [8, 152];
+
+const PICKER_X: usize = 296;
+const PICKER_ROW: usize = 8;
+const ZOOM_X: usize = 376;
+const ZOOM: usize = 8;
+
+const MAP_X: [usize; 2] = [8, 272];
+const MAP_ADDRESS: [u16; 2] = [0x9800, 0x9C00];
+
+const OAM_COLUMN_WIDTH: usize = 176;
+const OAM_ROW_HEIGHT: usize = 18;
+const OAM_ROWS: usize = 14;
+
+const GREY: [Color; 4] = [
+    [0xFF, 0xFF, 0xFF],
+    [0xAA, 0xAA, 0xAA],
+    [0x55, 0x55, 0x55],
+    [0x00, 0x00, 0x00],
+];
+
+#[derive(Copy, Clone, PartialEq)]
+enum Page {
+    Tiles,
+    Maps,
+    Oam,
+}
+
+const PAGES: [(Page, &str); 3] = [
+    (Page::Tiles, "1 TILES"),
+    (Page::Maps, "2 MAPS"),
+    (Page::Oam, "3 OAM"),
+];
+
+// Palette used to draw the tile data
+#[derive(Copy, Clone, PartialEq)]
+enum Palette {
+    Grey,
+    // BGP, OBP0, OBP1
+    Dmg(usize),
+    Background(usize),
+    Object(usize),
+}
+
+impl Palette {
+    fn all(gpu: &Gpu) -> Vec<Palette> {
+        let mut palettes = vec![
+            Palette::Grey,
+            Palette::Dmg(0),
+            Palette::Dmg(1),
+            Palette::Dmg(2),
+        ];
+        if gpu.gb_mode == GameBoyMode::Cgb {
+            palettes.extend((0..8).map(Palette::Background));
+            palettes.extend((0..8).map(Palette::Object));
+        }
+        palettes
+    }
+
+    fn name(&self) -> String {
+        match *self {
+            Palette::Grey => "GREY".to_string(),
+            Palette::Dmg(0) => "BGP".to_string(),
+            Palette::Dmg(index) => format!("OBP{}", index - 1),
+            Palette::Background(index) => format!("BG{}", index),
+            Palette::Object(index) => format!("OBJ{}", index),
+        }
+    }
+
+    fn colors(&self, gpu: &Gpu) -> [Color; 4] {
+        match *self {
+            Palette::Grey => GREY,
+            Palette::Dmg(index) => gpu.dmg_colors(index).map(|pixel| pixel.rgb()),
+            Palette::Background(index) => gpu.cgb_colors(false, index).map(|pixel| pixel.rgb()),
+            Palette::Object(index) => gpu.cgb_colors(true, index).map(|pixel| pixel.rgb()),
+        }
+    }
+}
+
+// Tile data, background maps and OAM
+pub struct VramView {
+    page: Page,
+    palette: Palette,
+}
+
+impl VramView {
+    pub fn new() -> VramView {
+        VramView {
+            page: Page::Tiles,
+            palette: Palette::Dmg(0),
+        }
+    }
+
+    fn draw_tiles(&self, gpu: &Gpu, surface: &mut Surface, mouse: Option<(usize, usize)>) {
+        let colors = self.palette.colors(gpu);
+        let mut hovered = None;
+
+        for (bank, vram) in [&gpu.vram, &gpu.vram1].into_iter().enumerate() {
+            let x = BANK_X[bank];
+            surface.text(x, TOP - 8, &format!("BANK {}", bank), TEXT);
+            for tile in 0..TILES_PER_BANK {
+                let tile_x = x + tile % TILES_PER_ROW * 8;
+                let tile_y = TOP + tile / TILES_PER_ROW * 8;
+                draw_tile(surface, vram, tile, (tile_x, tile_y), 1, &colors, 0);
+            }
+
+            if let Some((mouse_x, mouse_y)) = mouse {
+                let column = mouse_x.wrapping_sub(x) / 8;
+                let tile = (mouse_y.wrapping_sub(TOP) / 8).saturating_mul(TILES_PER_ROW) + column;
+                if column < TILES_PER_ROW && tile < TILES_PER_BANK {
+                    hovered = Some((bank, tile));
+                }
+            }
+        }
+
+        surface.text(PICKER_X, TOP - 8, "PALETTE (P)", TEXT);
+        for (index, palette) in Palette::all(gpu).into_iter().enumerate() {
+            let y = TOP + index * PICKER_ROW;
+            let color = if palette == self.palette {
+                HIGHLIGHT
+            } else {
+                DIM
+            };
+            surface.text(PICKER_X, y + 1, &palette.name(), color);
+            for (shade, rgb) in palette.colors(gpu).iter().enumerate() {
+                surface.fill(PICKER_X + 20 + shade * 8, y, 8, 7, *rgb);
+            }
+        }
+
+        let Some((bank, tile)) = hovered else {
+            surface.status("HOVER A TILE FOR DETAILS");
+            return;
+        };
+        let vram = if bank == 0 { &gpu.vram } else { &gpu.vram1 };
+        surface.outline(ZOOM_X - 1, TOP - 1, 8 * ZOOM + 2, 8 * ZOOM + 2, PANEL);
+        draw_tile(surface, vram, tile, (ZOOM_X, TOP), ZOOM, &colors, 0);
+        surface.outline(
+            BANK_X[bank] + tile % TILES_PER_ROW * 8,
+            TOP + tile / TILES_PER_ROW * 8,
+            8,
+            8,
+            HIGHLIGHT,
+        );
+
+        // Tiles 0x100-0x17F are the ones numbered 0x00-0x7F when LCDC.4 is clear
+        surface.status(&format!(
+            "TILE {:03X} BANK {} ADDRESS {:04X} (NUMBER {:02X})",
+            tile,
+            bank,
+            0x8000 + tile * 16,
+            tile & 0xFF
+        ));
+    }
+
+    fn draw_maps(&self, gpu: &Gpu, surface: &mut Surface, mouse: Option<(usize, usize)>) {
+        let cgb = gpu.gb_mode == GameBoyMode::Cgb;
+        let dmg_colors = gpu.dmg_colors(0).map(|pixel| pixel.rgb());
+        let mut status = "HOVER A TILE FOR DETAILS".to_string();
+
+        for (map, x) in MAP_X.into_iter().enumerate() {
+            let mut label = format!("{:04X}", MAP_ADDRESS[map]);
+            if gpu.lcdc.bg_tile_map as usize == map {
+                label.push_str(" BG");
+            }
+            if gpu.lcdc.window_tile_map as usize == map {
+                label.push_str(" WINDOW");
+            }
+            surface.text(x, TOP - 8, &label, TEXT);
+
+            let attributes = gpu.bg_map_attributes(map);
+            for (index, &attribute) in attributes.iter().enumerate() {
+                let address = MAP_ADDRESS[map] as usize + index;
+                let number = gpu.vram[address - 0x8000];
+                let tile = tile_index(gpu, number);
+                let (tile_x, tile_y) = (x + index % 32 * 8, TOP + index / 32 * 8);
+
+                if cgb {
+                    let colors = gpu
+                        .cgb_colors(false, (attribute & 0x07) as usize)
+                        .map(|pixel| pixel.rgb());
+                    let vram = if attribute & 0x08 != 0 {
+                        &gpu.vram1
+                    } else {
+                        &gpu.vram
+                    };
+                    draw_tile(surface, vram, tile, (tile_x, tile_y), 1, &colors, attribute);
+                } else {
+                    draw_tile(
+                        surface,
+                        &gpu.vram,
+                        tile,
+                        (tile_x, tile_y),
+                        1,
+                        &dmg_colors,
+                        0,
+                    );
+                }
+            }
+
+            // The visible 160x144 area wraps around the 256x256 map
+            if gpu.lcdc.bg_tile_map as usize == map {
+                for offset in 0..160 {
+                    let column = x + (gpu.scroll_x as usize + offset) % 256;
+                    surface.pixel(column, TOP + gpu.scroll_y as usize, OVERLAY);
+                    surface.pixel(column, TOP + (gpu.scroll_y as usize + 143) % 256, OVERLAY);
+                }
+                for offset in 0..144 {
+                    let row = TOP + (gpu.scroll_y as usize + offset) % 256;
+                    surface.pixel(x + gpu.scroll_x as usize, row, OVERLAY);
+                    surface.pixel(x + (gpu.scroll_x as usize + 159) % 256, row, OVERLAY);
+                }
+            }
+
+            let Some((mouse_x, mouse_y)) = mouse else {
+                continue;
+            };
+            let (column, row) = (mouse_x.wrapping_sub(x) / 8, mouse_y.wrapping_sub(TOP) / 8);
+            if column >= 32 || row >= 32 {
+                continue;
+            }
+            let index = row * 32 + column;
+            let address = MAP_ADDRESS[map] as usize + index;
+            surface.outline(x + column * 8, TOP + row * 8, 8, 8, HIGHLIGHT);
+            status = format!(
+                "MAP {:04X} X {:02} Y {:02} ADDRESS {:04X} TILE {:02X}",
+                MAP_ADDRESS[map],
+                column,
+                row,
+                address,
+                gpu.vram[address - 0x8000]
+            );
+            if cgb {
+                let attribute = attributes[index];
+                status.push_str(&format!(
+                    " ATTR {:02X} PAL {} BANK {}{}{}{}",
+                    attribute,
+                    attribute & 0x07,
+                    (attribute >> 3) & 1,
+                    if attribute & 0x20 != 0 { " XFLIP" } else { "" },
+                    if attribute & 0x40 != 0 { " YFLIP" } else { "" },
+                    if attribute & 0x80 != 0 {
+                        " PRIORITY"
+                    } else {
+                        ""
+                    },
+                ));
+            }
+        }
+
+        surface.status(&format!(
+            "SCX {:02X} SCY {:02X}  {}",
+            gpu.scroll_x, gpu.scroll_y, status
+        ));
+    }
+
+    fn draw_oam(&self, gpu: &Gpu, surface: &mut Surface, mouse: Option<(usize, usize)>) {
+        let cgb = gpu.gb_mode == GameBoyMode::Cgb;
+        let height = if gpu.lcdc.sprite_size { 16 } else { 8 };
+        let mut status = format!("OBJECTS 8X{}", height);
+
+        for index in 0..40 {
+            let entry = &gpu.oam[index * 4..index * 4 + 4];
+            let (y, x, number, flags) = (entry[0], entry[1], entry[2], entry[3]);
+
+            let left = 8 + index / OAM_ROWS * OAM_COLUMN_WIDTH;
+            let top = TOP + index % OAM_ROWS * OAM_ROW_HEIGHT;
+
+            let colors = if cgb {
+                gpu.cgb_colors(true, (flags & 0x07) as usize)
+            } else {
+                gpu.dmg_colors(1 + ((flags >> 4) & 1) as usize)
+            }
+            .map(|pixel| pixel.rgb());
+            let vram = if cgb && flags & 0x08 != 0 {
+                &gpu.vram1
+            } else {
+                &gpu.vram
+            };
+            let (xflip, yflip) = (flags & 0x20 != 0, flags & 0x40 != 0);
+
+            // Colour 0 is transparent, so the preview sits on a panel
+            surface.fill(left, top, 8, 16, PANEL);
+            if height == 16 {
+                let (first, second) = if yflip {
+                    (number | 1, number & 0xFE)
+                } else {
+                    (number & 0xFE, number | 1)
+                };
+                draw_object_tile(surface, vram, first, (left, top), &colors, flags);
+                draw_object_tile(surface, vram, second, (left, top + 8), &colors, flags);
+            } else {
+                draw_object_tile(surface, vram, number, (left, top), &colors, flags);
+            }
+
+            let visible = (1..160).contains(&y) && (1..168).contains(&x);
+            let color = if visible { TEXT } else { DIM };
+            surface.text(
+                left + 12,
+                top + 1,
+                &format!(
+                    "{:02} X {:4} Y {:4} T {:02X}",
+                    index,
+                    x as i16 - 8,
+                    y as i16 - 16,
+                    number
+                ),
+                color,
+            );
+            let palette = if cgb {
+                format!("PAL {} BANK {}", flags & 0x07, (flags >> 3) & 1)
+            } else {
+                format!("OBP{}", (flags >> 4) & 1)
+            };
+            surface.text(
+                left + 12,
+                top + 1 + font::LINE_HEIGHT,
+                &format!(
+                    "F {:02X} {}{}{}{}",
+                    flags,
+                    palette,
+                    if xflip { " X" } else { "" },
+                    if yflip { " Y" } else { "" },
+                    if flags & 0x80 != 0 { " BEHIND" } else { "" },
+                ),
+                color,
+            );
+
+            if let Some((mouse_x, mouse_y)) = mouse {
+                if (left..left + OAM_COLUMN_WIDTH).contains(&mouse_x)
+                    && (top..top + OAM_ROW_HEIGHT).contains(&mouse_y)
+                {
+                    surface.outline(
+                        left - 2,
+                        top - 1,
+                        OAM_COLUMN_WIDTH - 4,
+                        OAM_ROW_HEIGHT,
+                        HIGHLIGHT,
+                    );
+                    status = format!(
+                        "OBJECT {:02} AT {:04X}: {:02X} {:02X} {:02X} {:02X}",
+                        index,
+                        0xFE00 + index * 4,
+                        y,
+                        x,
+                        number,
+                        flags
+                    );
+                }
+            }
+        }
+
+        surface.status(&status);
+    }
+
+    fn cycle_palette(&mut self, gpu: &Gpu) {
+        let palettes = Palette::all(gpu);
+        let index = palettes
+            .iter()
+            .position(|&palette| palette == self.palette)
+            .map_or(0, |index| (index + 1) % palettes.len());
+        self.palette = palettes[index];
+    }
+}
+
+impl View for VramView {
+    fn title(&self) -> &'static str {
+        "VRAM Viewer"
+    }
+
+    fn size(&self) -> (usize, usize) {
+        (WIDTH, HEIGHT)
+    }
+
+    fn draw(&mut self, cpu: &Cpu, surface: &mut Surface, mouse: Option<(usize, usize)>) {
+        let gpu = &cpu.mem.gpu;
+
+        let mut x = 8;
+        for (page, name) in PAGES {
+            let color = if page == self.page { HIGHLIGHT } else { DIM };
+            surface.text(x, 4, name, color);
+            x += font::text_width(name) + 12;
+        }
+
+        match self.page {
+            Page::Tiles => self.draw_tiles(gpu, surface, mouse),
+            Page::Maps => self.draw_maps(gpu, surface, mouse),
+            Page::Oam => self.draw_oam(gpu, surface, mouse),
+        }
+    }
+
+    fn key_down(&mut self, cpu: &mut Cpu, keycode: Keycode) {
+        match keycode {
+            Keycode::Num1 => self.page = Page::Tiles,
+            Keycode::Num2 => self.page = Page::Maps,
+            Keycode::Num3 => self.page = Page::Oam,
+            Keycode::Tab => {
+                let index = PAGES
+                    .iter()
+                    .position(|(page, _)| *page == self.page)
+                    .unwrap();
+                self.page = PAGES[(index + 1) % PAGES.len()].0;
+            }
+            Keycode::P => self.cycle_palette(&cpu.mem.gpu),
+            _ => {}
+        }
+    }
+
+    fn click(&mut self, cpu: &mut Cpu, x: usize, y: usize) {
+        // Page names along the top
+        if y < TOP - 8 {
+            let mut left = 8;
+            for (page, name) in PAGES {
+                let width = font::text_width(name);
+                if (left..left + width).contains(&x) {
+                    self.page = page;
+                }
+                left += width + 12;
+            }
+            return;
+        }
+
+        if self.page == Page::Tiles && (PICKER_X..ZOOM_X - 8).contains(&x) && y >= TOP {
+            if let Some(&palette) = Palette::all(&cpu.mem.gpu).get((y - TOP) / PICKER_ROW) {
+                self.palette = palette;
+            }
+        }
+    }
+}
+
+// Index into the 384 tiles of a bank for a tile number, following LCDC.4
+fn tile_index(gpu: &Gpu, number: u8) -> usize {
+    if gpu.lcdc.bg_window_tile_data {
+        number as usize
+    } else {
+        (256 + number as i8 as isize) as usize
+    }
+}
+
+// Flips follow the flag bits of CGB map attributes and OAM
+fn draw_tile(
+    surface: &mut Surface,
+    vram: &[u8],
+    tile: usize,
+    (x, y): (usize, usize),
+    scale: usize,
+    colors: &[Color; 4],
+    flags: u8,
+) {
+    let (xflip, yflip) = (flags & 0x20 != 0, flags & 0x40 != 0);
+    for row in 0..8 {
+        let source_row = if yflip { 7 - row } else { row };
+        let low = vram[tile * 16 + source_row * 2];
+        let high = vram[tile * 16 + source_row * 2 + 1];
+        for column in 0..8 {
+            let bit = if xflip { column } else { 7 - column };
+            let color = ((low >> bit) & 1) | (((high >> bit) & 1) << 1);
+            surface.fill(
+                x + column * scale,
+                y + row * scale,
+                scale,
+                scale,
+                colors[color as usize],
+            );
+        }
+    }
+}
+
+// Objects always use the 0x8000 tile data and leave colour 0 transparent
+fn draw_object_tile(
+    surface: &mut Surface,
+    vram: &[u8],
+    number: u8,
+    (x, y): (usize, usize),
+    colors: &[Color; 4],
+    flags: u8,
+) {
+    let (xflip, yflip) = (flags & 0x20 != 0, flags & 0x40 != 0);
+    let tile = number as usize;
+    for row in 0..8 {
+        let source_row = if yflip { 7 - row } else { row };
+        let low = vram[tile * 16 + source_row * 2];
+        let high = vram[tile * 16 + source_row * 2 + 1];
+        for column in 0..8 {
+            let bit = if xflip { column } else { 7 - column };
+            let color = ((low >> bit) & 1) | (((high >> bit) & 1) << 1);
+            if color != 0 {
+                surface.pixel(x + column, y + row, colors[color as usize]);
+            }
+        }
+    }
+}
@@ -0,0 +1,814 @@
+pub mod watchpoints;
+
+use std::fmt;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::cpu::Cpu;
+use crate::symbols::Symbols;
+use crate::tracer::{self, Tracer, Trigger};
+
+use watchpoints::{Access, Watchpoint};
+
+const HISTORY_LENGTH: usize = 8;
+const CALL_STACK_LIMIT: usize = 256;
+const INTERRUPT_VECTORS: [u16; 5] = [0x40, 0x48, 0x50, 0x58, 0x60];
+
+const HELP: &str = "\
+Commands (addresses and values are hex or symbol names, counts are decimal):
+  c, continue                  resume emulation
+  s, step [count]              execute instructions, entering calls
+  n, next                      step over CALL and RST
+  finish, out                  run until the current function returns
+  v, vblank                    run until the next VBlank
+  b, break [bank:]addr [if <reg><op><value>]
+                               add a breakpoint, e.g. `b 1:4000 if a==3` or `b Main.loop`
+  watch r|w|rw|x addr[-end]    add a watchpoint on reads, writes or execution
+  d, delete <n>                remove breakpoint n
+  unwatch <n>                  remove watchpoint n
+  l, list                      list breakpoints and watchpoints
+  r, regs                      print registers and flags
+  bt, backtrace                print the call stack
+  set <reg> <value>            change a register
+  dis, disasm [bank:addr] [count]
+                               disassemble, around PC by default
+  x <addr> [length]            dump memory
+  trace                        show the trace settings
+  trace on [file] | ring <n> | off | dump [file]
+                               log in Gameboy Doctor format to a file, or keep
+                               the last n instructions (written out on a crash)
+  trace start|stop addr[-end] | frame <n> | none
+                               start or stop tracing when PC or the frame reaches it
+  trace bank <n> | any         only trace code running in this ROM bank
+  cheat                        list cheats
+  cheat add <code> [name]      add a GameShark (01VVAAAA) or Game Genie (VVA-AAA-CCC) code
+  cheat on|off|del <n>         enable, disable or remove cheat n
+  q, quit                      exit the emulator
+An empty line repeats the last command.";
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Register {
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+    H,
+    L,
+    AF,
+    BC,
+    DE,
+    HL,
+    SP,
+    PC,
+}
+
+impl Register {
+    fn parse(text: &str) -> Option<Register> {
+        match text.to_lowercase().as_str() {
+            "a" => Some(Register::A),
+            "b" => Some(Register::B),
+            "c" => Some(Register::C),
+            "d" => Some(Register::D),
+            "e" => Some(Register::E),
+            "f" => Some(Register::F),
+            "h" => Some(Register::H),
+            "l" => Some(Register::L),
+            "af" => Some(Register::AF),
+            "bc" => Some(Register::BC),
+            "de" => Some(Register::DE),
+            "hl" => Some(Register::HL),
+            "sp" => Some(Register::SP),
+            "pc" => Some(Register::PC),
+            _ => None,
+        }
+    }
+
+    fn read(&self, cpu: &Cpu) -> u16 {
+        let registers = cpu.registers();
+        match self {
+            Register::A => registers.a as u16,
+            Register::B => registers.b as u16,
+            Register::C => registers.c as u16,
+            Register::D => registers.d as u16,
+            Register::E => registers.e as u16,
+            Register::F => registers.get_af() & 0xFF,
+            Register::H => registers.h as u16,
+            Register::L => registers.l as u16,
+            Register::AF => registers.get_af(),
+            Register::BC => registers.get_bc(),
+            Register::DE => registers.get_de(),
+            Register::HL => registers.get_hl(),
+            Register::SP => cpu.sp(),
+            Register::PC => cpu.pc(),
+        }
+    }
+
+    fn write(&self, cpu: &mut Cpu, value: u16) {
+        let registers = cpu.registers_mut();
+        match self {
+            Register::A => registers.a = value as u8,
+            Register::B => registers.b = value as u8,
+            Register::C => registers.c = value as u8,
+            Register::D => registers.d = value as u8,
+            Register::E => registers.e = value as u8,
+            Register::F => registers.set_af((registers.a as u16) << 8 | (value & 0xF0)),
+            Register::H => registers.h = value as u8,
+            Register::L => registers.l = value as u8,
+            Register::AF => registers.set_af(value),
+            Register::BC => registers.set_bc(value),
+            Register::DE => registers.set_de(value),
+            Register::HL => registers.set_hl(value),
+            Register::SP => cpu.set_sp(value),
+            Register::PC => cpu.set_pc(value),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    LessEqual,
+    GreaterEqual,
+    Less,
+    Greater,
+}
+
+// Two character operators first so "<=" isn't read as "<"
+const COMPARISONS: [(&str, Comparison); 6] = [
+    ("==", Comparison::Equal),
+    ("!=", Comparison::NotEqual),
+    ("<=", Comparison::LessEqual),
+    (">=", Comparison::GreaterEqual),
+    ("<", Comparison::Less),
+    (">", Comparison::Greater),
+];
+
+struct Condition {
+    register: Register,
+    comparison: Comparison,
+    value: u16,
+}
+
+impl Condition {
+    fn parse(text: &str) -> Option<Condition> {
+        let (operator, comparison) = COMPARISONS
+            .iter()
+            .find(|(operator, _)| text.contains(operator))?;
+        let (register, value) = text.split_once(operator)?;
+        Some(Condition {
+            register: Register::parse(register.trim())?,
+            comparison: *comparison,
+            value: parse_number(value.trim())?,
+        })
+    }
+
+    fn matches(&self, cpu: &Cpu) -> bool {
+        let register = self.register.read(cpu);
+        match self.comparison {
+            Comparison::Equal => register == self.value,
+            Comparison::NotEqual => register != self.value,
+            Comparison::LessEqual => register <= self.value,
+            Comparison::GreaterEqual => register >= self.value,
+            Comparison::Less => register < self.value,
+            Comparison::Greater => register > self.value,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (operator, _) = COMPARISONS
+            .iter()
+            .find(|(_, comparison)| *comparison == self.comparison)
+            .unwrap();
+        write!(f, "{:?} {} {:X}", self.register, operator, self.value)
+    }
+}
+
+struct Breakpoint {
+    address: u16,
+    bank: Option<u16>,
+    condition: Option<Condition>,
+}
+
+impl Breakpoint {
+    fn matches(&self, cpu: &Cpu) -> bool {
+        self.address == cpu.pc()
+            && (self.bank.is_none() || self.bank == cpu.mem.rom_bank_at(self.address))
+            && self
+                .condition
+                .as_ref()
+                .is_none_or(|condition| condition.matches(cpu))
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.bank {
+            Some(bank) => write!(f, "{:02X}:{:04X}", bank, self.address)?,
+            None => write!(f, "{:04X}", self.address)?,
+        }
+        if let Some(condition) = &self.condition {
+            write!(f, " if {}", condition)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum RunMode {
+    Continue,
+    // Stop when the call returns to this address with the stack unwound
+    StepOver { address: u16, sp: u16 },
+    // Stop after a return pops the stack above this pointer
+    StepOut { sp: u16 },
+    UntilFrame { frame: u64 },
+}
+
+struct Frame {
+    call_site: u16,
+    bank: u16,
+    // Stack pointer just after the return address was pushed
+    sp: u16,
+}
+
+pub enum Action {
+    Resume,
+    Quit,
+}
+
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    mode: RunMode,
+    paused: bool,
+    // Breakpoints at the PC we resumed from are ignored until it changes
+    resume_pc: Option<u16>,
+    history: [u16; HISTORY_LENGTH],
+    history_index: usize,
+    last_opcode: u8,
+    last_pc: u16,
+    last_sp: u16,
+    call_stack: Vec<Frame>,
+    last_command: String,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger {
+            breakpoints: vec![],
+            mode: RunMode::Continue,
+            paused: false,
+            resume_pc: None,
+            history: [0; HISTORY_LENGTH],
+            history_index: 0,
+            last_opcode: 0,
+            last_pc: 0,
+            last_sp: 0,
+            call_stack: vec![],
+            last_command: String::new(),
+        }
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    // Called before every instruction, returns true when the prompt should be shown
+    pub fn check(&mut self, cpu: &Cpu) -> bool {
+        let pc = cpu.pc();
+        let returned = matches!(self.last_opcode, 0xC0 | 0xC8 | 0xC9 | 0xD0 | 0xD8 | 0xD9);
+        self.track_calls(cpu);
+        self.last_opcode = cpu.mem.peek_byte(pc);
+        if self.history[self.history_index] != pc {
+            self.history_index = (self.history_index + 1) % HISTORY_LENGTH;
+            self.history[self.history_index] = pc;
+        }
+
+        if self.paused {
+            return true;
+        }
+        if self.mode == RunMode::Continue
+            && self.breakpoints.is_empty()
+            && cpu.mem.watchpoints.is_empty()
+        {
+            return false;
+        }
+
+        if let Some(hit) = cpu.mem.watchpoints.take_hit() {
+            let access = if hit.write { "Write" } else { "Read" };
+            println!(
+                "{} watchpoint: [{:04X}] {} {:02X}",
+                access,
+                hit.address,
+                if hit.write { "<-" } else { "=" },
+                hit.value
+            );
+            return self.stop();
+        }
+
+        if self.resume_pc == Some(pc) {
+            // Still on the instruction we resumed from, e.g. while halted
+        } else {
+            self.resume_pc = None;
+            if let Some(index) = self.breakpoints.iter().position(|b| b.matches(cpu)) {
+                println!(
+                    "Breakpoint {}: {}{}",
+                    index,
+                    self.breakpoints[index],
+                    symbol_suffix(cpu, cpu.mem.rom_bank_at(pc), pc)
+                );
+                return self.stop();
+            }
+            if cpu.mem.watchpoints.executes(pc) {
+                println!("Execute watchpoint: {:04X}", pc);
+                return self.stop();
+            }
+        }
+
+        let done = match self.mode {
+            RunMode::Continue => false,
+            RunMode::StepOver { address, sp } => pc == address && cpu.sp() >= sp,
+            RunMode::StepOut { sp } => returned && cpu.sp() > sp,
+            RunMode::UntilFrame { frame } => cpu.mem.gpu.frames >= frame,
+        };
+        if done {
+            return self.stop();
+        }
+        false
+    }
+
+    // Keeps a shadow call stack from CALL, RST and interrupts, unwound when SP moves above a frame
+    fn track_calls(&mut self, cpu: &Cpu) {
+        let pc = cpu.pc();
+        let sp = cpu.sp();
+
+        while self.call_stack.last().is_some_and(|frame| frame.sp < sp) {
+            self.call_stack.pop();
+        }
+
+        let called = is_call(self.last_opcode) && sp == self.last_sp.wrapping_sub(2);
+        let interrupted = !called && pc != self.last_pc && INTERRUPT_VECTORS.contains(&pc);
+        if called || interrupted {
+            if self.call_stack.len() == CALL_STACK_LIMIT {
+                self.call_stack.remove(0);
+            }
+            self.call_stack.push(Frame {
+                call_site: self.last_pc,
+                bank: cpu.mem.bank_at(self.last_pc),
+                sp,
+            });
+        }
+
+        self.last_pc = pc;
+        self.last_sp = sp;
+    }
+
+    fn stop(&mut self) -> bool {
+        self.mode = RunMode::Continue;
+        self.paused = true;
+        true
+    }
+
+    fn resume(&mut self, cpu: &Cpu, mode: RunMode) -> Action {
+        self.mode = mode;
+        self.paused = false;
+        self.resume_pc = Some(cpu.pc());
+        Action::Resume
+    }
+
+    // Blocks on stdin until the user resumes or quits
+    pub fn prompt(&mut self, cpu: &mut Cpu, tracer: &mut Tracer) -> Action {
+        print_registers(cpu);
+        self.print_disassembly(cpu, None, 4);
+
+        loop {
+            print!("(gbdb) ");
+            io::stdout().flush().unwrap();
+
+            let mut line = String::new();
+            if io::stdin().read_line(&mut line).unwrap_or(0) == 0 {
+                // stdin closed, nothing more can be typed
+                return self.resume(cpu, RunMode::Continue);
+            }
+            let line = match line.trim() {
+                "" => self.last_command.clone(),
+                line => line.to_string(),
+            };
+            self.last_command = line.clone();
+
+            let mut words = line.split_whitespace();
+            let Some(command) = words.next() else {
+                continue;
+            };
+            let args: Vec<&str> = words.collect();
+
+            match command {
+                "h" | "help" | "?" => println!("{}", HELP),
+                "c" | "continue" => return self.resume(cpu, RunMode::Continue),
+                "s" | "step" => {
+                    let count = args.first().and_then(|n| n.parse().ok()).unwrap_or(1);
+                    self.step(cpu, count);
+                }
+                "n" | "next" => {
+                    let pc = cpu.pc();
+                    if is_call(cpu.mem.peek_byte(pc)) {
+                        let address = pc.wrapping_add(cpu.mem.disassemble(pc).length());
+                        let sp = cpu.sp();
+                        return self.resume(cpu, RunMode::StepOver { address, sp });
+                    }
+                    self.step(cpu, 1);
+                }
+                "finish" | "out" => {
+                    let sp = cpu.sp();
+                    return self.resume(cpu, RunMode::StepOut { sp });
+                }
+                "v" | "vblank" => {
+                    let frame = cpu.mem.gpu.frames + 1;
+                    return self.resume(cpu, RunMode::UntilFrame { frame });
+                }
+                "b" | "break" => self.add_breakpoint(cpu, &args),
+                "watch" => self.add_watchpoint(cpu, &args),
+                "d" | "delete" => match args.first().and_then(|n| n.parse::<usize>().ok()) {
+                    Some(index) if index < self.breakpoints.len() => {
+                        println!("Deleted breakpoint {}", self.breakpoints.remove(index));
+                    }
+                    _ => println!("No such breakpoint"),
+                },
+                "unwatch" => match args
+                    .first()
+                    .and_then(|n| n.parse().ok())
+                    .and_then(|index| cpu.mem.watchpoints.remove(index))
+                {
+                    Some(watchpoint) => println!("Deleted watchpoint {}", watchpoint),
+                    None => println!("No such watchpoint"),
+                },
+                "l" | "list" => {
+                    for (index, breakpoint) in self.breakpoints.iter().enumerate() {
+                        println!("Breakpoint {}: {}", index, breakpoint);
+                    }
+                    for (index, watchpoint) in cpu.mem.watchpoints.list().iter().enumerate() {
+                        println!("Watchpoint {}: {}", index, watchpoint);
+                    }
+                }
+                "r" | "regs" => print_registers(cpu),
+                "bt" | "backtrace" => {
+                    let pc = cpu.pc();
+                    print_frame(cpu, 0, cpu.mem.bank_at(pc), pc);
+                    for (depth, frame) in self.call_stack.iter().rev().enumerate() {
+                        print_frame(cpu, depth + 1, frame.bank, frame.call_site);
+                    }
+                }
+                "set" => match (
+                    args.first().and_then(|r| Register::parse(r)),
+                    args.get(1).and_then(|v| parse_address(v, &cpu.mem.symbols)),
+                ) {
+                    (Some(register), Some(value)) => {
+                        register.write(cpu, value);
+                        print_registers(cpu);
+                    }
+                    _ => println!("Usage: set <reg> <value>"),
+                },
+                "dis" | "disasm" => {
+                    let location = args
+                        .first()
+                        .and_then(|a| parse_location(a, &cpu.mem.symbols));
+                    let count = args.get(1).and_then(|n| n.parse().ok()).unwrap_or(10);
+                    self.print_disassembly(cpu, location, count);
+                }
+                "x" => match args
+                    .first()
+                    .and_then(|a| parse_address(a, &cpu.mem.symbols))
+                {
+                    Some(address) => {
+                        let length = args.get(1).and_then(|n| n.parse().ok()).unwrap_or(0x40);
+                        print_memory(cpu, address, length);
+                    }
+                    None => println!("Usage: x <addr> [length]"),
+                },
+                "trace" => configure_tracer(cpu, tracer, &args),
+                "cheat" => configure_cheats(cpu, &args),
+                "q" | "quit" => return Action::Quit,
+                _ => println!("Unknown command `{}`, type `help`", command),
+            }
+        }
+    }
+
+    // Stops early at a breakpoint or watchpoint, but not one on the instruction it starts from
+    fn step(&mut self, cpu: &mut Cpu, count: usize) {
+        self.paused = false;
+        self.resume_pc = Some(cpu.pc());
+        for _ in 0..count {
+            cpu.step();
+            if self.check(cpu) {
+                break;
+            }
+        }
+        self.paused = true;
+        print_registers(cpu);
+        print_instruction(cpu, None, cpu.pc());
+    }
+
+    fn add_breakpoint(&mut self, cpu: &Cpu, args: &[&str]) {
+        let Some(location) = args.first() else {
+            println!("Usage: break [bank:]addr [if <reg><op><value>]");
+            return;
+        };
+
+        let Some((bank, address)) = parse_location(location, &cpu.mem.symbols) else {
+            println!("Invalid address `{}`", location);
+            return;
+        };
+        // Only 4000-7FFF switches banks, bank 0 is always below it
+        if let Some(bank) = bank {
+            let mapped = match address {
+                0x0000..=0x3FFF => bank == 0,
+                0x4000..=0x7FFF => true,
+                _ => false,
+            };
+            if !mapped {
+                println!(
+                    "Bank {:02X} is never mapped at {:04X}, only 4000-7FFF switches banks",
+                    bank, address
+                );
+                return;
+            }
+        }
+
+        let condition = match args.get(1) {
+            Some(&"if") => match Condition::parse(&args[2..].concat()) {
+                Some(condition) => Some(condition),
+                None => {
+                    println!("Invalid condition, e.g. `if a==3` or `if hl>=c000`");
+                    return;
+                }
+            },
+            _ => None,
+        };
+
+        let breakpoint = Breakpoint {
+            address,
+            bank,
+            condition,
+        };
+        println!(
+            "Breakpoint {}: {}{}",
+            self.breakpoints.len(),
+            breakpoint,
+            symbol_suffix(cpu, bank, address)
+        );
+        self.breakpoints.push(breakpoint);
+    }
+
+    fn add_watchpoint(&mut self, cpu: &mut Cpu, args: &[&str]) {
+        let access = args.first().and_then(|a| Access::parse(a));
+        let range = args
+            .get(1)
+            .and_then(|range| parse_range(range, &cpu.mem.symbols));
+
+        match (access, range) {
+            (Some(access), Some((start, end))) if start <= end => {
+                let watchpoint = Watchpoint { start, end, access };
+                println!(
+                    "Watchpoint {}: {}",
+                    cpu.mem.watchpoints.list().len(),
+                    watchpoint
+                );
+                cpu.mem.watchpoints.add(watchpoint);
+            }
+            _ => println!("Usage: watch r|w|rw|x addr[-end]"),
+        }
+    }
+
+    // Without an address, shows the last executed instructions before PC
+    fn print_disassembly(&self, cpu: &Cpu, location: Option<(Option<u16>, u16)>, count: usize) {
+        let (bank, mut address) = match location {
+            Some(location) => location,
+            None => {
+                for back in (1..4).rev() {
+                    let index = (self.history_index + HISTORY_LENGTH - back) % HISTORY_LENGTH;
+                    let previous = self.history[index];
+                    if previous != cpu.pc() {
+                        print_instruction(cpu, None, previous);
+                    }
+                }
+                (None, cpu.pc())
+            }
+        };
+        for _ in 0..count {
+            address = address.wrapping_add(print_instruction(cpu, bank, address));
+        }
+    }
+}
+
+fn print_registers(cpu: &Cpu) {
+    let registers = cpu.registers();
+    let flags = registers.f;
+    println!(
+        "AF={:04X} BC={:04X} DE={:04X} HL={:04X} SP={:04X} PC={:04X} [{}{}{}{}] IME={} HALT={} STOP={} LY={:02X} frame={}",
+        registers.get_af(),
+        registers.get_bc(),
+        registers.get_de(),
+        registers.get_hl(),
+        cpu.sp(),
+        cpu.pc(),
+        if flags.z { 'Z' } else { '-' },
+        if flags.n { 'N' } else { '-' },
+        if flags.h { 'H' } else { '-' },
+        if flags.c { 'C' } else { '-' },
+        cpu.ime() as u8,
+        cpu.is_halted() as u8,
+        cpu.is_stopped() as u8,
+        cpu.mem.gpu.line,
+        cpu.mem.gpu.frames,
+    );
+}
+
+// Prints one line of disassembly, from `bank` if given, and returns its length
+fn print_instruction(cpu: &Cpu, bank: Option<u16>, address: u16) -> u16 {
+    let (disassembly, bank) = match bank {
+        Some(bank) if (0x4000..0x8000).contains(&address) => {
+            (cpu.mem.disassemble_bank(bank, address), Some(bank))
+        }
+        _ => (cpu.mem.disassemble(address), cpu.mem.rom_bank_at(address)),
+    };
+    let label_bank = bank.unwrap_or_else(|| cpu.mem.bank_at(address));
+    if let Some(label) = cpu.mem.symbols.label(label_bank, address) {
+        println!("{}:", label);
+    }
+    let bytes: Vec<String> = disassembly
+        .bytes
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect();
+    let bank = match bank {
+        Some(bank) => format!("{:02X}:", bank),
+        None => "   ".to_string(),
+    };
+    println!(
+        "{} {}{:04X}  {:<9} {:<20} ; {}",
+        if address == cpu.pc() { '>' } else { ' ' },
+        bank,
+        address,
+        bytes.join(" "),
+        disassembly.text,
+        disassembly.cycles_text()
+    );
+    disassembly.length()
+}
+
+fn print_memory(cpu: &Cpu, address: u16, length: u16) {
+    for row in (0..length).step_by(16) {
+        let start = address.wrapping_add(row);
+        let bytes: Vec<u8> = (0..16.min(length - row))
+            .map(|offset| cpu.mem.peek_byte(start.wrapping_add(offset)))
+            .collect();
+        let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        let text: String = bytes
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() {
+                    byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        println!("{:04X}  {:<47}  {}", start, hex.join(" "), text);
+    }
+}
+
+// [bank:]addr or a symbol, which also gives the bank when it's in ROM
+fn parse_location(text: &str, symbols: &Symbols) -> Option<(Option<u16>, u16)> {
+    if let Some((bank, address)) = symbols.address(text) {
+        return Some(((address < 0x8000).then_some(bank), address));
+    }
+    match text.split_once(':') {
+        Some((bank, address)) => Some((Some(parse_number(bank)?), parse_number(address)?)),
+        None => Some((None, parse_number(text)?)),
+    }
+}
+
+fn parse_address(text: &str, symbols: &Symbols) -> Option<u16> {
+    match symbols.address(text) {
+        Some((_, address)) => Some(address),
+        None => parse_number(text),
+    }
+}
+
+// addr[-end]
+fn parse_range(text: &str, symbols: &Symbols) -> Option<(u16, u16)> {
+    match text.split_once('-') {
+        Some((start, end)) => Some((parse_address(start, symbols)?, parse_address(end, symbols)?)),
+        None => parse_address(text, symbols).map(|address| (address, address)),
+    }
+}
+
+fn is_call(opcode: u8) -> bool {
+    matches!(
+        opcode,
+        0xC4 | 0xCC | 0xCD | 0xD4 | 0xDC | 0xC7 | 0xCF | 0xD7 | 0xDF | 0xE7 | 0xEF | 0xF7 | 0xFF
+    )
+}
+
+fn configure_tracer(cpu: &Cpu, tracer: &mut Tracer, args: &[&str]) {
+    let path = Path::new(args.get(1).copied().unwrap_or(tracer::DEFAULT_PATH));
+    match args.first().copied() {
+        None => {}
+        Some("on") => tracer.start_file(path),
+        Some("off") => tracer.disable(),
+        Some("dump") => tracer.dump(path, &cpu.mem.symbols),
+        Some("ring") => match args.get(1).and_then(|n| n.parse().ok()) {
+            Some(size) => tracer.start_ring(size),
+            None => println!("Usage: trace ring <n>"),
+        },
+        Some(which @ ("start" | "stop")) => {
+            let trigger = match args.get(1).copied() {
+                Some("none") => None,
+                Some("frame") => match args.get(2).and_then(|n| n.parse().ok()) {
+                    Some(frame) => Some(Trigger::Frame(frame)),
+                    None => return println!("Usage: trace {} frame <n>", which),
+                },
+                Some(range) => match parse_range(range, &cpu.mem.symbols) {
+                    Some((start, end)) => Some(Trigger::Address(start, end)),
+                    None => return println!("Invalid address `{}`", range),
+                },
+                None => return println!("Usage: trace {} addr[-end] | frame <n> | none", which),
+            };
+            if which == "start" {
+                tracer.start = trigger;
+            } else {
+                tracer.stop = trigger;
+            }
+        }
+        Some("bank") => match args.get(1).copied() {
+            Some("any") => tracer.bank = None,
+            Some(bank) => match parse_number(bank) {
+                Some(bank) => tracer.bank = Some(bank),
+                None => return println!("Invalid bank `{}`", bank),
+            },
+            None => return println!("Usage: trace bank <n> | any"),
+        },
+        Some(other) => return println!("Unknown trace option `{}`", other),
+    }
+    println!("{}", tracer.describe());
+}
+
+// Changes are saved to the ROM's .cht file straight away
+fn configure_cheats(cpu: &mut Cpu, args: &[&str]) {
+    let cheats = &mut cpu.mem.cheats;
+    let index = args.get(1).and_then(|n| n.parse::<usize>().ok());
+    match (args.first().copied(), index) {
+        (None, _) => {
+            if cheats.list().is_empty() {
+                println!("No cheats");
+            }
+            for (index, cheat) in cheats.list().iter().enumerate() {
+                println!("Cheat {}: {}", index, cheat);
+            }
+            if !cheats.enabled {
+                println!("All cheats are switched off (F8)");
+            }
+        }
+        (Some("add"), _) => match args.get(1) {
+            Some(code) => match cheats.add(code, &args[2..].join(" ")) {
+                Some(cheat) => println!("Added cheat {}", cheat),
+                None => println!("Invalid code `{}`", code),
+            },
+            None => println!("Usage: cheat add <code> [name]"),
+        },
+        (Some(which @ ("on" | "off")), Some(index)) => {
+            match cheats.set_enabled(index, which == "on") {
+                Some(cheat) => println!("Cheat {}: {}", index, cheat),
+                None => println!("No such cheat"),
+            }
+        }
+        (Some("del"), Some(index)) => match cheats.remove(index) {
+            Some(cheat) => println!("Deleted cheat {}", cheat),
+            None => println!("No such cheat"),
+        },
+        _ => println!("Usage: cheat [add <code> [name] | on|off|del <n>]"),
+    }
+}
+
+// " <Main.loop+3>" when a symbol covers the address
+fn symbol_suffix(cpu: &Cpu, bank: Option<u16>, address: u16) -> String {
+    let bank = bank.unwrap_or_else(|| cpu.mem.bank_at(address));
+    match cpu.mem.symbols.describe(bank, address) {
+        Some(name) => format!(" <{}>", name),
+        None => String::new(),
+    }
+}
+
+fn print_frame(cpu: &Cpu, depth: usize, bank: u16, address: u16) {
+    let name = cpu.mem.symbols.describe(bank, address).unwrap_or_default();
+    println!("#{:<3} {:02X}:{:04X}  {}", depth, bank, address, name);
+}
+
+// Hex, with an optional $ or 0x prefix
+fn parse_number(text: &str) -> Option<u16> {
+    let text = text.trim_start_matches('$').trim_start_matches("0x");
+    u16::from_str_radix(text, 16).ok()
+}
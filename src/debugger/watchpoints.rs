@@ -0,0 +1,141 @@
+use std::cell::Cell;
+use std::fmt;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+    Execute,
+}
+
+impl Access {
+    pub fn parse(text: &str) -> Option<Access> {
+        match text {
+            "r" | "read" => Some(Access::Read),
+            "w" | "write" => Some(Access::Write),
+            "rw" | "access" => Some(Access::ReadWrite),
+            "x" | "exec" | "execute" => Some(Access::Execute),
+            _ => None,
+        }
+    }
+
+    fn reads(&self) -> bool {
+        matches!(self, Access::Read | Access::ReadWrite)
+    }
+
+    fn writes(&self) -> bool {
+        matches!(self, Access::Write | Access::ReadWrite)
+    }
+}
+
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub access: Access,
+}
+
+impl Watchpoint {
+    pub fn contains(&self, address: u16) -> bool {
+        (self.start..=self.end).contains(&address)
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let access = match self.access {
+            Access::Read => "r",
+            Access::Write => "w",
+            Access::ReadWrite => "rw",
+            Access::Execute => "x",
+        };
+        if self.start == self.end {
+            write!(f, "{:<2} {:04X}", access, self.start)
+        } else {
+            write!(f, "{:<2} {:04X}-{:04X}", access, self.start, self.end)
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct WatchHit {
+    pub address: u16,
+    pub value: u8,
+    pub write: bool,
+}
+
+// Reads happen through &Memory, so the first hit is kept in a Cell until the debugger takes it
+pub struct Watchpoints {
+    list: Vec<Watchpoint>,
+    hit: Cell<Option<WatchHit>>,
+}
+
+impl Watchpoints {
+    pub fn new() -> Watchpoints {
+        Watchpoints {
+            list: vec![],
+            hit: Cell::new(None),
+        }
+    }
+
+    pub fn list(&self) -> &[Watchpoint] {
+        &self.list
+    }
+
+    pub fn add(&mut self, watchpoint: Watchpoint) {
+        self.list.push(watchpoint);
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Watchpoint> {
+        (index < self.list.len()).then(|| self.list.remove(index))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn executes(&self, address: u16) -> bool {
+        self.list
+            .iter()
+            .any(|watchpoint| watchpoint.access == Access::Execute && watchpoint.contains(address))
+    }
+
+    #[inline(always)]
+    pub fn check_read(&self, address: u16, value: u8) {
+        if !self.list.is_empty() {
+            self.check(address, value, false);
+        }
+    }
+
+    #[inline(always)]
+    pub fn check_write(&self, address: u16, value: u8) {
+        if !self.list.is_empty() {
+            self.check(address, value, true);
+        }
+    }
+
+    fn check(&self, address: u16, value: u8, write: bool) {
+        if self.hit.get().is_some() {
+            return;
+        }
+        let matched = self.list.iter().any(|watchpoint| {
+            watchpoint.contains(address)
+                && if write {
+                    watchpoint.access.writes()
+                } else {
+                    watchpoint.access.reads()
+                }
+        });
+        if matched {
+            self.hit.set(Some(WatchHit {
+                address,
+                value,
+                write,
+            }));
+        }
+    }
+
+    pub fn take_hit(&self) -> Option<WatchHit> {
+        self.hit.take()
+    }
+}
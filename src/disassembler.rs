@@ -0,0 +1,499 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::symbols::Symbols;
+
+use crate::cpu::instructions::{
+    ADDHLTarget, ArithmeticTarget, BitPosition, IncDecTarget, IndirectTarget, Instruction,
+    JumpCondition, LoadByteSource, LoadByteTarget, LoadType, LoadWordTarget, PrefixTarget,
+    StackTarget,
+};
+
+pub const ROM_BANK_SIZE: usize = 0x4000;
+
+// Opcodes that lock up the CPU, shown as data
+const ILLEGAL_OPCODES: [u8; 11] = [
+    0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD,
+];
+
+pub struct Disassembly {
+    pub bytes: Vec<u8>,
+    // RGBDS syntax, e.g. "ld a, [hl+]"
+    pub text: String,
+    pub cycles: u8,
+    // Cycles when a conditional jump, call or return is taken
+    pub branch_cycles: Option<u8>,
+    // Address the instruction jumps to or accesses, if it's in the operand
+    pub operand: Option<u16>,
+}
+
+impl Disassembly {
+    pub fn length(&self) -> u16 {
+        self.bytes.len() as u16
+    }
+
+    // Replaces the operand address with a label, e.g. "call $4000" -> "call Main"
+    pub fn symbolize(&mut self, label: impl Fn(u16) -> Option<String>) {
+        if let Some(operand) = self.operand {
+            if let Some(name) = label(operand) {
+                self.text = self.text.replace(&format!("${:04x}", operand), &name);
+            }
+        }
+    }
+
+    pub fn cycles_text(&self) -> String {
+        match self.branch_cycles {
+            Some(taken) => format!("{}/{}", taken, self.cycles),
+            None => self.cycles.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+// Decodes the instruction at the address, reading bytes through `read`
+pub fn disassemble(read: impl Fn(u16) -> u8, address: u16) -> Disassembly {
+    let opcode = read(address);
+
+    if ILLEGAL_OPCODES.contains(&opcode) {
+        return Disassembly {
+            bytes: vec![opcode],
+            text: format!("db ${:02x}", opcode),
+            cycles: 4,
+            branch_cycles: None,
+            operand: None,
+        };
+    }
+
+    let instruction = if opcode == 0xCB {
+        Instruction::get_instruction(read(address.wrapping_add(1)), true)
+    } else {
+        Instruction::get_instruction(opcode, false)
+    };
+
+    let bytes: Vec<u8> = (0..instruction.length())
+        .map(|offset| read(address.wrapping_add(offset)))
+        .collect();
+
+    let (text, cycles, branch_cycles) = render(&instruction, address, &bytes);
+    let operand = operand(&instruction, address, &bytes);
+
+    Disassembly {
+        bytes,
+        text,
+        cycles,
+        branch_cycles,
+        operand,
+    }
+}
+
+// Reads through a ROM image, bank 0 at 0x0000-0x3FFF and `bank` at 0x4000-0x7FFF
+pub fn read_rom(rom: &[u8], bank: u16, address: u16) -> u8 {
+    let offset = match address {
+        0x0000..=0x3FFF => address as usize,
+        _ => bank as usize * ROM_BANK_SIZE + (address as usize & 0x3FFF),
+    };
+    rom.get(offset).copied().unwrap_or(0xFF)
+}
+
+// Prints a whole bank as an RGBDS source listing, with labels from the .sym file next to the ROM
+pub fn dump_bank(path: &Path, bank: u16) {
+    let rom = fs::read(path).expect("failed to open rom file");
+    let symbols = Symbols::load(&path.with_extension("sym")).unwrap_or_default();
+    let banks = rom.len().div_ceil(ROM_BANK_SIZE);
+    if bank as usize >= banks {
+        println!("Bank {} out of range, the ROM has {} banks", bank, banks);
+        return;
+    }
+
+    let (start, end) = if bank == 0 {
+        println!("SECTION \"ROM Bank $000\", ROM0[$0000]\n");
+        (0x0000u32, 0x4000u32)
+    } else {
+        println!(
+            "SECTION \"ROM Bank ${:03x}\", ROMX[$4000], BANK[${:x}]\n",
+            bank, bank
+        );
+        (0x4000u32, 0x8000u32)
+    };
+
+    let mut address = start;
+    while address < end {
+        let mut disassembly = disassemble(|a| read_rom(&rom, bank, a), address as u16);
+        disassembly.symbolize(|operand| {
+            let operand_bank = if (0x4000..0x8000).contains(&operand) {
+                bank
+            } else {
+                0
+            };
+            symbols.label(operand_bank, operand).map(str::to_string)
+        });
+        if let Some(label) = symbols.label(bank, address as u16) {
+            println!("{}:", label);
+        }
+        let remaining = (end - address) as usize;
+
+        // An instruction crossing the end of the bank is left as data
+        let (text, bytes) = if disassembly.bytes.len() > remaining {
+            let bytes = &disassembly.bytes[..remaining];
+            let data: Vec<String> = bytes.iter().map(|b| format!("${:02x}", b)).collect();
+            (format!("db {}", data.join(", ")), bytes)
+        } else {
+            (disassembly.text.clone(), &disassembly.bytes[..])
+        };
+
+        let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        println!(
+            "    {:<24} ; ${:04x}: {:<8}  ({})",
+            text,
+            address,
+            hex.join(" "),
+            disassembly.cycles_text()
+        );
+        address += bytes.len() as u32;
+    }
+}
+
+// Immediate values like "ld hl, $0010" are left alone, they aren't always addresses
+fn operand(instruction: &Instruction, address: u16, bytes: &[u8]) -> Option<u16> {
+    let a16 = || u16::from(bytes[2]) << 8 | u16::from(bytes[1]);
+    match instruction {
+        Instruction::Jp(_) | Instruction::Call(_) => Some(a16()),
+        Instruction::Jr(_) => Some(
+            address
+                .wrapping_add(2)
+                .wrapping_add(bytes[1] as i8 as i16 as u16),
+        ),
+        Instruction::Ld(
+            LoadType::IndirectFromA(IndirectTarget::Word)
+            | LoadType::AFromIndirect(IndirectTarget::Word)
+            | LoadType::IndirectFromSP,
+        ) => Some(a16()),
+        Instruction::Ld(LoadType::ByteAddressFromA | LoadType::AFromByteAddress) => {
+            Some(0xFF00 | u16::from(bytes[1]))
+        }
+        _ => None,
+    }
+}
+
+fn render(instruction: &Instruction, address: u16, bytes: &[u8]) -> (String, u8, Option<u8>) {
+    let d8 = || format!("${:02x}", bytes[1]);
+    let a16 = || format!("${:04x}", u16::from(bytes[2]) << 8 | u16::from(bytes[1]));
+    let e8 = || {
+        let offset = bytes[1] as i8;
+        if offset < 0 {
+            format!("-${:02x}", offset.unsigned_abs())
+        } else {
+            format!("+${:02x}", offset)
+        }
+    };
+
+    let arithmetic = |mnemonic: &str, target: &ArithmeticTarget| {
+        let operand = match target {
+            ArithmeticTarget::D8 => d8(),
+            target => arithmetic_target(target).to_string(),
+        };
+        let cycles = match target {
+            ArithmeticTarget::HL | ArithmeticTarget::D8 => 8,
+            _ => 4,
+        };
+        // RGBDS style keeps the accumulator for add, adc and sbc only
+        let text = match mnemonic {
+            "add" | "adc" | "sbc" => format!("{} a, {}", mnemonic, operand),
+            _ => format!("{} {}", mnemonic, operand),
+        };
+        (text, cycles, None)
+    };
+
+    let prefixed = |mnemonic: &str, target: &PrefixTarget, bit: Option<&BitPosition>| {
+        let cycles = match (target, mnemonic) {
+            (PrefixTarget::HL, "bit") => 12,
+            (PrefixTarget::HL, _) => 16,
+            _ => 8,
+        };
+        let text = match bit {
+            Some(bit) => format!(
+                "{} {}, {}",
+                mnemonic,
+                bit_number(bit),
+                prefix_target(target)
+            ),
+            None => format!("{} {}", mnemonic, prefix_target(target)),
+        };
+        (text, cycles, None)
+    };
+
+    let simple = |text: &str, cycles: u8| (text.to_string(), cycles, None);
+
+    match instruction {
+        Instruction::Adc(target) => arithmetic("adc", target),
+        Instruction::Add(target) => arithmetic("add", target),
+        Instruction::And(target) => arithmetic("and", target),
+        Instruction::Cp(target) => arithmetic("cp", target),
+        Instruction::Or(target) => arithmetic("or", target),
+        Instruction::Sbc(target) => arithmetic("sbc", target),
+        Instruction::Sub(target) => arithmetic("sub", target),
+        Instruction::Xor(target) => arithmetic("xor", target),
+
+        Instruction::Inc(target) | Instruction::Dec(target) => {
+            let mnemonic = match instruction {
+                Instruction::Inc(_) => "inc",
+                _ => "dec",
+            };
+            let cycles = match target {
+                IncDecTarget::Hli => 12,
+                IncDecTarget::BC | IncDecTarget::DE | IncDecTarget::HL | IncDecTarget::SP => 8,
+                _ => 4,
+            };
+            (
+                format!("{} {}", mnemonic, inc_dec_target(target)),
+                cycles,
+                None,
+            )
+        }
+
+        Instruction::AddHl(target) => {
+            let register = match target {
+                ADDHLTarget::BC => "bc",
+                ADDHLTarget::DE => "de",
+                ADDHLTarget::HL => "hl",
+                ADDHLTarget::SP => "sp",
+            };
+            (format!("add hl, {}", register), 8, None)
+        }
+
+        Instruction::Bit(target, bit) => prefixed("bit", target, Some(bit)),
+        Instruction::Res(target, bit) => prefixed("res", target, Some(bit)),
+        Instruction::Set(target, bit) => prefixed("set", target, Some(bit)),
+        Instruction::Swap(target) => prefixed("swap", target, None),
+        Instruction::Rl(target) => prefixed("rl", target, None),
+        Instruction::Rlc(target) => prefixed("rlc", target, None),
+        Instruction::Rr(target) => prefixed("rr", target, None),
+        Instruction::Rrc(target) => prefixed("rrc", target, None),
+        Instruction::Sla(target) => prefixed("sla", target, None),
+        Instruction::Sra(target) => prefixed("sra", target, None),
+        Instruction::Srl(target) => prefixed("srl", target, None),
+        Instruction::Rla => simple("rla", 4),
+        Instruction::Rlca => simple("rlca", 4),
+        Instruction::Rra => simple("rra", 4),
+        Instruction::Rrca => simple("rrca", 4),
+
+        Instruction::Ld(load_type) => match load_type {
+            LoadType::Byte(target, source) => {
+                let source_text = match source {
+                    LoadByteSource::D8 => d8(),
+                    source => load_byte_source(source).to_string(),
+                };
+                let cycles = match (target, source) {
+                    (LoadByteTarget::HL, LoadByteSource::D8) => 12,
+                    (LoadByteTarget::HL, _) | (_, LoadByteSource::HL | LoadByteSource::D8) => 8,
+                    _ => 4,
+                };
+                (
+                    format!("ld {}, {}", load_byte_target(target), source_text),
+                    cycles,
+                    None,
+                )
+            }
+            LoadType::Word(target) => {
+                let register = match target {
+                    LoadWordTarget::BC => "bc",
+                    LoadWordTarget::DE => "de",
+                    LoadWordTarget::HL => "hl",
+                    LoadWordTarget::SP => "sp",
+                };
+                (format!("ld {}, {}", register, a16()), 12, None)
+            }
+            LoadType::IndirectFromA(IndirectTarget::LastByte) => simple("ldh [c], a", 8),
+            LoadType::AFromIndirect(IndirectTarget::LastByte) => simple("ldh a, [c]", 8),
+            LoadType::IndirectFromA(IndirectTarget::Word) => {
+                (format!("ld [{}], a", a16()), 16, None)
+            }
+            LoadType::AFromIndirect(IndirectTarget::Word) => {
+                (format!("ld a, [{}]", a16()), 16, None)
+            }
+            LoadType::IndirectFromA(target) => {
+                (format!("ld {}, a", indirect_target(target)), 8, None)
+            }
+            LoadType::AFromIndirect(target) => {
+                (format!("ld a, {}", indirect_target(target)), 8, None)
+            }
+            LoadType::ByteAddressFromA => (format!("ldh [$ff{:02x}], a", bytes[1]), 12, None),
+            LoadType::AFromByteAddress => (format!("ldh a, [$ff{:02x}]", bytes[1]), 12, None),
+            LoadType::IndirectFromSP => (format!("ld [{}], sp", a16()), 20, None),
+            LoadType::SPFromHL => simple("ld sp, hl", 8),
+            LoadType::HLFromSP => (format!("ld hl, sp{}", e8()), 12, None),
+        },
+
+        Instruction::Jp(condition) => branch("jp", condition, a16(), (16, 12)),
+        Instruction::JpHl => simple("jp hl", 4),
+        Instruction::Jr(condition) => {
+            let target = address
+                .wrapping_add(2)
+                .wrapping_add(bytes[1] as i8 as i16 as u16);
+            branch("jr", condition, format!("${:04x}", target), (12, 8))
+        }
+        Instruction::Call(condition) => branch("call", condition, a16(), (24, 12)),
+        Instruction::Ret(JumpCondition::Always) => simple("ret", 16),
+        Instruction::Ret(condition) => (format!("ret {}", jump_condition(condition)), 8, Some(20)),
+        Instruction::Reti => simple("reti", 16),
+        Instruction::Rst(location) => (format!("rst ${:02x}", location.to_hex()), 16, None),
+
+        Instruction::AddSp => {
+            let offset = e8();
+            (
+                format!("add sp, {}", offset.trim_start_matches('+')),
+                16,
+                None,
+            )
+        }
+        Instruction::Pop(target) => (format!("pop {}", stack_target(target)), 12, None),
+        Instruction::Push(target) => (format!("push {}", stack_target(target)), 16, None),
+
+        Instruction::Ccf => simple("ccf", 4),
+        Instruction::Cpl => simple("cpl", 4),
+        Instruction::Daa => simple("daa", 4),
+        Instruction::Di => simple("di", 4),
+        Instruction::Ei => simple("ei", 4),
+        Instruction::Halt => simple("halt", 4),
+        Instruction::Nop => simple("nop", 4),
+        Instruction::Stop => simple("stop", 4),
+        Instruction::Scf => simple("scf", 4),
+    }
+}
+
+// Unconditional branches always take the longer time
+fn branch(
+    mnemonic: &str,
+    condition: &JumpCondition,
+    target: String,
+    (taken, not_taken): (u8, u8),
+) -> (String, u8, Option<u8>) {
+    match condition {
+        JumpCondition::Always => (format!("{} {}", mnemonic, target), taken, None),
+        condition => (
+            format!("{} {}, {}", mnemonic, jump_condition(condition), target),
+            not_taken,
+            Some(taken),
+        ),
+    }
+}
+
+fn jump_condition(condition: &JumpCondition) -> &'static str {
+    match condition {
+        JumpCondition::NotZero => "nz",
+        JumpCondition::Zero => "z",
+        JumpCondition::NotCarry => "nc",
+        JumpCondition::Carry => "c",
+        JumpCondition::Always => "",
+    }
+}
+
+fn arithmetic_target(target: &ArithmeticTarget) -> &'static str {
+    match target {
+        ArithmeticTarget::A => "a",
+        ArithmeticTarget::B => "b",
+        ArithmeticTarget::C => "c",
+        ArithmeticTarget::D => "d",
+        ArithmeticTarget::E => "e",
+        ArithmeticTarget::H => "h",
+        ArithmeticTarget::L => "l",
+        ArithmeticTarget::HL => "[hl]",
+        ArithmeticTarget::D8 => "n8",
+    }
+}
+
+fn inc_dec_target(target: &IncDecTarget) -> &'static str {
+    match target {
+        IncDecTarget::A => "a",
+        IncDecTarget::B => "b",
+        IncDecTarget::C => "c",
+        IncDecTarget::D => "d",
+        IncDecTarget::E => "e",
+        IncDecTarget::H => "h",
+        IncDecTarget::L => "l",
+        IncDecTarget::Hli => "[hl]",
+        IncDecTarget::BC => "bc",
+        IncDecTarget::DE => "de",
+        IncDecTarget::HL => "hl",
+        IncDecTarget::SP => "sp",
+    }
+}
+
+fn prefix_target(target: &PrefixTarget) -> &'static str {
+    match target {
+        PrefixTarget::A => "a",
+        PrefixTarget::B => "b",
+        PrefixTarget::C => "c",
+        PrefixTarget::D => "d",
+        PrefixTarget::E => "e",
+        PrefixTarget::H => "h",
+        PrefixTarget::L => "l",
+        PrefixTarget::HL => "[hl]",
+    }
+}
+
+fn bit_number(bit: &BitPosition) -> u8 {
+    match bit {
+        BitPosition::B0 => 0,
+        BitPosition::B1 => 1,
+        BitPosition::B2 => 2,
+        BitPosition::B3 => 3,
+        BitPosition::B4 => 4,
+        BitPosition::B5 => 5,
+        BitPosition::B6 => 6,
+        BitPosition::B7 => 7,
+    }
+}
+
+fn load_byte_target(target: &LoadByteTarget) -> &'static str {
+    match target {
+        LoadByteTarget::A => "a",
+        LoadByteTarget::B => "b",
+        LoadByteTarget::C => "c",
+        LoadByteTarget::D => "d",
+        LoadByteTarget::E => "e",
+        LoadByteTarget::H => "h",
+        LoadByteTarget::L => "l",
+        LoadByteTarget::HL => "[hl]",
+    }
+}
+
+fn load_byte_source(source: &LoadByteSource) -> &'static str {
+    match source {
+        LoadByteSource::A => "a",
+        LoadByteSource::B => "b",
+        LoadByteSource::C => "c",
+        LoadByteSource::D => "d",
+        LoadByteSource::E => "e",
+        LoadByteSource::H => "h",
+        LoadByteSource::L => "l",
+        LoadByteSource::HL => "[hl]",
+        LoadByteSource::D8 => "n8",
+    }
+}
+
+fn indirect_target(target: &IndirectTarget) -> &'static str {
+    match target {
+        IndirectTarget::BC => "[bc]",
+        IndirectTarget::DE => "[de]",
+        IndirectTarget::Hli => "[hl+]",
+        IndirectTarget::Hld => "[hl-]",
+        IndirectTarget::Word => "[n16]",
+        IndirectTarget::LastByte => "[c]",
+    }
+}
+
+fn stack_target(target: &StackTarget) -> &'static str {
+    match target {
+        StackTarget::AF => "af",
+        StackTarget::BC => "bc",
+        StackTarget::DE => "de",
+        StackTarget::HL => "hl",
+    }
+}
@@ -0,0 +1,257 @@
+// Scalers and LCD effects applied to each frame before it's shown, screenshots and recordings
+// keep the plain frame
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Scaler {
+    // Left to SDL, which scales by whole numbers
+    Nearest,
+    Scale2x,
+    Scale3x,
+    // Scale2x's rules with similar rather than equal colours, blending instead of copying so
+    // gradients and diagonals come out smooth
+    Hq2x,
+}
+
+const SCALERS: [(&str, Scaler); 4] = [
+    ("nearest", Scaler::Nearest),
+    ("scale2x", Scaler::Scale2x),
+    ("scale3x", Scaler::Scale3x),
+    ("hq2x", Scaler::Hq2x),
+];
+
+impl Scaler {
+    pub fn from_name(name: &str) -> Option<Scaler> {
+        SCALERS
+            .iter()
+            .find(|(scaler_name, _)| scaler_name.eq_ignore_ascii_case(name))
+            .map(|(_, scaler)| *scaler)
+    }
+
+    pub fn name(&self) -> &'static str {
+        SCALERS.iter().find(|(_, scaler)| scaler == self).unwrap().0
+    }
+
+    pub fn next(&self) -> Scaler {
+        let index = SCALERS
+            .iter()
+            .position(|(_, scaler)| scaler == self)
+            .unwrap();
+        SCALERS[(index + 1) % SCALERS.len()].1
+    }
+
+    fn factor(&self) -> u32 {
+        match self {
+            Scaler::Nearest => 1,
+            Scaler::Scale2x | Scaler::Hq2x => 2,
+            Scaler::Scale3x => 3,
+        }
+    }
+}
+
+pub struct Filters {
+    pub scaler: Scaler,
+    // Dark lines between the pixels like the dot matrix of the original LCD
+    pub lcd_grid: bool,
+    // Blends each frame with the one before, like the slow LCD. Games that flicker sprites every
+    // other frame rely on it for transparency
+    pub ghosting: bool,
+    previous: Vec<u8>,
+}
+
+impl Filters {
+    pub fn new(scaler: Scaler, lcd_grid: bool, ghosting: bool) -> Filters {
+        Filters {
+            scaler,
+            lcd_grid,
+            ghosting,
+            previous: vec![],
+        }
+    }
+
+    // RGBA in, RGBA out with the new width and height
+    pub fn apply(&mut self, pixels: &[u8], width: u32, height: u32) -> (Vec<u8>, u32, u32) {
+        let mut frame = pixels.to_vec();
+        if self.ghosting && self.previous.len() == frame.len() {
+            for (pixel, previous) in frame.iter_mut().zip(self.previous.iter()) {
+                *pixel = ((*pixel as u16 + *previous as u16) / 2) as u8;
+            }
+        }
+        self.previous = pixels.to_vec();
+
+        let (width, height) = (width as usize, height as usize);
+        let mut frame = match self.scaler {
+            Scaler::Nearest => frame,
+            Scaler::Scale2x => scale2x(&frame, width, height),
+            Scaler::Scale3x => scale3x(&frame, width, height),
+            Scaler::Hq2x => hq2x(&frame, width, height),
+        };
+        let mut factor = self.scaler.factor() as usize;
+
+        if self.lcd_grid {
+            // The grid needs a few pixels per dot to show
+            if factor == 1 {
+                frame = nearest(&frame, width, height, 3);
+                factor = 3;
+            }
+            darken_grid(&mut frame, width * factor, factor);
+        }
+        (frame, (width * factor) as u32, (height * factor) as u32)
+    }
+}
+
+fn pixel(frame: &[u8], width: usize, x: usize, y: usize) -> [u8; 4] {
+    let offset = (y * width + x) * 4;
+    [
+        frame[offset],
+        frame[offset + 1],
+        frame[offset + 2],
+        frame[offset + 3],
+    ]
+}
+
+// The 3x3 neighbourhood around a pixel, clamped at the edges:
+// A B C
+// D E F
+// G H I
+fn neighbours(frame: &[u8], width: usize, height: usize, x: usize, y: usize) -> [[u8; 4]; 9] {
+    let xs = [x.saturating_sub(1), x, (x + 1).min(width - 1)];
+    let ys = [y.saturating_sub(1), y, (y + 1).min(height - 1)];
+    let mut block = [[0; 4]; 9];
+    for (row, &y) in ys.iter().enumerate() {
+        for (column, &x) in xs.iter().enumerate() {
+            block[row * 3 + column] = pixel(frame, width, x, y);
+        }
+    }
+    block
+}
+
+// Runs a scaler that turns each pixel into factor x factor pixels
+fn scale_with(
+    frame: &[u8],
+    width: usize,
+    height: usize,
+    factor: usize,
+    scale: impl Fn(&[[u8; 4]; 9]) -> Vec<[u8; 4]>,
+) -> Vec<u8> {
+    let out_width = width * factor;
+    let mut scaled = vec![0; frame.len() * factor * factor];
+    for y in 0..height {
+        for x in 0..width {
+            let block = scale(&neighbours(frame, width, height, x, y));
+            for (index, color) in block.iter().enumerate() {
+                let (out_x, out_y) = (x * factor + index % factor, y * factor + index / factor);
+                let offset = (out_y * out_width + out_x) * 4;
+                scaled[offset..offset + 4].copy_from_slice(color);
+            }
+        }
+    }
+    scaled
+}
+
+fn nearest(frame: &[u8], width: usize, height: usize, factor: usize) -> Vec<u8> {
+    scale_with(frame, width, height, factor, |block| {
+        vec![block[4]; factor * factor]
+    })
+}
+
+// AdvMAME2x: corners take a neighbour's colour where two edges meet
+fn scale2x(frame: &[u8], width: usize, height: usize) -> Vec<u8> {
+    scale_with(frame, width, height, 2, |block| {
+        let [_, b, _, d, e, f, _, h, _] = *block;
+        if b != h && d != f {
+            vec![
+                if d == b { d } else { e },
+                if b == f { f } else { e },
+                if d == h { d } else { e },
+                if h == f { f } else { e },
+            ]
+        } else {
+            vec![e; 4]
+        }
+    })
+}
+
+// AdvMAME3x
+fn scale3x(frame: &[u8], width: usize, height: usize) -> Vec<u8> {
+    scale_with(frame, width, height, 3, |block| {
+        let [a, b, c, d, e, f, g, h, i] = *block;
+        if b != h && d != f {
+            vec![
+                if d == b { d } else { e },
+                if (d == b && e != c) || (b == f && e != a) {
+                    b
+                } else {
+                    e
+                },
+                if b == f { f } else { e },
+                if (d == b && e != g) || (d == h && e != a) {
+                    d
+                } else {
+                    e
+                },
+                e,
+                if (b == f && e != i) || (h == f && e != c) {
+                    f
+                } else {
+                    e
+                },
+                if d == h { d } else { e },
+                if (d == h && e != i) || (h == f && e != g) {
+                    h
+                } else {
+                    e
+                },
+                if h == f { f } else { e },
+            ]
+        } else {
+            vec![e; 9]
+        }
+    })
+}
+
+fn hq2x(frame: &[u8], width: usize, height: usize) -> Vec<u8> {
+    scale_with(frame, width, height, 2, |block| {
+        let [_, b, _, d, e, f, _, h, _] = *block;
+        let corner = |first: [u8; 4], second: [u8; 4], opposite: [u8; 4], other: [u8; 4]| {
+            if similar(first, second) && !similar(first, opposite) && !similar(second, other) {
+                blend(e, blend(first, second))
+            } else {
+                e
+            }
+        };
+        vec![
+            corner(d, b, h, f),
+            corner(b, f, d, h),
+            corner(d, h, b, f),
+            corner(h, f, d, b),
+        ]
+    })
+}
+
+// Close enough in YUV to count as the same colour, with hq2x's thresholds
+fn similar(first: [u8; 4], second: [u8; 4]) -> bool {
+    let yuv = |color: [u8; 4]| {
+        let (r, g, b) = (color[0] as i32, color[1] as i32, color[2] as i32);
+        ((r + g + b) / 3, (r - b) / 2, (2 * g - r - b) / 4)
+    };
+    let (first, second) = (yuv(first), yuv(second));
+    (first.0 - second.0).abs() <= 48
+        && (first.1 - second.1).abs() <= 7
+        && (first.2 - second.2).abs() <= 6
+}
+
+fn blend(first: [u8; 4], second: [u8; 4]) -> [u8; 4] {
+    [0, 1, 2, 3].map(|channel| ((first[channel] as u16 + second[channel] as u16) / 2) as u8)
+}
+
+// Darkens the last row and column of every dot
+fn darken_grid(frame: &mut [u8], width: usize, factor: usize) {
+    for (index, pixel) in frame.chunks_mut(4).enumerate() {
+        let (x, y) = (index % width, index / width);
+        if x % factor == factor - 1 || y % factor == factor - 1 {
+            for channel in pixel.iter_mut().take(3) {
+                *channel = (*channel as u16 * 3 / 4) as u8;
+            }
+        }
+    }
+}
@@ -0,0 +1,523 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use crate::cpu::Cpu;
+
+pub const DEFAULT_PORT: u16 = 2345;
+
+// There is no SM83 in GDB, so the registers are described as plain 16-bit values
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.gnu.gdb.sm83.core">
+    <reg name="af" bitsize="16" type="uint16" regnum="0"/>
+    <reg name="bc" bitsize="16" type="uint16"/>
+    <reg name="de" bitsize="16" type="uint16"/>
+    <reg name="hl" bitsize="16" type="uint16"/>
+    <reg name="sp" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+const REGISTER_COUNT: usize = 6;
+
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+
+// Remote serial protocol server, polled from the main loop so the game keeps running between packets
+pub struct Gdb {
+    listener: TcpListener,
+    stream: Option<TcpStream>,
+    buffer: Vec<u8>,
+    breakpoints: Vec<u16>,
+    stopped: bool,
+    // Breakpoint at the PC we continued from is skipped until it changes
+    resume_pc: Option<u16>,
+}
+
+impl Gdb {
+    // None when the port can't be used, the emulator runs without the server then
+    pub fn new(port: u16) -> Option<Gdb> {
+        let listener = match TcpListener::bind(("127.0.0.1", port))
+            .and_then(|listener| listener.set_nonblocking(true).map(|()| listener))
+        {
+            Ok(listener) => listener,
+            Err(error) => {
+                println!("GDB: failed to listen on 127.0.0.1:{}: {}", port, error);
+                return None;
+            }
+        };
+        println!("GDB: listening on 127.0.0.1:{}", port);
+
+        Some(Gdb {
+            listener,
+            stream: None,
+            buffer: vec![],
+            breakpoints: vec![],
+            stopped: false,
+            resume_pc: None,
+        })
+    }
+
+    // Accepts a connection and answers any packets that arrived
+    pub fn poll(&mut self, cpu: &mut Cpu) {
+        if self.stream.is_none() {
+            match self.listener.accept() {
+                Ok((stream, address)) => {
+                    println!("GDB: connected from {}", address);
+                    stream.set_nodelay(true).ok();
+                    self.stream = Some(stream);
+                    self.buffer.clear();
+                    // The target is halted while the client attaches
+                    self.stopped = true;
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return,
+                Err(error) => {
+                    println!("GDB: accept failed: {}", error);
+                    return;
+                }
+            }
+        }
+
+        if !self.receive() {
+            println!("GDB: disconnected");
+            self.disconnect();
+            return;
+        }
+
+        while let Some(packet) = self.next_packet() {
+            if let Some(reply) = self.handle(cpu, &packet) {
+                self.send(&reply);
+            }
+        }
+    }
+
+    // Called before every instruction, returns true while the client has the CPU stopped
+    pub fn check(&mut self, cpu: &Cpu) -> bool {
+        if self.stopped {
+            return true;
+        }
+        if self.breakpoints.is_empty() {
+            return false;
+        }
+
+        let pc = cpu.pc();
+        if self.resume_pc == Some(pc) {
+            return false;
+        }
+        self.resume_pc = None;
+
+        if self.breakpoints.contains(&pc) {
+            self.stop(SIGTRAP);
+            return true;
+        }
+        false
+    }
+
+    fn stop(&mut self, signal: u8) {
+        self.stopped = true;
+        self.send(&format!("S{:02x}", signal));
+    }
+
+    fn resume(&mut self, cpu: &mut Cpu, packet: &str) {
+        if let Some(address) = parse_hex(packet) {
+            cpu.set_pc(address as u16);
+        }
+        self.stopped = false;
+        self.resume_pc = Some(cpu.pc());
+    }
+
+    fn disconnect(&mut self) {
+        self.stream = None;
+        self.breakpoints.clear();
+        self.stopped = false;
+    }
+
+    // Reads whatever is available without blocking, false once the client has gone
+    fn receive(&mut self) -> bool {
+        let Some(stream) = self.stream.as_mut() else {
+            return false;
+        };
+        if stream.set_nonblocking(true).is_err() {
+            return false;
+        }
+
+        let mut chunk = [0u8; 4096];
+        let connected = loop {
+            match stream.read(&mut chunk) {
+                Ok(0) => break false,
+                Ok(length) => self.buffer.extend_from_slice(&chunk[..length]),
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break true,
+                Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                Err(_) => break false,
+            }
+        };
+
+        stream.set_nonblocking(false).is_ok() && connected
+    }
+
+    // Pulls the next complete packet out of the buffer, acknowledging it
+    fn next_packet(&mut self) -> Option<String> {
+        loop {
+            match self.buffer.first()? {
+                b'$' => break,
+                // Ctrl-C from the client
+                0x03 => {
+                    self.buffer.remove(0);
+                    if !self.stopped {
+                        self.stop(SIGINT);
+                    }
+                }
+                // Acks and anything between packets
+                _ => {
+                    self.buffer.remove(0);
+                }
+            }
+        }
+
+        let end = self.buffer.iter().position(|&byte| byte == b'#')?;
+        if self.buffer.len() < end + 3 {
+            return None;
+        }
+
+        let data = self.buffer[1..end].to_vec();
+        let checksum = std::str::from_utf8(&self.buffer[end + 1..end + 3])
+            .ok()
+            .and_then(|text| u8::from_str_radix(text, 16).ok());
+        self.buffer.drain(..end + 3);
+
+        let sum = data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+        if checksum != Some(sum) {
+            self.write(b"-");
+            return None;
+        }
+        self.write(b"+");
+
+        Some(String::from_utf8_lossy(&data).into_owned())
+    }
+
+    fn send(&mut self, data: &str) {
+        let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        let packet = format!("${}#{:02x}", data, checksum);
+        self.write(packet.as_bytes());
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        if let Some(stream) = self.stream.as_mut() {
+            if stream.write_all(bytes).is_err() {
+                self.disconnect();
+            }
+        }
+    }
+
+    // Returns the reply, or None when it comes later (continue stops at a breakpoint)
+    fn handle(&mut self, cpu: &mut Cpu, packet: &str) -> Option<String> {
+        if packet.is_empty() {
+            return Some(String::new());
+        }
+        let (command, args) = packet.split_at(1);
+
+        let reply = match command {
+            "?" => format!("S{:02x}", SIGTRAP),
+            "g" => (0..REGISTER_COUNT)
+                .map(|register| encode_word(read_register(cpu, register)))
+                .collect(),
+            "G" => {
+                for register in 0..REGISTER_COUNT {
+                    let Some(value) = args
+                        .get(register * 4..register * 4 + 4)
+                        .and_then(decode_word)
+                    else {
+                        return Some("E01".to_string());
+                    };
+                    write_register(cpu, register, value);
+                }
+                "OK".to_string()
+            }
+            "p" => match parse_hex(args) {
+                Some(register) if register < REGISTER_COUNT => {
+                    encode_word(read_register(cpu, register))
+                }
+                _ => "E01".to_string(),
+            },
+            "P" => {
+                let parsed = args.split_once('=').and_then(|(register, value)| {
+                    Some((parse_hex(register)?, decode_word(value)?))
+                });
+                match parsed {
+                    Some((register, value)) if register < REGISTER_COUNT => {
+                        write_register(cpu, register, value);
+                        "OK".to_string()
+                    }
+                    _ => "E01".to_string(),
+                }
+            }
+            "m" => match parse_range(args) {
+                Some((address, length)) => (0..length)
+                    .map(|offset| {
+                        format!("{:02x}", cpu.mem.peek_byte(address.wrapping_add(offset)))
+                    })
+                    .collect(),
+                None => "E01".to_string(),
+            },
+            "M" => {
+                let parsed = args
+                    .split_once(':')
+                    .and_then(|(range, data)| Some((parse_range(range)?, decode_bytes(data)?)));
+                match parsed {
+                    Some(((address, _), bytes)) => {
+                        for (offset, byte) in bytes.into_iter().enumerate() {
+                            cpu.mem
+                                .write_byte(address.wrapping_add(offset as u16), byte);
+                        }
+                        "OK".to_string()
+                    }
+                    None => "E01".to_string(),
+                }
+            }
+            "c" => {
+                self.resume(cpu, args);
+                return None;
+            }
+            "s" => {
+                if let Some(address) = parse_hex(args) {
+                    cpu.set_pc(address as u16);
+                }
+                cpu.step();
+                format!("S{:02x}", SIGTRAP)
+            }
+            // Software and hardware breakpoints behave the same here
+            "Z" | "z" => {
+                let mut fields = args.split(',');
+                let kind = fields.next();
+                let address = fields.next().and_then(parse_hex);
+                match (kind, address) {
+                    (Some("0") | Some("1"), Some(address)) => {
+                        let address = address as u16;
+                        if command == "Z" {
+                            if !self.breakpoints.contains(&address) {
+                                self.breakpoints.push(address);
+                            }
+                        } else {
+                            self.breakpoints.retain(|&b| b != address);
+                        }
+                        "OK".to_string()
+                    }
+                    _ => String::new(),
+                }
+            }
+            "H" => "OK".to_string(),
+            "T" => "OK".to_string(),
+            "D" => {
+                self.send("OK");
+                println!("GDB: detached");
+                self.disconnect();
+                return None;
+            }
+            "k" => {
+                println!("GDB: killed");
+                self.disconnect();
+                return None;
+            }
+            "q" => self.query(args),
+            _ => String::new(),
+        };
+
+        Some(reply)
+    }
+
+    fn query(&self, query: &str) -> String {
+        if query.starts_with("Supported") {
+            return "PacketSize=1000;qXfer:features:read+".to_string();
+        }
+        if let Some(request) = query.strip_prefix("Xfer:features:read:target.xml:") {
+            let Some((offset, length)) = request
+                .split_once(',')
+                .and_then(|(offset, length)| Some((parse_hex(offset)?, parse_hex(length)?)))
+            else {
+                return "E01".to_string();
+            };
+            let xml = TARGET_XML.as_bytes();
+            let start = offset.min(xml.len());
+            let end = (start + length).min(xml.len());
+            let prefix = if end == xml.len() { 'l' } else { 'm' };
+            return format!("{}{}", prefix, String::from_utf8_lossy(&xml[start..end]));
+        }
+        match query {
+            "Attached" => "1".to_string(),
+            "C" => "QC1".to_string(),
+            "fThreadInfo" => "m1".to_string(),
+            "sThreadInfo" => "l".to_string(),
+            _ => String::new(),
+        }
+    }
+}
+
+// Register numbers follow the order in TARGET_XML
+fn read_register(cpu: &Cpu, register: usize) -> u16 {
+    let registers = cpu.registers();
+    match register {
+        0 => registers.get_af(),
+        1 => registers.get_bc(),
+        2 => registers.get_de(),
+        3 => registers.get_hl(),
+        4 => cpu.sp(),
+        _ => cpu.pc(),
+    }
+}
+
+fn write_register(cpu: &mut Cpu, register: usize, value: u16) {
+    match register {
+        0 => cpu.registers_mut().set_af(value),
+        1 => cpu.registers_mut().set_bc(value),
+        2 => cpu.registers_mut().set_de(value),
+        3 => cpu.registers_mut().set_hl(value),
+        4 => cpu.set_sp(value),
+        _ => cpu.set_pc(value),
+    }
+}
+
+fn parse_hex(text: &str) -> Option<usize> {
+    usize::from_str_radix(text, 16).ok()
+}
+
+// "addr,length"
+fn parse_range(text: &str) -> Option<(u16, u16)> {
+    let (address, length) = text.split_once(',')?;
+    Some((parse_hex(address)? as u16, parse_hex(length)? as u16))
+}
+
+// Registers are sent as little endian bytes
+fn encode_word(value: u16) -> String {
+    format!("{:02x}{:02x}", value & 0xFF, value >> 8)
+}
+
+fn decode_word(text: &str) -> Option<u16> {
+    let bytes = decode_bytes(text)?;
+    match bytes[..] {
+        [low, high] => Some(u16::from(high) << 8 | u16::from(low)),
+        _ => None,
+    }
+}
+
+fn decode_bytes(text: &str) -> Option<Vec<u8>> {
+    (0..text.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(text.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge;
+    use crate::mmu::Memory;
+    use std::fs;
+    use std::time::Duration;
+
+    fn test_cpu() -> Cpu {
+        // NOPs from the entry point on, with two known bytes to read back
+        let mut rom = vec![0; 0x8000];
+        rom[0x150] = 0x12;
+        rom[0x151] = 0x34;
+        let dir = std::env::temp_dir();
+        let path = dir.join("gdb-test.gb");
+        fs::write(&path, rom).unwrap();
+        let cartridge = cartridge::new_cartridge(&path, &dir.join("gdb-test.sav")).unwrap();
+        let mut cpu = Cpu::new(Memory::new(cartridge, None, None));
+        cpu.skip_boot_rom();
+        cpu
+    }
+
+    fn packet(data: &str) -> String {
+        let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        format!("${}#{:02x}", data, checksum)
+    }
+
+    // Polls the server until the client has read `expected` bytes of acks and replies
+    fn read(gdb: &mut Gdb, cpu: &mut Cpu, client: &mut TcpStream, expected: usize) -> String {
+        let mut received = vec![];
+        for _ in 0..200 {
+            gdb.poll(cpu);
+            let mut chunk = [0u8; 256];
+            match client.read(&mut chunk) {
+                Ok(length) => received.extend_from_slice(&chunk[..length]),
+                Err(error) if error.kind() == ErrorKind::WouldBlock => {}
+                Err(error) => panic!("{}", error),
+            }
+            if received.len() >= expected {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        String::from_utf8(received).unwrap()
+    }
+
+    // Sends a packet, expects it acked and answered with a reply whose checksum matches
+    fn exchange(gdb: &mut Gdb, cpu: &mut Cpu, client: &mut TcpStream, data: &str) -> String {
+        client.write_all(packet(data).as_bytes()).unwrap();
+        let received = read(gdb, cpu, client, 1);
+        assert!(received.starts_with('+'), "{} not acked", data);
+        let received = match received.len() {
+            1 => read(gdb, cpu, client, 4),
+            _ => received[1..].to_string(),
+        };
+        let reply = received
+            .strip_prefix('$')
+            .and_then(|received| received.split_once('#'))
+            .map(|(reply, _)| reply.to_string())
+            .unwrap_or_else(|| panic!("no reply to {}: {:?}", data, received));
+        assert_eq!(
+            received,
+            packet(&reply),
+            "checksum of the reply to {}",
+            data
+        );
+        client.write_all(b"+").unwrap();
+        reply
+    }
+
+    #[test]
+    fn loopback() {
+        let mut cpu = test_cpu();
+        let mut gdb = Gdb::new(0).unwrap();
+        let port = gdb.listener.local_addr().unwrap().port();
+        let mut client = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        client.set_nonblocking(true).unwrap();
+
+        assert_eq!(exchange(&mut gdb, &mut cpu, &mut client, "?"), "S05");
+        // AF, BC, DE, HL, SP and PC, little endian
+        let registers = exchange(&mut gdb, &mut cpu, &mut client, "g");
+        assert_eq!(registers.len(), 24);
+        assert_eq!(&registers[16..], "feff0001");
+        assert_eq!(exchange(&mut gdb, &mut cpu, &mut client, "m150,2"), "1234");
+
+        assert_eq!(exchange(&mut gdb, &mut cpu, &mut client, "s"), "S05");
+        assert_eq!(cpu.pc(), 0x101);
+
+        assert_eq!(exchange(&mut gdb, &mut cpu, &mut client, "Z0,105,1"), "OK");
+        client.write_all(packet("c").as_bytes()).unwrap();
+        assert_eq!(read(&mut gdb, &mut cpu, &mut client, 1), "+");
+        while !gdb.check(&cpu) {
+            cpu.step();
+        }
+        assert_eq!(cpu.pc(), 0x105);
+        assert_eq!(read(&mut gdb, &mut cpu, &mut client, 7), packet("S05"));
+        client.write_all(b"+").unwrap();
+
+        assert_eq!(exchange(&mut gdb, &mut cpu, &mut client, "z0,105,1"), "OK");
+        assert!(gdb.breakpoints.is_empty());
+
+        // A bad checksum is refused
+        client.write_all(b"$g#00").unwrap();
+        assert_eq!(read(&mut gdb, &mut cpu, &mut client, 1), "-");
+    }
+
+    #[test]
+    fn busy_port() {
+        let taken = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = taken.local_addr().unwrap().port();
+        assert!(Gdb::new(port).is_none());
+    }
+}
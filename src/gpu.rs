@@ -1,7 +1,11 @@
+pub mod compat;
 mod lcdc;
+pub mod palette;
 pub mod stat;
 
+use compat::CompatPalette;
 use lcdc::Lcdc;
+use palette::{ColorCorrection, DmgPalette};
 use stat::{Mode, Stat};
 
 use crate::mmu::{OAM_SIZE, VRAM_BEGIN, VRAM_SIZE};
@@ -10,14 +14,8 @@ const TILESET_FIRST_BEGIN_ADDRESS: u16 = 0x8000;
 const TILESET_SECOND_BEGIN_ADDRESS: u16 = 0x9000;
 
 const NUMBER_OF_OBJECTS: usize = 40;
+const OBJECTS_PER_LINE: usize = 10;
 
-#[derive(Copy, Clone, Debug, PartialEq)]
-pub enum Color {
-    White = 255,
-    LightGray = 170,
-    DarkGray = 85,
-    Black = 0,
-}
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Pixel {
     r: u8,
@@ -25,6 +23,12 @@ pub struct Pixel {
     b: u8,
 }
 
+impl Pixel {
+    pub fn rgb(&self) -> [u8; 3] {
+        [self.r, self.g, self.b]
+    }
+}
+
 impl Default for Pixel {
     fn default() -> Self {
         Pixel {
@@ -35,29 +39,9 @@ impl Default for Pixel {
     }
 }
 
-impl std::convert::From<Color> for Pixel {
-    fn from(color: Color) -> Self {
-        Pixel {
-            r: color as u8,
-            g: color as u8,
-            b: color as u8,
-        }
-    }
-}
-
-impl std::convert::From<u8> for Color {
-    fn from(n: u8) -> Self {
-        match n {
-            0 => Color::White,
-            1 => Color::LightGray,
-            2 => Color::DarkGray,
-            3 => Color::Black,
-            _ => panic!("Cannot convert {} to color", n),
-        }
-    }
-}
-
+#[derive(Copy, Clone)]
 pub struct ObjectData {
+    index: u8,
     x: i16,
     y: i16,
     tile: u8,
@@ -72,6 +56,7 @@ pub struct ObjectData {
 impl Default for ObjectData {
     fn default() -> Self {
         ObjectData {
+            index: Default::default(),
             x: -16,
             y: -8,
             tile: Default::default(),
@@ -129,6 +114,8 @@ const SCREEN_HEIGHT: usize = 144;
 
 pub struct Gpu {
     pub canvas_buffer: [u8; SCREEN_WIDTH * SCREEN_HEIGHT * 4],
+    // Shade of each pixel in DMG mode after BGP, OBP0 or OBP1, for the SGB to colour
+    pub shades: [u8; SCREEN_WIDTH * SCREEN_HEIGHT],
     pub vram: [u8; VRAM_SIZE],
     pub vram1: [u8; VRAM_SIZE],
     pub oam: [u8; OAM_SIZE],
@@ -159,14 +146,25 @@ pub struct Gpu {
     pub vram_bank: u8,
     pub speed: u8,
     pub gb_mode: GameBoyMode,
-    boot_rom: bool,
+    dmg_compatibility: bool,
     pub interrupts_fired: u8,
+    pub frames: u64,
+    first_line: bool,
+    skip_frame: bool,
+    window_triggered: bool,
+    window_wraps: bool,
+    line_objects: [ObjectData; OBJECTS_PER_LINE],
+    line_object_count: usize,
+    pub opri: u8,
+    dmg_palette: Option<DmgPalette>,
+    color_correction: ColorCorrection,
 }
 
 impl Gpu {
-    pub fn new(gb_mode: GameBoyMode, boot_rom: bool) -> Gpu {
+    pub fn new(gb_mode: GameBoyMode, dmg_compatibility: bool) -> Gpu {
         Gpu {
             canvas_buffer: [0; SCREEN_WIDTH * SCREEN_HEIGHT * 4],
+            shades: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
             vram: [0; VRAM_SIZE],
             vram1: [0; VRAM_SIZE],
             oam: [0; OAM_SIZE],
@@ -197,13 +195,76 @@ impl Gpu {
             vram_bank: 0,
             speed: 0x00,
             gb_mode,
-            boot_rom,
+            dmg_compatibility,
             interrupts_fired: 0,
+            frames: 0,
+            first_line: false,
+            skip_frame: false,
+            window_triggered: false,
+            window_wraps: false,
+            line_objects: [Default::default(); OBJECTS_PER_LINE],
+            line_object_count: 0,
+            opri: 0,
+            dmg_palette: None,
+            color_correction: ColorCorrection::None,
         }
     }
 
+    pub fn write_lcdc(&mut self, value: u8) {
+        let was_enabled = self.lcdc.display_enabled;
+        self.lcdc.write(value);
+
+        if was_enabled && !self.lcdc.display_enabled {
+            self.disable_display();
+        } else if !was_enabled && self.lcdc.display_enabled {
+            self.enable_display();
+        }
+    }
+
+    fn disable_display(&mut self) {
+        // With the LCD off LY reads 0, STAT reports mode 0 and the screen is blank
+        self.line = 0;
+        self.cycles = 0;
+        self.wly = 0;
+        self.window_triggered = false;
+        self.window_wraps = false;
+        self.stat.mode = Mode::HorizontalBlank;
+        self.first_line = false;
+        self.skip_frame = false;
+
+        // The colour BG colour 0 shows in the selected palette, white on the CGB
+        let blank = match self.gb_mode {
+            GameBoyMode::Dmg => self.dmg_colors(0)[0],
+            GameBoyMode::Cgb => Pixel::default(),
+        };
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                self.draw_pixel_to_buffer(x, y, blank);
+            }
+        }
+        // The SGB colours its frame from these
+        self.shades = [0; SCREEN_WIDTH * SCREEN_HEIGHT];
+    }
+
+    fn enable_display(&mut self) {
+        // Line 0 starts without an OAM scan and the first frame is never shown
+        self.cycles = 0;
+        self.first_line = true;
+        self.skip_frame = true;
+        self.set_equal_lines_check();
+    }
+
     pub fn write_vram(&mut self, index: usize, value: u8) {
-        if self.vram_bank == 1 {
+        self.write_vram_bank(self.vram_bank, index, value);
+    }
+
+    pub fn read_vram(&self, index: usize) -> u8 {
+        self.read_vram_bank(self.vram_bank, index)
+    }
+
+    // Accesses either bank regardless of VBK
+    pub fn write_vram_bank(&mut self, bank: u8, index: usize, value: u8) {
+        if bank == 1 {
             match index {
                 0x0000..=0x17FF => {
                     self.vram1[index] = value;
@@ -223,8 +284,8 @@ impl Gpu {
         }
     }
 
-    pub fn read_vram(&self, index: usize) -> u8 {
-        if self.vram_bank == 1 {
+    pub fn read_vram_bank(&self, bank: u8, index: usize) -> u8 {
+        if bank == 1 {
             match index {
                 0x0000..=0x17FF => self.vram1[index],
                 0x1800..=0x1BFF => self.bg_map_attributes0[index - 0x1800],
@@ -242,68 +303,149 @@ impl Gpu {
 
     pub fn set_bg_palette(&mut self, value: u8) {
         self.palettes[0] = value;
-        self.palette_bg = [
-            Color::from(value & 0b11).into(),
-            Color::from((value >> 2) & 0b11).into(),
-            Color::from((value >> 4) & 0b11).into(),
-            Color::from(value >> 6).into(),
-        ];
+        self.palette_bg = self.dmg_shades(value);
     }
 
     pub fn set_dmg_object_palette(&mut self, value: u8, index: usize) {
         self.palettes[index + 1] = value;
-        self.dmg_object_palettes[index] = [
-            Color::from(value & 0b11).into(),
-            Color::from((value >> 2) & 0b11).into(),
-            Color::from((value >> 4) & 0b11).into(),
-            Color::from(value >> 6).into(),
+        self.dmg_object_palettes[index] = self.dmg_shades(value);
+    }
+
+    fn dmg_shades(&self, value: u8) -> [Pixel; 4] {
+        let shades = self.dmg_palette.unwrap_or(DmgPalette::Grey).shades();
+        [
+            shades[(value & 0b11) as usize],
+            shades[((value >> 2) & 0b11) as usize],
+            shades[((value >> 4) & 0b11) as usize],
+            shades[(value >> 6) as usize],
         ]
     }
 
-    pub fn set_cgb_bg_palette(&mut self, value: u8) {
-        self.bg_palette[self.bgpi as usize] = value;
+    // Colours of BGP, OBP0 and OBP1 as currently displayed
+    pub fn dmg_colors(&self, index: usize) -> [Pixel; 4] {
+        if self.uses_compat_palettes() {
+            let colors = match index {
+                0 => self.palettes_bg[0],
+                _ => self.palettes_object[index - 1],
+            };
+            let value = self.palettes[index];
+            return [0, 1, 2, 3].map(|color| colors[((value >> (color * 2)) & 0x03) as usize]);
+        }
+        match index {
+            0 => self.palette_bg,
+            _ => self.dmg_object_palettes[index - 1],
+        }
+    }
 
-        let palette_number = self.bgpi / 8;
-        let color_index = (self.bgpi as usize % 8) / 2;
+    pub fn cgb_colors(&self, object: bool, palette: usize) -> [Pixel; 4] {
+        if object {
+            self.palettes_object[palette]
+        } else {
+            self.palettes_bg[palette]
+        }
+    }
 
-        let palette = &mut self.palettes_bg[palette_number as usize];
+    pub fn bg_map_attributes(&self, map: usize) -> &[u8; 1024] {
+        if map == 0 {
+            &self.bg_map_attributes0
+        } else {
+            &self.bg_map_attributes1
+        }
+    }
 
-        let palette_offset = (palette_number * 8) as usize;
-        let color_offset = color_index * 2;
+    pub fn dmg_palette(&self) -> Option<DmgPalette> {
+        self.dmg_palette
+    }
 
-        let color = rgb555_to_rgb888(
-            self.bg_palette[palette_offset + color_offset],
-            self.bg_palette[palette_offset + color_offset + 1],
-        );
-        palette[color_index] = color;
+    // None keeps the hardware colours: CGB compatibility palettes if loaded, otherwise grey
+    pub fn set_dmg_palette(&mut self, palette: Option<DmgPalette>) {
+        self.dmg_palette = palette;
+        self.set_bg_palette(self.palettes[0]);
+        self.set_dmg_object_palette(self.palettes[1], 0);
+        self.set_dmg_object_palette(self.palettes[2], 1);
+    }
 
-        if self.auto_increment_bg {
-            self.bgpi = (self.bgpi + 1) & 0x3F;
+    pub fn color_correction(&self) -> ColorCorrection {
+        self.color_correction
+    }
+
+    pub fn set_color_correction(&mut self, correction: ColorCorrection) {
+        self.color_correction = correction;
+        for palette in 0..8 {
+            for color in 0..4 {
+                let offset = palette * 8 + color * 2;
+                self.palettes_bg[palette][color] =
+                    self.cgb_color(self.bg_palette[offset], self.bg_palette[offset + 1]);
+                self.palettes_object[palette][color] =
+                    self.cgb_color(self.object_palette[offset], self.object_palette[offset + 1]);
+            }
         }
     }
 
-    pub fn set_cgb_object_palette(&mut self, value: u8) {
-        self.object_palette[self.obpi as usize] = value;
+    fn cgb_color(&self, first: u8, second: u8) -> Pixel {
+        let r = first & 0x1F;
+        let g = (first >> 5) | ((second & 0x03) << 3);
+        let b = (second >> 2) & 0x1F;
+        self.color_correction.apply(r, g, b)
+    }
 
-        let palette_number = self.obpi as usize / 8;
+    fn uses_compat_palettes(&self) -> bool {
+        self.dmg_compatibility && self.dmg_palette.is_none()
+    }
 
-        let palette = &mut self.palettes_object[palette_number];
-        let color_index = (self.obpi as usize % 8) / 2;
+    pub fn load_compat_palette(&mut self, palette: &CompatPalette) {
+        self.dmg_compatibility = true;
 
-        let palette_offset = palette_number * 8;
-        let color_offset = color_index * 2;
+        for (index, color) in palette.bg.iter().enumerate() {
+            self.bg_palette[index * 2] = *color as u8;
+            self.bg_palette[index * 2 + 1] = (*color >> 8) as u8;
+            self.palettes_bg[0][index] = self.cgb_color(*color as u8, (*color >> 8) as u8);
+        }
+
+        for (number, colors) in [palette.obj0, palette.obj1].iter().enumerate() {
+            for (index, color) in colors.iter().enumerate() {
+                let offset = number * 8 + index * 2;
+                self.object_palette[offset] = *color as u8;
+                self.object_palette[offset + 1] = (*color >> 8) as u8;
+                self.palettes_object[number][index] =
+                    self.cgb_color(*color as u8, (*color >> 8) as u8);
+            }
+        }
+    }
+
+    pub fn set_cgb_bg_palette(&mut self, value: u8) {
+        self.write_palette_ram(false, self.bgpi as usize, value);
+
+        if self.auto_increment_bg {
+            self.bgpi = (self.bgpi + 1) & 0x3F;
+        }
+    }
 
-        let color = rgb555_to_rgb888(
-            self.object_palette[palette_offset + color_offset],
-            self.object_palette[palette_offset + color_offset + 1],
-        );
-        palette[color_index] = color;
+    pub fn set_cgb_object_palette(&mut self, value: u8) {
+        self.write_palette_ram(true, self.obpi as usize, value);
 
         if self.auto_increment_object {
             self.obpi = (self.obpi + 1) & 0x3F;
         }
     }
 
+    // Writes a byte of BG or object palette RAM and updates the colour it belongs to
+    pub fn write_palette_ram(&mut self, object: bool, index: usize, value: u8) {
+        let palette_number = index / 8;
+        let color_index = (index % 8) / 2;
+        let offset = palette_number * 8 + color_index * 2;
+
+        if object {
+            self.object_palette[index] = value;
+            self.palettes_object[palette_number][color_index] =
+                self.cgb_color(self.object_palette[offset], self.object_palette[offset + 1]);
+        } else {
+            self.bg_palette[index] = value;
+            self.palettes_bg[palette_number][color_index] =
+                self.cgb_color(self.bg_palette[offset], self.bg_palette[offset + 1]);
+        }
+    }
+
     pub fn step(&mut self, cycles: u8) {
         if !self.lcdc.display_enabled {
             return;
@@ -311,6 +453,14 @@ impl Gpu {
         self.cycles += cycles as u16;
 
         match self.stat.mode {
+            Mode::HorizontalBlank if self.first_line => {
+                if self.cycles >= 76 {
+                    self.cycles %= 76;
+                    self.first_line = false;
+                    self.line_object_count = 0;
+                    self.set_mode(Mode::VRAMAccess);
+                }
+            }
             Mode::HorizontalBlank => {
                 if self.cycles >= 204 {
                     self.cycles %= 204;
@@ -318,6 +468,7 @@ impl Gpu {
                     if self.line >= 143 {
                         self.set_mode(Mode::VerticalBlank);
                         self.fire_interrupt(Interrupt::VBlank);
+                        self.frames += 1;
                         self.bg_priority_map = [Default::default(); 65536];
                     } else {
                         self.set_current_line(self.line + 1);
@@ -332,6 +483,9 @@ impl Gpu {
                     self.set_current_line(self.line + 1);
                     if self.line > 153 {
                         self.wly = 0;
+                        self.window_triggered = false;
+                        self.window_wraps = false;
+                        self.skip_frame = false;
                         self.set_mode(Mode::OAMAccess);
                         self.set_current_line(0);
                     }
@@ -341,6 +495,7 @@ impl Gpu {
             Mode::OAMAccess => {
                 if self.cycles >= 80 {
                     self.cycles %= 80;
+                    self.scan_oam();
                     self.set_mode(Mode::VRAMAccess);
                 }
             }
@@ -400,6 +555,14 @@ impl Gpu {
     }
 
     fn render_line(&mut self) {
+        if self.line == self.window_y {
+            self.window_triggered = true;
+        }
+
+        if self.skip_frame {
+            return;
+        }
+
         if self.gb_mode == GameBoyMode::Dmg {
             self.render_scan_line();
         } else {
@@ -417,10 +580,11 @@ impl Gpu {
                 self.canvas_buffer[canvas_buffer_offset + 1] = 255;
                 self.canvas_buffer[canvas_buffer_offset + 2] = 255;
                 self.canvas_buffer[canvas_buffer_offset + 3] = 255;
+                self.shades[x + self.line as usize * SCREEN_WIDTH] = 0;
             }
         }
 
-        if self.lcdc.window_display_enabled {
+        if self.lcdc.bg_window_enabled {
             self.render_window_line();
         }
 
@@ -450,12 +614,9 @@ impl Gpu {
 
             let color_index = get_color_index(tile_data, tile_color_data, pixel_index);
 
-            let palette = match self.boot_rom {
-                true => &self.palettes_bg[0],
-                false => &self.palette_bg,
-            };
-
-            let pixel = palette[color_index as usize];
+            let pixel = self.dmg_bg_pixel(color_index);
+            self.shades[x as usize + self.line as usize * SCREEN_WIDTH] =
+                shade(self.palettes[0], color_index);
 
             if color_index == 0 {
                 self.bg_priority_map[self.line as usize + 256 * x as usize].color =
@@ -471,24 +632,28 @@ impl Gpu {
         }
     }
 
+    fn dmg_bg_pixel(&self, color_index: u8) -> Pixel {
+        if self.uses_compat_palettes() {
+            self.palettes_bg[0][shade(self.palettes[0], color_index) as usize]
+        } else {
+            self.palette_bg[color_index as usize]
+        }
+    }
+
     fn render_window_line(&mut self) {
-        if self.line < self.window_y
-            || self.window_x < 7
-            || self.window_x >= 167
-            || self.line >= 144
-        {
+        let Some(screen_x) = self.window_start() else {
             return;
-        }
+        };
 
-        let screen_x = self.window_x.wrapping_sub(7);
+        for x in screen_x.max(0) as u8..SCREEN_WIDTH as u8 {
+            let column = (x as i16 - screen_x) as u8;
 
-        for x in screen_x..SCREEN_WIDTH as u8 {
-            let tile_address = self.calculate_window_address(self.wly, x);
+            let tile_address = self.calculate_window_address(self.wly, column);
             let tile_number = self.vram[(tile_address - VRAM_BEGIN as u16) as usize];
 
             let tile = self.calculate_tile_address(tile_number) - VRAM_BEGIN as u16;
 
-            let pixel_index = self.window_x.wrapping_sub(x) % 8;
+            let pixel_index = 7 - (column % 8);
 
             let y_address_offset = ((self.wly) % 8 * 2) as u16;
 
@@ -499,12 +664,9 @@ impl Gpu {
 
             let color_index = get_color_index(tile_data, tile_color_data, pixel_index);
 
-            let palette = match self.boot_rom {
-                true => &self.palettes_bg[0],
-                false => &self.palette_bg,
-            };
-
-            let pixel = palette[color_index as usize];
+            let pixel = self.dmg_bg_pixel(color_index);
+            self.shades[x as usize + self.line as usize * SCREEN_WIDTH] =
+                shade(self.palettes[0], color_index);
 
             if color_index == 0 {
                 self.bg_priority_map[self.line as usize + 256 * x as usize].color =
@@ -522,24 +684,19 @@ impl Gpu {
     }
 
     fn render_object_line(&mut self) {
-        let object_height = if self.lcdc.sprite_size { 16 } else { 8 };
-        let objs = self.fetch_objects();
-        for object in objs.iter().rev() {
-            let line_offset = if object.yflip {
-                object_height - 1 - (self.line as i16 - object.y)
-            } else {
-                self.line as i16 - object.y
-            };
+        let mut drawn = [false; SCREEN_WIDTH];
 
-            let tile_address = object.tile as u16 * 16;
+        for i in 0..self.line_object_count {
+            let object = self.line_objects[i];
+            let tile_data_address = self.object_tile_data_address(&object);
 
-            let tile_data = self.vram[tile_address as usize + (line_offset * 2) as usize];
-            let tile_color_data = self.vram[tile_address as usize + (line_offset * 2) as usize + 1];
+            let tile_data = self.vram[tile_data_address];
+            let tile_color_data = self.vram[tile_data_address + 1];
 
             for x in 0..8 {
                 let x_offset = object.x + x as i16;
 
-                if x_offset < 0 || x_offset >= SCREEN_WIDTH as i16 {
+                if x_offset < 0 || x_offset >= SCREEN_WIDTH as i16 || drawn[x_offset as usize] {
                     continue;
                 }
 
@@ -547,27 +704,30 @@ impl Gpu {
 
                 let color_index = get_color_index(tile_data, tile_color_data, pixel_index);
 
+                if color_index == 0 {
+                    continue;
+                }
+
+                // The first opaque object pixel wins even if the background then hides it
+                drawn[x_offset as usize] = true;
+
                 let palette_index = if object.palette == ObjectPalette::One {
                     1
                 } else {
                     0
                 };
 
-                let palette = match self.boot_rom {
-                    true => self.palettes_object[palette_index],
-                    false => self.dmg_object_palettes[palette_index],
+                let shade = shade(self.palettes[palette_index + 1], color_index);
+                let pixel = match self.uses_compat_palettes() {
+                    true => self.palettes_object[palette_index][shade as usize],
+                    false => self.dmg_object_palettes[palette_index][color_index as usize],
                 };
 
-                let palette_index =
-                    (self.palettes[object.palette as usize + 1] >> (color_index * 2)) & 0x03;
+                let offset = self.line as usize + 256 * x_offset as usize;
 
-                if color_index != 0 {
-                    let offset = self.line as usize + 256 * x_offset as usize;
-                    let pixel = palette[palette_index as usize];
-
-                    if !self.background_has_priority(object.priority, offset) {
-                        self.draw_pixel_to_buffer(x_offset as usize, self.line as usize, pixel);
-                    }
+                if !self.background_has_priority(object.priority, offset) {
+                    self.draw_pixel_to_buffer(x_offset as usize, self.line as usize, pixel);
+                    self.shades[x_offset as usize + self.line as usize * SCREEN_WIDTH] = shade;
                 }
             }
         }
@@ -576,9 +736,7 @@ impl Gpu {
     fn render_scan_line_cgb(&mut self) {
         self.render_background_line_cgb();
 
-        if self.lcdc.window_display_enabled {
-            self.render_window_line_cgb();
-        }
+        self.render_window_line_cgb();
 
         if self.lcdc.object_display_enabled {
             self.render_object_line_cgb();
@@ -656,23 +814,19 @@ impl Gpu {
     }
 
     fn render_window_line_cgb(&mut self) {
-        if self.line < self.window_y
-            || self.window_x < 7
-            || self.window_x >= 167
-            || self.line >= 144
-        {
+        let Some(screen_x) = self.window_start() else {
             return;
-        }
+        };
 
-        let screen_x = self.window_x.wrapping_sub(7);
+        for x in screen_x.max(0) as u8..SCREEN_WIDTH as u8 {
+            let column = (x as i16 - screen_x) as u8;
 
-        for x in screen_x..SCREEN_WIDTH as u8 {
-            let tile_address = self.calculate_window_address(self.wly, x);
+            let tile_address = self.calculate_window_address(self.wly, column);
             let tile_number = self.vram[(tile_address - VRAM_BEGIN as u16) as usize];
 
             let tile = self.calculate_tile_address(tile_number) - VRAM_BEGIN as u16;
 
-            let pixel_index = self.window_x.wrapping_sub(x) % 8;
+            let pixel_index = 7 - (column % 8);
 
             let y_address_offset = ((self.wly) % 8 * 2) as u16;
 
@@ -736,28 +890,23 @@ impl Gpu {
     }
 
     fn render_object_line_cgb(&mut self) {
-        let object_height = if self.lcdc.sprite_size { 16 } else { 8 };
-        let objs = self.fetch_objects();
-        for object in objs.iter().rev() {
-            let line_offset = if object.yflip {
-                object_height - 1 - (self.line as i16 - object.y)
-            } else {
-                self.line as i16 - object.y
-            };
+        let mut drawn = [false; SCREEN_WIDTH];
 
-            let tile_address = object.tile as u16 * 16;
+        for i in 0..self.line_object_count {
+            let object = self.line_objects[i];
+            let tile_data_address = self.object_tile_data_address(&object);
 
             let vram_bank = object.bank;
 
-            let vram = if vram_bank { self.vram1 } else { self.vram };
+            let vram = if vram_bank { &self.vram1 } else { &self.vram };
 
-            let tile_data = vram[tile_address as usize + (line_offset * 2) as usize];
-            let tile_color_data = vram[tile_address as usize + (line_offset * 2) as usize + 1];
+            let tile_data = vram[tile_data_address];
+            let tile_color_data = vram[tile_data_address + 1];
 
             for x in 0..8 {
                 let x_offset = object.x + x as i16;
 
-                if x_offset < 0 || x_offset >= SCREEN_WIDTH as i16 {
+                if x_offset < 0 || x_offset >= SCREEN_WIDTH as i16 || drawn[x_offset as usize] {
                     continue;
                 }
 
@@ -765,58 +914,84 @@ impl Gpu {
 
                 let color_index = get_color_index(tile_data, tile_color_data, pixel_index);
 
+                if color_index == 0 {
+                    continue;
+                }
+
+                drawn[x_offset as usize] = true;
+
                 let object_palette = object.cgb_palette;
                 let palette = self.palettes_object[object_palette as usize];
 
-                if color_index != 0 {
-                    let offset = self.line as usize + 256 * x_offset as usize;
-                    let color = palette[color_index as usize];
+                let offset = self.line as usize + 256 * x_offset as usize;
+                let color = palette[color_index as usize];
 
-                    if !self.background_has_priority(object.priority, offset) {
-                        self.draw_pixel_to_buffer(x_offset as usize, self.line as usize, color);
-                    }
+                if !self.background_has_priority(object.priority, offset) {
+                    self.draw_pixel_to_buffer(x_offset as usize, self.line as usize, color);
                 }
             }
         }
     }
 
-    fn fetch_objects(&self) -> Vec<ObjectData> {
+    // Selects the first ten objects in OAM order that overlap the current line
+    fn scan_oam(&mut self) {
         let object_height = if self.lcdc.sprite_size { 16 } else { 8 };
-        let mut objects: Vec<ObjectData> = vec![];
+        self.line_object_count = 0;
+
         for object in 0..NUMBER_OF_OBJECTS {
-            if objects.len() >= 10 {
+            if self.line_object_count >= OBJECTS_PER_LINE {
                 break;
             }
             let object_address = object * 4;
-            let y = self.oam[object_address].wrapping_sub(16);
-            let object_x = self.oam[object_address + 1] as i16 - 8;
-            if self.line >= y && self.line < y + object_height {
-                let mut tile = self.oam[object_address + 2];
-                let options = self.oam[object_address + 3];
-                if object_height == 16 {
-                    tile &= 0xFE;
-                }
-                objects.push(ObjectData {
-                    x: object_x,
-                    y: y as i16,
-                    tile,
-                    palette: if options & 0x10 != 0 {
-                        ObjectPalette::One
-                    } else {
-                        ObjectPalette::Zero
-                    },
-                    xflip: options & 0x20 != 0,
-                    yflip: options & 0x40 != 0,
-                    priority: options & 0x80 != 0,
-                    cgb_palette: options & 0x07,
-                    bank: options & 0x08 != 0,
-                });
+            let y = self.oam[object_address] as i16 - 16;
+            let line = self.line as i16;
+            if line < y || line >= y + object_height {
+                continue;
             }
+            let options = self.oam[object_address + 3];
+            self.line_objects[self.line_object_count] = ObjectData {
+                index: object as u8,
+                x: self.oam[object_address + 1] as i16 - 8,
+                y,
+                tile: self.oam[object_address + 2],
+                palette: if options & 0x10 != 0 {
+                    ObjectPalette::One
+                } else {
+                    ObjectPalette::Zero
+                },
+                xflip: options & 0x20 != 0,
+                yflip: options & 0x40 != 0,
+                priority: options & 0x80 != 0,
+                cgb_palette: options & 0x07,
+                bank: options & 0x08 != 0,
+            };
+            self.line_object_count += 1;
         }
-        if self.gb_mode == GameBoyMode::Dmg {
-            objects.sort_by_key(|object| object.x);
+
+        let objects = &mut self.line_objects[..self.line_object_count];
+        if self.gb_mode == GameBoyMode::Dmg || self.opri & 0x01 != 0 {
+            objects.sort_unstable_by_key(|object| (object.x, object.index));
+        } else {
+            objects.sort_unstable_by_key(|object| object.index);
         }
-        objects
+    }
+
+    // LCDC.2 is read when the tile is fetched, so a mid-line change affects objects already selected
+    fn object_tile_data_address(&self, object: &ObjectData) -> usize {
+        let (tile, object_height) = if self.lcdc.sprite_size {
+            (object.tile & 0xFE, 16)
+        } else {
+            (object.tile, 8)
+        };
+
+        let row = (self.line as i16 - object.y) as u8 & (object_height - 1);
+        let row = if object.yflip {
+            object_height - 1 - row
+        } else {
+            row
+        };
+
+        tile as usize * 16 + row as usize * 2
     }
 
     fn background_has_priority(&self, priority: bool, offset: usize) -> bool {
@@ -837,15 +1012,39 @@ impl Gpu {
         }
     }
 
-    fn calculate_window_address(&self, y: u8, x: u8) -> u16 {
+    // Returns the screen x the window starts at on this line, or None if it isn't drawn
+    fn window_start(&mut self) -> Option<i16> {
+        let wraps = self.window_wraps;
+        self.window_wraps = false;
+
+        if !self.lcdc.window_display_enabled || !self.window_triggered {
+            return None;
+        }
+
+        // WX=166 draws a single column and then covers the whole of the next line
+        if wraps {
+            return Some(0);
+        }
+
+        match self.window_x {
+            0 => Some(-7 + (self.scroll_x & 0x07) as i16),
+            1..=165 => Some(self.window_x as i16 - 7),
+            166 => {
+                self.window_wraps = true;
+                Some(159)
+            }
+            _ => None,
+        }
+    }
+
+    fn calculate_window_address(&self, y: u8, column: u8) -> u16 {
         let tile_map = if self.lcdc.window_tile_map {
             0x9C00
         } else {
             0x9800
         };
-        let x_offset = x.wrapping_sub(self.window_x.wrapping_sub(7));
 
-        calculate_address(tile_map, y, x_offset)
+        calculate_address(tile_map, y, column)
     }
 
     fn calculate_bg_address(&self, y: u8, x: u8) -> u16 {
@@ -858,6 +1057,19 @@ impl Gpu {
         calculate_address(tile_map, y, x)
     }
 
+    // The SGB's transfer commands read 4KB from the screen: the first 256 background tiles, 20 to
+    // a row from the top left
+    pub fn transfer_data(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(0x1000);
+        for index in 0..256u16 {
+            let address = self.calculate_bg_address((index / 20 * 8) as u8, (index % 20 * 8) as u8);
+            let tile_number = self.vram[(address - VRAM_BEGIN as u16) as usize];
+            let tile = (self.calculate_tile_address(tile_number) - VRAM_BEGIN as u16) as usize;
+            data.extend_from_slice(&self.vram[tile..tile + 16]);
+        }
+        data
+    }
+
     fn calculate_tile_address(&self, tile_number: u8) -> u16 {
         if self.lcdc.bg_window_tile_data {
             return TILESET_FIRST_BEGIN_ADDRESS + tile_number as u16 * 16;
@@ -875,6 +1087,11 @@ impl Gpu {
     }
 }
 
+// The shade a DMG palette gives a colour number
+fn shade(palette: u8, color_index: u8) -> u8 {
+    (palette >> (color_index * 2)) & 0x03
+}
+
 fn calculate_address(address: u16, y: u8, x: u8) -> u16 {
     address + (y as u16 / 8 * 32) + (x as u16 / 8)
 }
@@ -890,14 +1107,3 @@ fn get_color_index(tile_data: u8, tile_color_data: u8, pixel_index: u8) -> u8 {
         0
     }) << 1
 }
-
-pub fn rgb555_to_rgb888(first: u8, second: u8) -> Pixel {
-    let r_5 = first & 0x1F;
-    let g_5 = (first >> 5) | ((second & 0x03) << 3);
-    let b_5 = second >> 2;
-
-    let r = (r_5 << 3) | (r_5 >> 2);
-    let g = (g_5 << 3) | (g_5 >> 2);
-    let b = (b_5 << 3) | (b_5 >> 2);
-    Pixel { r, g, b }
-}
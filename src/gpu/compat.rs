@@ -0,0 +1,209 @@
+use crate::joypad::Key;
+
+// Palettes the CGB boot ROM gives to DMG games, as RGB555 colours
+pub struct CompatPalette {
+    pub bg: [u16; 4],
+    pub obj0: [u16; 4],
+    pub obj1: [u16; 4],
+}
+
+const fn rgb(r: u16, g: u16, b: u16) -> u16 {
+    r | (g << 5) | (b << 10)
+}
+
+const WHITE: u16 = rgb(31, 31, 31);
+const BLACK: u16 = rgb(0, 0, 0);
+
+const PALETTES: [[u16; 4]; 30] = [
+    [WHITE, rgb(31, 21, 12), rgb(16, 6, 0), BLACK],
+    [
+        rgb(31, 28, 24),
+        rgb(25, 19, 16),
+        rgb(16, 13, 5),
+        rgb(11, 6, 1),
+    ],
+    [WHITE, rgb(17, 17, 27), rgb(10, 10, 17), BLACK],
+    [WHITE, rgb(15, 31, 6), rgb(0, 16, 0), BLACK],
+    [WHITE, rgb(31, 16, 16), rgb(18, 7, 7), BLACK],
+    [WHITE, rgb(20, 20, 20), rgb(10, 10, 10), BLACK],
+    [WHITE, rgb(31, 31, 0), rgb(15, 9, 0), BLACK],
+    [WHITE, rgb(15, 31, 0), rgb(22, 14, 0), BLACK],
+    [WHITE, rgb(21, 21, 16), rgb(8, 14, 15), BLACK],
+    [rgb(20, 19, 31), rgb(31, 31, 0), rgb(0, 12, 0), BLACK],
+    [
+        rgb(31, 31, 25),
+        rgb(12, 29, 29),
+        rgb(19, 16, 6),
+        rgb(11, 11, 11),
+    ],
+    [rgb(22, 22, 31), rgb(31, 31, 18), rgb(21, 11, 8), BLACK],
+    [rgb(31, 31, 20), rgb(31, 18, 18), rgb(18, 18, 31), BLACK],
+    [
+        rgb(31, 31, 19),
+        rgb(18, 22, 31),
+        rgb(12, 18, 14),
+        rgb(0, 7, 7),
+    ],
+    [rgb(13, 31, 0), WHITE, rgb(31, 10, 9), BLACK],
+    [rgb(10, 27, 0), rgb(31, 16, 0), rgb(31, 31, 0), WHITE],
+    [WHITE, rgb(31, 14, 0), rgb(18, 8, 0), BLACK],
+    [rgb(31, 24, 8), rgb(31, 26, 0), rgb(18, 7, 0), rgb(9, 0, 0)],
+    [WHITE, rgb(10, 31, 0), rgb(31, 8, 0), BLACK],
+    [rgb(31, 12, 10), rgb(26, 0, 0), rgb(12, 0, 0), BLACK],
+    [WHITE, rgb(31, 19, 0), rgb(31, 0, 0), BLACK],
+    [WHITE, rgb(0, 31, 0), rgb(6, 16, 0), rgb(0, 9, 0)],
+    [WHITE, rgb(11, 23, 31), rgb(31, 0, 0), rgb(0, 0, 31)],
+    [WHITE, rgb(31, 31, 15), rgb(0, 16, 31), rgb(31, 0, 0)],
+    [WHITE, rgb(31, 31, 0), rgb(31, 0, 0), BLACK],
+    [rgb(31, 31, 0), rgb(31, 0, 0), rgb(12, 0, 0), BLACK],
+    [WHITE, rgb(31, 25, 0), rgb(19, 12, 0), BLACK],
+    [BLACK, rgb(0, 16, 16), rgb(31, 27, 0), WHITE],
+    [WHITE, rgb(12, 20, 31), rgb(0, 0, 31), BLACK],
+    [WHITE, rgb(15, 31, 6), rgb(0, 12, 24), BLACK],
+];
+
+const fn combination(obj0: usize, obj1: usize, bg: usize) -> CompatPalette {
+    CompatPalette {
+        bg: PALETTES[bg],
+        obj0: PALETTES[obj0],
+        obj1: PALETTES[obj1],
+    }
+}
+
+// A few entries start one colour before a palette boundary in the boot ROM's table
+const SHIFTED_RED: [u16; 4] = [BLACK, WHITE, rgb(31, 16, 16), rgb(18, 7, 7)];
+const SHIFTED_BLUE: [u16; 4] = [WHITE, WHITE, rgb(12, 20, 31), rgb(0, 0, 31)];
+
+const COMBINATIONS: [CompatPalette; 51] = [
+    combination(4, 4, 29),
+    combination(18, 18, 18),
+    combination(20, 20, 20),
+    combination(24, 24, 24),
+    combination(9, 9, 9),
+    combination(0, 0, 0),
+    combination(27, 27, 27),
+    combination(5, 5, 5),
+    combination(12, 12, 12),
+    combination(26, 26, 26),
+    combination(16, 8, 8),
+    combination(4, 28, 28),
+    combination(4, 2, 2),
+    combination(3, 4, 4),
+    combination(4, 29, 29),
+    combination(28, 4, 28),
+    combination(2, 17, 2),
+    combination(16, 16, 8),
+    combination(4, 4, 7),
+    combination(4, 4, 18),
+    combination(4, 4, 20),
+    combination(19, 19, 9),
+    CompatPalette {
+        bg: PALETTES[11],
+        obj0: SHIFTED_RED,
+        obj1: SHIFTED_RED,
+    },
+    combination(17, 17, 2),
+    combination(4, 4, 2),
+    combination(4, 4, 3),
+    combination(28, 28, 0),
+    combination(3, 3, 0),
+    combination(0, 0, 1),
+    combination(18, 22, 18),
+    combination(20, 22, 20),
+    combination(24, 22, 24),
+    combination(16, 22, 8),
+    combination(17, 4, 13),
+    CompatPalette {
+        bg: PALETTES[14],
+        obj0: SHIFTED_BLUE,
+        obj1: PALETTES[0],
+    },
+    CompatPalette {
+        bg: PALETTES[15],
+        obj0: SHIFTED_BLUE,
+        obj1: PALETTES[4],
+    },
+    combination(19, 22, 9),
+    combination(16, 28, 10),
+    combination(4, 23, 28),
+    combination(17, 22, 2),
+    combination(4, 0, 2),
+    combination(4, 28, 3),
+    combination(28, 3, 0),
+    combination(3, 28, 4),
+    combination(21, 28, 4),
+    combination(3, 28, 0),
+    combination(25, 3, 28),
+    combination(0, 28, 8),
+    combination(4, 3, 28),
+    combination(28, 3, 6),
+    combination(4, 28, 29),
+];
+
+// Title checksums of licensed games, the last entries also need the 4th title letter to match
+const TITLE_CHECKSUMS: [u8; 94] = [
+    0x00, 0x88, 0x16, 0x36, 0xD1, 0xDB, 0xF2, 0x3C, 0x8C, 0x92, 0x3D, 0x5C, 0x58, 0xC9, 0x3E, 0x70,
+    0x1D, 0x59, 0x69, 0x19, 0x35, 0xA8, 0x14, 0xAA, 0x75, 0x95, 0x99, 0x34, 0x6F, 0x15, 0xFF, 0x97,
+    0x4B, 0x90, 0x17, 0x10, 0x39, 0xF7, 0xF6, 0xA2, 0x49, 0x4E, 0x43, 0x68, 0xE0, 0x8B, 0xF0, 0xCE,
+    0x0C, 0x29, 0xE8, 0xB7, 0x86, 0x9A, 0x52, 0x01, 0x9D, 0x71, 0x9C, 0xBD, 0x5D, 0x6D, 0x67, 0x3F,
+    0x6B, 0xB3, 0x46, 0x28, 0xA5, 0xC6, 0xD3, 0x27, 0x61, 0x18, 0x66, 0x6A, 0xBF, 0x0D, 0xF4, 0xB3,
+    0x46, 0x28, 0xA5, 0xC6, 0xD3, 0x27, 0x61, 0x18, 0x66, 0x6A, 0xBF, 0x0D, 0xF4, 0xB3,
+];
+
+const FIRST_AMBIGUOUS_CHECKSUM: usize = 65;
+
+const FOURTH_LETTERS: &[u8; 29] = b"BEFAARBEKEK R-URAR INAILICE R";
+
+const TITLE_PALETTES: [u8; 94] = [
+    0, 4, 5, 35, 34, 3, 31, 15, 10, 5, 19, 36, 7, 37, 30, 44, 21, 32, 31, 20, 5, 33, 13, 14, 5, 29,
+    5, 18, 9, 3, 2, 26, 25, 25, 41, 42, 26, 45, 42, 45, 36, 38, 26, 42, 30, 41, 34, 34, 5, 42, 6,
+    5, 33, 25, 42, 42, 40, 2, 16, 25, 42, 42, 5, 0, 39, 36, 22, 25, 6, 32, 12, 36, 11, 39, 18, 39,
+    24, 31, 50, 17, 46, 6, 27, 0, 47, 41, 41, 0, 0, 19, 34, 23, 18, 29,
+];
+
+// Picks the palette for a DMG cartridge from its header, starting at 0x0100
+pub fn header_palette(header: &[u8]) -> &'static CompatPalette {
+    let old_licensee = header[0x4B];
+    let nintendo = old_licensee == 0x01
+        || (old_licensee == 0x33 && header[0x44] == b'0' && header[0x45] == b'1');
+
+    if !nintendo {
+        return &COMBINATIONS[0];
+    }
+
+    let title = &header[0x34..0x44];
+    let checksum = title.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+
+    let index = TITLE_CHECKSUMS
+        .iter()
+        .enumerate()
+        .position(|(index, &value)| {
+            value == checksum
+                && (index < FIRST_AMBIGUOUS_CHECKSUM
+                    || FOURTH_LETTERS[index - FIRST_AMBIGUOUS_CHECKSUM] == title[3])
+        })
+        .unwrap_or(0);
+
+    &COMBINATIONS[TITLE_PALETTES[index] as usize]
+}
+
+// Palettes chosen by holding a direction, optionally with A or B, while the boot logo is shown
+pub fn button_palette(direction: &Key, modifier: Option<&Key>) -> Option<&'static CompatPalette> {
+    let index = match (direction, modifier) {
+        (Key::Right, None) => 1,
+        (Key::Left, None) => 48,
+        (Key::Up, None) => 5,
+        (Key::Down, None) => 8,
+        (Key::Right, Some(Key::A)) => 0,
+        (Key::Left, Some(Key::A)) => 40,
+        (Key::Up, Some(Key::A)) => 43,
+        (Key::Down, Some(Key::A)) => 3,
+        (Key::Right, Some(Key::B)) => 6,
+        (Key::Left, Some(Key::B)) => 7,
+        (Key::Up, Some(Key::B)) => 28,
+        (Key::Down, Some(Key::B)) => 49,
+        _ => return None,
+    };
+
+    Some(&COMBINATIONS[index])
+}
@@ -0,0 +1,189 @@
+use std::fs;
+use std::path::Path;
+
+use super::Pixel;
+
+const fn pixel(hex: u32) -> Pixel {
+    Pixel {
+        r: (hex >> 16) as u8,
+        g: (hex >> 8) as u8,
+        b: hex as u8,
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DmgPalette {
+    Grey,
+    Green,
+    Pocket,
+    Light,
+    Custom([Pixel; 4]),
+}
+
+impl DmgPalette {
+    // Shades from lightest to darkest
+    pub fn shades(&self) -> [Pixel; 4] {
+        match self {
+            DmgPalette::Grey => [
+                pixel(0xFFFFFF),
+                pixel(0xAAAAAA),
+                pixel(0x555555),
+                pixel(0x000000),
+            ],
+            DmgPalette::Green => [
+                pixel(0x9BBC0F),
+                pixel(0x8BAC0F),
+                pixel(0x306230),
+                pixel(0x0F380F),
+            ],
+            DmgPalette::Pocket => [
+                pixel(0xC4CFA1),
+                pixel(0x8B956D),
+                pixel(0x4D533C),
+                pixel(0x1F1F1F),
+            ],
+            DmgPalette::Light => [
+                pixel(0x00B581),
+                pixel(0x009A71),
+                pixel(0x00694A),
+                pixel(0x004F3B),
+            ],
+            DmgPalette::Custom(shades) => *shades,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DmgPalette::Grey => "Grey",
+            DmgPalette::Green => "Green",
+            DmgPalette::Pocket => "Pocket",
+            DmgPalette::Light => "Light",
+            DmgPalette::Custom(_) => "Custom",
+        }
+    }
+
+    // Reads four hex colours, lightest first, e.g. "#E0F8D0 #88C070 #346856 #081820"
+    pub fn load(path: &Path) -> Option<DmgPalette> {
+        let contents = fs::read_to_string(path).ok()?;
+        let colors: Vec<Pixel> = contents
+            .split_whitespace()
+            .map(|color| u32::from_str_radix(color.trim_start_matches('#'), 16).map(pixel))
+            .collect::<Result<_, _>>()
+            .ok()?;
+
+        match colors[..] {
+            [a, b, c, d] => Some(DmgPalette::Custom([a, b, c, d])),
+            _ => {
+                println!("Palette file needs exactly 4 colours: {}", path.display());
+                None
+            }
+        }
+    }
+}
+
+// Cycles None -> Grey -> Green -> Pocket -> Light -> Custom (if one was loaded) -> None
+pub fn next_dmg_palette(
+    current: Option<DmgPalette>,
+    custom: Option<DmgPalette>,
+) -> Option<DmgPalette> {
+    match current {
+        None => Some(DmgPalette::Grey),
+        Some(DmgPalette::Grey) => Some(DmgPalette::Green),
+        Some(DmgPalette::Green) => Some(DmgPalette::Pocket),
+        Some(DmgPalette::Pocket) => Some(DmgPalette::Light),
+        Some(DmgPalette::Light) => custom,
+        Some(DmgPalette::Custom(_)) => None,
+    }
+}
+
+// Looks a palette up by name, Some(None) being the hardware colours
+pub fn dmg_palette_by_name(name: &str, custom: Option<DmgPalette>) -> Option<Option<DmgPalette>> {
+    match name.to_lowercase().as_str() {
+        "hardware" => Some(None),
+        "grey" | "gray" => Some(Some(DmgPalette::Grey)),
+        "green" => Some(Some(DmgPalette::Green)),
+        "pocket" => Some(Some(DmgPalette::Pocket)),
+        "light" => Some(Some(DmgPalette::Light)),
+        "custom" => custom.map(Some),
+        _ => None,
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ColorCorrection {
+    None,
+    Channels,
+    Gbc,
+    Gba,
+}
+
+impl ColorCorrection {
+    pub fn next(&self) -> ColorCorrection {
+        match self {
+            ColorCorrection::None => ColorCorrection::Channels,
+            ColorCorrection::Channels => ColorCorrection::Gbc,
+            ColorCorrection::Gbc => ColorCorrection::Gba,
+            ColorCorrection::Gba => ColorCorrection::None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorCorrection::None => "None",
+            ColorCorrection::Channels => "Channel mix",
+            ColorCorrection::Gbc => "GBC LCD",
+            ColorCorrection::Gba => "GBA LCD",
+        }
+    }
+
+    pub fn apply(&self, r: u8, g: u8, b: u8) -> Pixel {
+        match self {
+            ColorCorrection::None => Pixel {
+                r: (r << 3) | (r >> 2),
+                g: (g << 3) | (g >> 2),
+                b: (b << 3) | (b >> 2),
+            },
+            ColorCorrection::Channels => {
+                // Integer mix that bleeds the channels into each other like the CGB screen
+                let (r, g, b) = (r as u16, g as u16, b as u16);
+                Pixel {
+                    r: ((r * 13 + g * 2 + b) >> 1).min(255) as u8,
+                    g: ((g * 3 + b) << 1).min(255) as u8,
+                    b: ((r * 3 + g * 2 + b * 11) >> 1).min(255) as u8,
+                }
+            }
+            ColorCorrection::Gbc => mix(
+                (r, g, b),
+                2.2,
+                0.94,
+                [
+                    [0.78824, 0.12157, 0.0],
+                    [0.025, 0.72941, 0.275],
+                    [0.12039, 0.12, 0.82],
+                ],
+            ),
+            ColorCorrection::Gba => mix(
+                (r, g, b),
+                2.5,
+                0.91,
+                [
+                    [0.82, 0.24, -0.06],
+                    [0.125, 0.665, 0.21],
+                    [0.195, 0.075, 0.73],
+                ],
+            ),
+        }
+    }
+}
+
+// Linearises the colour, mixes the channels as the LCD does and re-encodes it for a 2.2 display
+fn mix((r, g, b): (u8, u8, u8), lcd_gamma: f32, luminance: f32, matrix: [[f32; 3]; 3]) -> Pixel {
+    let linear = [r, g, b].map(|channel| (channel as f32 / 31.0).powf(lcd_gamma) * luminance);
+
+    let [r, g, b] = matrix.map(|row| {
+        let value = row[0] * linear[0] + row[1] * linear[1] + row[2] * linear[2];
+        (value.clamp(0.0, 1.0).powf(1.0 / 2.2) * 255.0).round() as u8
+    });
+
+    Pixel { r, g, b }
+}
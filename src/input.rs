@@ -0,0 +1,141 @@
+use crate::joypad::Key;
+
+const KEY_NAMES: [(&str, Key); 8] = [
+    ("up", Key::Up),
+    ("down", Key::Down),
+    ("left", Key::Left),
+    ("right", Key::Right),
+    ("a", Key::A),
+    ("b", Key::B),
+    ("start", Key::Start),
+    ("select", Key::Select),
+];
+
+// A named sequence of steps, each holding some buttons for a number of frames
+pub struct Macro {
+    pub name: String,
+    steps: Vec<(Vec<Key>, u32)>,
+}
+
+impl Macro {
+    // Steps like `A+B+Start+Select:10`, the frame count defaults to 1 and no buttons just waits
+    pub fn parse(name: &str, steps: &[&str]) -> Option<Macro> {
+        let mut parsed = vec![];
+        for step in steps {
+            let (buttons, frames) = step.split_once(':').unwrap_or((step, "1"));
+            let Some(frames) = frames.trim().parse().ok().filter(|&frames| frames > 0) else {
+                println!(
+                    "Bindings: invalid frame count in macro `{}`: {}",
+                    name, step
+                );
+                return None;
+            };
+            let keys = buttons
+                .split('+')
+                .map(str::trim)
+                .filter(|button| !button.is_empty())
+                .map(|button| {
+                    KEY_NAMES
+                        .iter()
+                        .find(|(key_name, _)| key_name.eq_ignore_ascii_case(button))
+                        .map(|(_, key)| *key)
+                })
+                .collect::<Option<Vec<_>>>();
+            let Some(keys) = keys else {
+                println!("Bindings: unknown button in macro `{}`: {}", name, step);
+                return None;
+            };
+            parsed.push((keys, frames));
+        }
+
+        if parsed.is_empty() {
+            println!("Bindings: macro `{}` has no input", name);
+            return None;
+        }
+        Some(Macro {
+            name: name.to_string(),
+            steps: parsed,
+        })
+    }
+}
+
+// Turbo buttons and macros, stepped by emulated frames so they behave the same under
+// fast-forward and replay the same in a movie
+pub struct FrameInput {
+    turbo: Vec<Key>,
+    turbo_frame: u32,
+    // Steps of the playing macro, last first, with the frames left in each
+    steps: Vec<(Vec<Key>, u32)>,
+    // Buttons currently pressed by turbo or a macro
+    pressed: Vec<Key>,
+    last_frame: u64,
+}
+
+impl FrameInput {
+    pub fn new() -> FrameInput {
+        FrameInput {
+            turbo: vec![],
+            turbo_frame: 0,
+            steps: vec![],
+            pressed: vec![],
+            last_frame: 0,
+        }
+    }
+
+    pub fn set_turbo(&mut self, key: Key, held: bool) {
+        if !held {
+            self.turbo.retain(|&turbo| turbo != key);
+        } else if !self.turbo.contains(&key) {
+            // Start on a press so a tap still registers
+            if self.turbo.is_empty() {
+                self.turbo_frame = 0;
+            }
+            self.turbo.push(key);
+        }
+    }
+
+    // Replaces any macro already playing
+    pub fn play(&mut self, input_macro: &Macro) {
+        println!("Macro: {}", input_macro.name);
+        self.steps = input_macro.steps.iter().rev().cloned().collect();
+    }
+
+    // Returns the buttons to press or release once a new frame has started
+    pub fn update(&mut self, frame: u64, turbo_frames: u32) -> Vec<(Key, bool)> {
+        if frame == self.last_frame {
+            return vec![];
+        }
+        self.last_frame = frame;
+
+        let mut wanted = vec![];
+        if let Some((keys, frames)) = self.steps.last_mut() {
+            wanted.extend_from_slice(keys);
+            *frames -= 1;
+            if *frames == 0 {
+                self.steps.pop();
+            }
+        }
+        if !self.turbo.is_empty() {
+            if (self.turbo_frame / turbo_frames).is_multiple_of(2) {
+                let turbo = self.turbo.iter().filter(|key| !wanted.contains(key));
+                wanted.extend(turbo.copied().collect::<Vec<_>>());
+            }
+            self.turbo_frame += 1;
+        }
+
+        let mut events: Vec<(Key, bool)> = self
+            .pressed
+            .iter()
+            .filter(|key| !wanted.contains(key))
+            .map(|&key| (key, false))
+            .collect();
+        events.extend(
+            wanted
+                .iter()
+                .filter(|key| !self.pressed.contains(key))
+                .map(|&key| (key, true)),
+        );
+        self.pressed = wanted;
+        events
+    }
+}
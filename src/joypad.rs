@@ -1,4 +1,4 @@
-#[derive(Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Key {
     Up,
     Down,
@@ -10,10 +10,43 @@ pub enum Key {
     Select,
 }
 
+pub const KEYS: [Key; 8] = [
+    Key::Up,
+    Key::Down,
+    Key::Left,
+    Key::Right,
+    Key::A,
+    Key::B,
+    Key::Start,
+    Key::Select,
+];
+
+impl Key {
+    // Line in the low nibble of P1, and whether it belongs to the action group
+    fn line(&self) -> (u8, bool) {
+        match self {
+            Key::A => (0x01, true),
+            Key::B => (0x02, true),
+            Key::Select => (0x04, true),
+            Key::Start => (0x08, true),
+            Key::Right => (0x01, false),
+            Key::Left => (0x02, false),
+            Key::Up => (0x04, false),
+            Key::Down => (0x08, false),
+        }
+    }
+}
+
 pub struct Joypad {
+    // Active low like the hardware lines, 0 is pressed
     action_buttons: u8,
     direction_buttons: u8,
-    selected_buttons: u8,
+    // P1 bits 4 (directions) and 5 (actions), a group is selected when its bit is 0
+    select: u8,
+    interrupt_requested: bool,
+    // Set by the SGB's MLT_REQ, only the first joypad has buttons pressed
+    pub players: u8,
+    pub player: u8,
 }
 
 impl Joypad {
@@ -21,48 +54,83 @@ impl Joypad {
         Joypad {
             action_buttons: 0x0F,
             direction_buttons: 0x0F,
-            selected_buttons: 0xF0,
+            select: 0x30,
+            interrupt_requested: false,
+            players: 1,
+            player: 0,
         }
     }
 
     pub fn push_button(&mut self, key: Key) {
-        match key {
-            Key::A => self.action_buttons &= 0x01 ^ 0xF,
-            Key::B => self.action_buttons &= 0x02 ^ 0xF,
-            Key::Select => self.action_buttons &= 0x04 ^ 0xF,
-            Key::Start => self.action_buttons &= 0x08 ^ 0xF,
-            Key::Right => self.direction_buttons &= 0x01 ^ 0xF,
-            Key::Left => self.direction_buttons &= 0x02 ^ 0xF,
-            Key::Up => self.direction_buttons &= 0x04 ^ 0xF,
-            Key::Down => self.direction_buttons &= 0x08 ^ 0xF,
+        let before = self.lines();
+        match key.line() {
+            (line, true) => self.action_buttons &= !line,
+            (line, false) => self.direction_buttons &= !line,
         }
+        self.update_interrupt(before);
     }
 
     pub fn release_button(&mut self, key: Key) {
-        match key {
-            Key::A => self.action_buttons |= 0x01,
-            Key::B => self.action_buttons |= 0x02,
-            Key::Select => self.action_buttons |= 0x04,
-            Key::Start => self.action_buttons |= 0x08,
-            Key::Right => self.direction_buttons |= 0x01,
-            Key::Left => self.direction_buttons |= 0x02,
-            Key::Up => self.direction_buttons |= 0x04,
-            Key::Down => self.direction_buttons |= 0x08,
+        match key.line() {
+            (line, true) => self.action_buttons |= line,
+            (line, false) => self.direction_buttons |= line,
         }
     }
 
+    pub fn is_pressed(&self, key: &Key) -> bool {
+        match key.line() {
+            (line, true) => self.action_buttons & line == 0,
+            (line, false) => self.direction_buttons & line == 0,
+        }
+    }
+
+    // Whether P1 selects either button group
+    pub fn selected(&self) -> bool {
+        self.select != 0x30
+    }
+
+    // P10-P13 as the CPU sees them, both groups are ANDed when both are selected
+    pub fn lines(&self) -> u8 {
+        let mut lines = 0x0F;
+        if self.player != 0 {
+            return lines;
+        }
+        if self.select & 0x10 == 0 {
+            lines &= self.direction_buttons;
+        }
+        if self.select & 0x20 == 0 {
+            lines &= self.action_buttons;
+        }
+        lines
+    }
+
+    // With neither group selected the SGB puts the joypad number on the lines, 0x0F for the first
     pub fn read_input(&self) -> u8 {
-        let value = self.selected_buttons & 0x30;
-        match value {
-            0x10 => self.action_buttons,
-            0x20 => self.direction_buttons,
-            0x30 => 0xCF,
-            _ => 0x0,
+        if self.select == 0x30 {
+            return 0xFF - self.player;
         }
+        0xC0 | self.select | self.lines()
     }
 
     pub fn write(&mut self, value: u8) {
-        // println!("Joypad write: {:#04x}", value);
-        self.selected_buttons = value;
+        let before = self.lines();
+        // Deselecting the action buttons moves on to the next joypad
+        if self.players > 1 && self.select & 0x20 == 0 && value & 0x20 != 0 {
+            self.player = (self.player + 1) % self.players;
+        }
+        self.select = value & 0x30;
+        self.update_interrupt(before);
+    }
+
+    // Any line going from high to low requests the joypad interrupt
+    fn update_interrupt(&mut self, before: u8) {
+        if before & !self.lines() != 0 {
+            self.interrupt_requested = true;
+        }
+    }
+
+    // Returns true once per request, polled by the MMU
+    pub fn take_interrupt(&mut self) -> bool {
+        std::mem::take(&mut self.interrupt_requested)
     }
 }
@@ -1,54 +1,177 @@
+mod bindings;
+mod capture;
 mod cartridge;
+mod cheats;
+mod config;
 mod cpu;
+mod debug_window;
+mod debugger;
+mod disassembler;
+mod filter;
+mod font;
+mod gdb;
 mod gpu;
+mod input;
 mod interrupts;
 mod joypad;
+mod menu;
 mod mmu;
+mod model;
+mod movie;
+mod osd;
+mod ram_search;
+mod sgb;
+mod symbols;
 mod timer;
+mod tracer;
 
 use std::fs;
-use std::fs::OpenOptions;
-use std::io::BufWriter;
+use std::path::{Path, PathBuf};
 use std::thread::sleep;
-use std::{fs::File, time::Duration, time::Instant};
+use std::{time::Duration, time::Instant};
 
 use rfd::FileDialog;
 
+use bindings::{Binding, Bindings, Hotkey, Stick};
+use capture::{Format, Recorder};
+use cheats::Cheats;
+use config::Config;
 use cpu::Cpu;
+use debug_window::{DebugWindows, InspectorView, MemoryView, SearchView, VramView};
+use debugger::{Action, Debugger};
+use filter::Filters;
+use gdb::Gdb;
+use gpu::palette::{self, DmgPalette};
+use input::FrameInput;
 use joypad::Key;
+use menu::{Command, Item, Menu};
 use mmu::Memory;
+use movie::Movie;
+use osd::Osd;
+use symbols::Symbols;
+use tracer::Tracer;
 
-use sdl2::event::Event;
+use sdl2::controller::GameController;
+use sdl2::event::{Event, WindowEvent};
 use sdl2::keyboard::Keycode;
-use sdl2::rect::Rect;
-use sdl2::video::Window;
+use sdl2::video::{Window, WindowPos};
 
-const SCALE: u32 = 5;
 const SCREEN_WIDTH: u32 = 160;
 const SCREEN_HEIGHT: u32 = 144;
-const WINDOW_WIDTH: u32 = SCREEN_WIDTH * SCALE;
-const WINDOW_HEIGHT: u32 = SCREEN_HEIGHT * SCALE;
+const FAST_FORWARD_SPEED: f64 = 4.0;
 
-const DEBUG: bool = false;
+// Frontend settings from the command line and files next to the executable
+struct Settings {
+    rom_path: PathBuf,
+    // Kept to start another ROM the same way
+    config: Config,
+    boot_rom: Option<Vec<u8>>,
+    bindings_path: PathBuf,
+    custom_palette: Option<DmgPalette>,
+    bindings: Bindings,
+    start_paused: bool,
+    screenshot_scale: u32,
+    integer_scale: bool,
+    filters: Filters,
+}
 
 fn main() {
-    let log_file = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true)
-        .open("log.txt")
-        .expect("failed to open log file");
-    let mut f = BufWriter::new(&log_file);
+    // `gameboy-emulator disasm <rom> [bank]` prints a ROM bank instead of running it
+    let args: Vec<String> = std::env::args().collect();
+    if args.iter().any(|arg| arg == "--help") {
+        println!("{}", config::USAGE);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("disasm") {
+        let Some(rom) = args.get(2) else {
+            println!("Usage: {} disasm <rom> [bank]", args[0]);
+            return;
+        };
+        let bank = match args.get(3) {
+            Some(bank) => match bank.strip_prefix('$').or(bank.strip_prefix("0x")) {
+                Some(hex) => u16::from_str_radix(hex, 16),
+                None => bank.parse(),
+            },
+            None => Ok(0),
+        };
+        let Ok(bank) = bank else {
+            println!("Invalid bank `{}`", args[3]);
+            println!("Usage: {} disasm <rom> [bank]", args[0]);
+            return;
+        };
+        disassembler::dump_bank(Path::new(rom), bank);
+        return;
+    }
+
+    // `gameboy-emulator bk2 <movie> [bk2]` converts a movie for BizHawk
+    if args.get(1).map(String::as_str) == Some("bk2") {
+        let Some(movie) = args.get(2).map(Path::new) else {
+            println!("Usage: {} bk2 <movie> [bk2]", args[0]);
+            return;
+        };
+        let bk2 = args
+            .get(3)
+            .map_or(movie.with_extension("bk2"), PathBuf::from);
+        movie::export_bk2(movie, &bk2);
+        return;
+    }
+
+    // `--gdb [port]` starts the remote debugging server
+    let gdb = args
+        .iter()
+        .position(|arg| arg == "--gdb")
+        .and_then(|index| {
+            let port = args
+                .get(index + 1)
+                .and_then(|port| port.parse().ok())
+                .unwrap_or(gdb::DEFAULT_PORT);
+            Gdb::new(port)
+        });
+
+    // `--trace [file]` logs every instruction from the start, `--trace-ring <n>` keeps the last n
+    let mut tracer = Tracer::new();
+    if let Some(index) = args.iter().position(|arg| arg == "--trace") {
+        let path = args
+            .get(index + 1)
+            .filter(|path| !path.starts_with("--"))
+            .map_or(tracer::DEFAULT_PATH, String::as_str);
+        tracer.start_file(Path::new(path));
+    }
+    if let Some(index) = args.iter().position(|arg| arg == "--trace-ring") {
+        match args.get(index + 1) {
+            Some(size) => match size.parse() {
+                Ok(size) if size > 0 => tracer.start_ring(size),
+                _ => println!("Invalid trace ring size `{}`", size),
+            },
+            None => println!("--trace-ring needs a value"),
+        }
+    }
+
+    let current_exe = std::env::current_exe().unwrap();
+
+    let exe_path = match current_exe.parent() {
+        Some(path) => path,
+        None => panic!("Failed to get exe path"),
+    };
 
-    let (mut window, sdl_context) = initialize_sdl2();
+    // config.toml holds the defaults, the command line overrides them
+    let mut config = Config::load(&exe_path.join("config.toml"));
+    config.apply_args(&args);
 
-    let file_path = FileDialog::new()
-        .add_filter("Gameboy ROM", &["gb", "gbc"])
-        .pick_file();
+    let (mut window, sdl_context) = initialize_sdl2(config.scale, config.fullscreen);
 
-    let file_path = match file_path {
+    let file_path = match config.rom.clone() {
         Some(path) => path,
-        None => panic!("No file selected"),
+        None => {
+            let file_path = FileDialog::new()
+                .add_filter("Gameboy ROM", &["gb", "gbc"])
+                .pick_file();
+
+            match file_path {
+                Some(path) => path,
+                None => panic!("No file selected"),
+            }
+        }
     };
 
     if let Some(filename) = file_path.file_name().unwrap().to_str() {
@@ -57,160 +180,692 @@ fn main() {
 
     window.raise();
 
-    let cartridge = cartridge::new_cartridge(&file_path);
-
-    let cgb_flag = cartridge.get_cgb_flag();
+    if let Some(save_dir) = &config.save_dir {
+        fs::create_dir_all(save_dir).expect("Failed to create the save directory");
+    }
 
-    let current_exe = std::env::current_exe().unwrap();
+    // An explicitly given boot ROM has to exist, boot.bin next to the executable is optional
+    let boot_rom_contents = match &config.boot_rom {
+        Some(path) => Some(fs::read(path).expect("Failed to read the boot rom")),
+        None => match fs::read(exe_path.join("boot.bin")) {
+            Ok(rom) => Some(rom),
+            Err(_) => {
+                println!("No boot rom found");
+                None
+            }
+        },
+    };
 
-    let exe_path = match current_exe.parent() {
-        Some(path) => path,
-        None => panic!("Failed to get exe path"),
+    let custom_palette = DmgPalette::load(&exe_path.join("palette.txt"));
+    let mut cpu = match load_rom(
+        &file_path,
+        &config,
+        boot_rom_contents.clone(),
+        custom_palette,
+    ) {
+        Ok(cpu) => cpu,
+        Err(error) => {
+            println!("{}", error);
+            std::process::exit(1);
+        }
     };
 
-    let boot_rom = fs::read(exe_path.join("boot.bin"));
+    // The SGB shows a border around the screen
+    let (width, height) = frame_size(&cpu);
+    if (width, height) != (SCREEN_WIDTH, SCREEN_HEIGHT) {
+        _ = window.set_size(width * config.scale, height * config.scale);
+        window.set_position(WindowPos::Centered, WindowPos::Centered);
+    }
 
-    let boot_rom_contents = match boot_rom {
-        Ok(rom) => Some(rom),
-        Err(_) => {
-            println!("No boot rom found");
-            None
-        }
+    // `--record [file]` records a movie from power on, `--play [file]` plays one back read-only
+    let movie_path = |option: &str| {
+        args.iter().position(|arg| arg == option).map(|index| {
+            args.get(index + 1)
+                .filter(|path| !path.starts_with("--"))
+                .map_or(file_path.with_extension("movie"), PathBuf::from)
+        })
+    };
+    let movie = if let Some(path) = movie_path("--record") {
+        Some(Movie::record(&mut cpu, &path, &game_name(&file_path)))
+    } else if let Some(path) = movie_path("--play") {
+        Movie::play(&mut cpu, &path, true)
+    } else {
+        None
+    };
+
+    let bindings_path = exe_path.join("bindings.toml");
+    let settings = Settings {
+        rom_path: file_path.clone(),
+        custom_palette,
+        bindings: Bindings::load(&bindings_path),
+        start_paused: config.paused,
+        screenshot_scale: config.screenshot_scale,
+        integer_scale: !config.stretch,
+        filters: Filters::new(config.filter, config.lcd_grid, config.ghosting),
+        config,
+        boot_rom: boot_rom_contents,
+        bindings_path,
     };
 
-    let mmu = Memory::new(cartridge, boot_rom_contents.clone());
+    sdl2(
+        &mut cpu,
+        window,
+        sdl_context,
+        settings,
+        movie,
+        gdb,
+        &mut tracer,
+    );
+}
 
-    let mut cpu = Cpu::new(mmu);
+// A console with the cartridge in it, past the boot ROM if there isn't one
+fn load_rom(
+    path: &Path,
+    config: &Config,
+    boot_rom: Option<Vec<u8>>,
+    custom_palette: Option<DmgPalette>,
+) -> Result<Cpu, String> {
+    let cartridge = cartridge::new_cartridge(path, &config.save_path(path))?;
+    let mut cpu = Cpu::new(Memory::new(cartridge, boot_rom, config.model));
 
-    if boot_rom_contents.is_none() {
-        match cgb_flag {
-            0x80 | 0xC0 => {
-                cpu.boot_cgb();
-            }
-            _ => {
-                cpu.boot();
-            }
+    // The game shows its own "only for CGB" screen, this only explains why
+    if !cpu.mem.model.is_cgb() && cpu.mem.header()[0x43] == 0xC0 {
+        println!("This cartridge only runs on a CGB");
+    }
+
+    if let Some(name) = &config.palette {
+        match palette::dmg_palette_by_name(name, custom_palette) {
+            Some(palette) => cpu.mem.gpu.set_dmg_palette(palette),
+            None => println!("Unknown palette `{}`", name),
         }
     }
 
-    sdl2(&mut cpu, window, sdl_context, &mut f);
+    // RGBDS writes labels to rom-file-name.sym
+    if let Some(symbols) = Symbols::load(&path.with_extension("sym")) {
+        cpu.mem.symbols = symbols;
+    }
+    cpu.mem.cheats = Cheats::load(&path.with_extension("cht"));
+
+    cpu.skip_boot_rom();
+    Ok(cpu)
 }
 
-fn initialize_sdl2() -> (Window, sdl2::Sdl) {
+fn initialize_sdl2(scale: u32, fullscreen: bool) -> (Window, sdl2::Sdl) {
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
 
     // Create a window
-    let window = video_subsystem
-        .window("Gameboy Emulator", WINDOW_WIDTH, WINDOW_HEIGHT)
-        .position_centered()
-        .build()
-        .unwrap();
+    let mut builder = video_subsystem.window(
+        "Gameboy Emulator",
+        SCREEN_WIDTH * scale,
+        SCREEN_HEIGHT * scale,
+    );
+    builder.position_centered().resizable();
+    if fullscreen {
+        builder.fullscreen_desktop();
+    }
+    let window = builder.build().unwrap();
 
     (window, sdl_context)
 }
 
-fn sdl2(cpu: &mut Cpu, window: Window, sdl_context: sdl2::Sdl, log_file: &mut BufWriter<&File>) {
+fn sdl2(
+    cpu: &mut Cpu,
+    window: Window,
+    sdl_context: sdl2::Sdl,
+    mut settings: Settings,
+    mut movie: Option<Movie>,
+    mut gdb: Option<Gdb>,
+    tracer: &mut Tracer,
+) {
     // Initialize SDL2
 
+    let (mut width, mut height) = frame_size(cpu);
     let mut canvas = window.into_canvas().build().unwrap();
+    // Scales the screen to the window keeping its shape, letterboxed around the edges
+    canvas.set_logical_size(width, height).unwrap();
+    canvas.set_integer_scale(settings.integer_scale).unwrap();
 
-    // Create a texture to render to
+    // Create a texture to render to, remade when the filters or a new ROM change its size. The
+    // logical size follows it so integer scaling stays whole for every texel
     let texture_creator = canvas.texture_creator();
-    let mut texture = texture_creator
-        .create_texture(
-            sdl2::pixels::PixelFormatEnum::RGBA32,
-            sdl2::render::TextureAccess::Streaming,
-            SCREEN_WIDTH,
-            SCREEN_HEIGHT,
-        )
-        .unwrap();
+    let create_texture = |width, height| {
+        texture_creator
+            .create_texture(
+                sdl2::pixels::PixelFormatEnum::RGBA32,
+                sdl2::render::TextureAccess::Streaming,
+                width,
+                height,
+            )
+            .unwrap()
+    };
+    let mut texture = create_texture(width, height);
 
     // Wait for a quit event
     let mut cycles_elapsed_in_frame = 0usize;
     let mut now = Instant::now();
     let mut event_pump = sdl_context.event_pump().unwrap();
+
+    // Buttons held at power on pick a DMG compatibility palette, like on the CGB boot logo
+    event_pump.pump_events();
+    for keycode in event_pump
+        .keyboard_state()
+        .pressed_scancodes()
+        .filter_map(Keycode::from_scancode)
+    {
+        if let Some(Binding::Button(key)) = settings.bindings.key(keycode) {
+            if movie.is_none() {
+                cpu.mem.joypad.push_button(key);
+            }
+        }
+    }
+    cpu.mem.select_compat_palette();
+
+    let mut debugger = Debugger::new();
+    let video_subsystem = sdl_context.video().unwrap();
+    let mut debug_windows = DebugWindows::new();
+
+    // Controllers are opened as they're connected, SDL reports the ones present at startup too
+    let controller_subsystem = sdl_context.game_controller().unwrap();
+    let mut controllers: Vec<GameController> = vec![];
+    let mut stick = Stick::new();
+
+    let mut frame_input = FrameInput::new();
+    let mut paused = settings.start_paused;
+    let mut fast_forward = false;
+    let mut recorder: Option<Recorder> = None;
+    let mut osd = Osd::new(settings.config.show_fps);
+    let mut menu: Option<Menu> = None;
+
     'running: loop {
+        // Bound keys and buttons, pressed or released, applied once the events are handled
+        let mut inputs: Vec<(Binding, bool)> = vec![];
+
         for event in event_pump.poll_iter() {
+            if debug_windows.handle_event(cpu, &event) {
+                continue;
+            }
+            if let Some(open) = menu.as_mut() {
+                if rebind(open, &mut settings, &event, &mut osd) {
+                    continue;
+                }
+            }
             match event {
+                Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } if menu.is_some() => menu = None,
                 Event::Quit { .. }
+                | Event::Window {
+                    win_event: WindowEvent::Close,
+                    ..
+                }
                 | Event::KeyDown {
                     keycode: Some(Keycode::Escape),
                     ..
                 } => break 'running,
                 Event::KeyDown {
                     keycode: Some(keycode),
+                    repeat: false,
                     ..
-                } => match keycode {
-                    // Keycode::Up => cpu.mem.joypad.push_button(Key::Up),
-                    Keycode::Up => key_press(cpu, Key::Up),
-                    Keycode::Down => key_press(cpu, Key::Down),
-                    Keycode::Left => key_press(cpu, Key::Left),
-                    Keycode::Right => key_press(cpu, Key::Right),
-                    Keycode::Z => key_press(cpu, Key::A),
-                    Keycode::X => key_press(cpu, Key::B),
-                    Keycode::Return => key_press(cpu, Key::Start),
-                    Keycode::RShift => key_press(cpu, Key::Select),
-                    _ => {}
-                },
+                } => inputs.extend(
+                    settings
+                        .bindings
+                        .key(keycode)
+                        .map(|binding| (binding, true)),
+                ),
                 Event::KeyUp {
                     keycode: Some(keycode),
                     ..
-                } => match keycode {
-                    Keycode::Up => key_release(cpu, Key::Up),
-                    Keycode::Down => key_release(cpu, Key::Down),
-                    Keycode::Left => key_release(cpu, Key::Left),
-                    Keycode::Right => key_release(cpu, Key::Right),
-                    Keycode::Z => key_release(cpu, Key::A),
-                    Keycode::X => key_release(cpu, Key::B),
-                    Keycode::Return => key_release(cpu, Key::Start),
-                    Keycode::RShift => key_release(cpu, Key::Select),
-                    _ => {}
-                },
+                } => inputs.extend(
+                    settings
+                        .bindings
+                        .key(keycode)
+                        .map(|binding| (binding, false)),
+                ),
+                Event::ControllerDeviceAdded { which, .. } => {
+                    match controller_subsystem.open(which) {
+                        Ok(controller) => {
+                            osd.notify(format!("Controller connected: {}", controller.name()));
+                            controllers.push(controller);
+                        }
+                        Err(error) => println!("Failed to open controller {}: {}", which, error),
+                    }
+                }
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    controllers.retain(|controller| {
+                        if controller.instance_id() == which {
+                            osd.notify(format!("Controller disconnected: {}", controller.name()));
+                        }
+                        controller.instance_id() != which
+                    });
+                }
+                Event::ControllerButtonDown { button, .. } => inputs.extend(
+                    settings
+                        .bindings
+                        .button(button)
+                        .map(|binding| (binding, true)),
+                ),
+                Event::ControllerButtonUp { button, .. } => inputs.extend(
+                    settings
+                        .bindings
+                        .button(button)
+                        .map(|binding| (binding, false)),
+                ),
+                Event::ControllerAxisMotion { axis, value, .. } => {
+                    let threshold = settings.bindings.stick_threshold;
+                    let (released, pressed) = stick.update(axis, value, threshold);
+                    inputs.extend(released.map(|key| (Binding::Button(key), false)));
+                    inputs.extend(pressed.map(|key| (Binding::Button(key), true)));
+                }
                 _ => {}
             }
         }
-        let time_delta = now.elapsed().subsec_nanos();
-        now = Instant::now();
-        let delta = time_delta as f64 / 1_000_000_000_f64;
-        let cycles_to_run = delta * 4190000_f64;
-        // let cycles_to_run = delta * 8000000 as f64;
-        let mut cycles_elapsed = 0;
-        while cycles_elapsed <= cycles_to_run as usize {
-            cycles_elapsed += cpu.step() as usize;
-            if DEBUG {
-                cpu.log(log_file);
+
+        for (binding, pressed) in inputs {
+            // The menu takes the buttons while it's open
+            if let Some(open) = menu.as_mut() {
+                let command = match binding {
+                    Binding::Button(key) if pressed => open.input(key, &settings.rom_path),
+                    Binding::Hotkey(Hotkey::Menu) if pressed => Command::Close,
+                    _ => Command::None,
+                };
+                match command {
+                    Command::None => {}
+                    Command::Close => menu = None,
+                    Command::Select(Item::Reset) => {
+                        reset(cpu, movie.as_mut(), &mut osd);
+                        menu = None;
+                    }
+                    Command::Select(Item::Quit) => break 'running,
+                    Command::Select(item) => change_setting(item, cpu, &mut settings, &mut osd),
+                    Command::LoadRom(path) => {
+                        // A ROM that can't be loaded leaves the running game alone
+                        let loaded = match load_rom(
+                            &path,
+                            &settings.config,
+                            settings.boot_rom.clone(),
+                            settings.custom_palette,
+                        ) {
+                            Ok(loaded) => loaded,
+                            Err(error) => {
+                                osd.notify(error);
+                                continue;
+                            }
+                        };
+                        if let Some(mut stopped) = movie.take() {
+                            stopped.stop(cpu);
+                        }
+                        if let Some(stopped) = recorder.take() {
+                            stopped.stop();
+                        }
+                        // The palette choices carry over like on a reset
+                        let dmg_palette = cpu.mem.gpu.dmg_palette();
+                        let color_correction = cpu.mem.gpu.color_correction();
+                        *cpu = loaded;
+                        cpu.mem.gpu.set_dmg_palette(dmg_palette);
+                        cpu.mem.gpu.set_color_correction(color_correction);
+                        cpu.mem.select_compat_palette();
+
+                        (width, height) = frame_size(cpu);
+                        if let Some(filename) = path.file_name().unwrap().to_str() {
+                            _ = canvas.window_mut().set_title(filename);
+                        }
+                        osd.notify(format!("Loaded {}", game_name(&path)));
+                        settings.rom_path = path;
+                        menu = None;
+                    }
+                }
+                continue;
+            }
+            match binding {
+                Binding::Button(key) => set_button(cpu, movie.as_mut(), key, pressed),
+                Binding::Turbo(key) => frame_input.set_turbo(key, pressed),
+                Binding::Macro(index) => {
+                    if pressed {
+                        frame_input.play(&settings.bindings.macros[index]);
+                    }
+                }
+                Binding::Hotkey(Hotkey::FastForward) => fast_forward = pressed,
+                Binding::Hotkey(_) if !pressed => {}
+                Binding::Hotkey(Hotkey::Pause) => {
+                    paused = !paused;
+                    osd.notify(if paused { "Paused" } else { "Resumed" });
+                }
+                Binding::Hotkey(Hotkey::Screenshot) => {
+                    if save_screenshot(cpu, &settings.rom_path, settings.screenshot_scale) {
+                        osd.notify("Screenshot saved");
+                    }
+                }
+                Binding::Hotkey(Hotkey::Reset) => reset(cpu, movie.as_mut(), &mut osd),
+                // Each stops the movie in progress, or starts a new one
+                Binding::Hotkey(hotkey @ (Hotkey::RecordMovie | Hotkey::PlayMovie)) => {
+                    if let Some(mut stopped) = movie.take() {
+                        let active = stopped.active();
+                        stopped.stop(cpu);
+                        if active {
+                            continue;
+                        }
+                    }
+                    let path = settings.rom_path.with_extension("movie");
+                    movie = if hotkey == Hotkey::RecordMovie {
+                        Some(Movie::record(cpu, &path, &game_name(&settings.rom_path)))
+                    } else {
+                        Movie::play(cpu, &path, true)
+                    };
+                }
+                Binding::Hotkey(Hotkey::ToggleReadOnly) => {
+                    if let Some(movie) = movie.as_mut() {
+                        movie.toggle_read_only();
+                        println!("Movie: {}", movie.status());
+                    }
+                }
+                // Either stops the recording in progress, or starts a new one
+                Binding::Hotkey(hotkey @ (Hotkey::RecordGif | Hotkey::RecordVideo)) => {
+                    if let Some(stopped) = recorder.take() {
+                        stopped.stop();
+                        osd.notify("Recording stopped");
+                        continue;
+                    }
+                    let (format, extension) = match hotkey {
+                        Hotkey::RecordGif => (Format::Gif, "gif"),
+                        _ => (Format::Y4m, "y4m"),
+                    };
+                    let path = capture::capture_path(&settings.rom_path, extension);
+                    recorder = Recorder::start(&path, format, width, height);
+                    if recorder.is_some() {
+                        osd.notify(format!("Recording to {}", extension.to_uppercase()));
+                    }
+                }
+                Binding::Hotkey(Hotkey::NextFilter) => {
+                    let filters = &mut settings.filters;
+                    filters.scaler = filters.scaler.next();
+                    osd.notify(format!("Filter: {}", filters.scaler.name()));
+                }
+                Binding::Hotkey(Hotkey::Menu) => {
+                    // Let go of the buttons so none stay held in the game
+                    for key in joypad::KEYS {
+                        set_button(cpu, movie.as_mut(), key, false);
+                    }
+                    menu = Some(Menu::new());
+                }
+                Binding::Hotkey(Hotkey::NextPalette) => {
+                    next_palette(cpu, settings.custom_palette, &mut osd)
+                }
+                Binding::Hotkey(Hotkey::NextColorCorrection) => {
+                    next_color_correction(cpu, &mut osd)
+                }
+                Binding::Hotkey(Hotkey::VramViewer) => {
+                    debug_windows.toggle(&video_subsystem, Box::new(VramView::new()))
+                }
+                Binding::Hotkey(Hotkey::Inspector) => {
+                    debug_windows.toggle(&video_subsystem, Box::new(InspectorView::new()))
+                }
+                Binding::Hotkey(Hotkey::MemoryEditor) => {
+                    debug_windows.toggle(&video_subsystem, Box::new(MemoryView::new()))
+                }
+                Binding::Hotkey(Hotkey::RamSearch) => {
+                    debug_windows.toggle(&video_subsystem, Box::new(SearchView::new()))
+                }
+                Binding::Hotkey(Hotkey::ToggleCheats) => {
+                    let cheats = &mut cpu.mem.cheats;
+                    cheats.toggle_all();
+                    osd.notify(format!("Cheats: {}", on_off(cheats.enabled)));
+                }
+                Binding::Hotkey(Hotkey::Trace) => {
+                    if tracer.enabled {
+                        tracer.disable();
+                    } else {
+                        tracer.start_file(Path::new(tracer::DEFAULT_PATH));
+                    }
+                }
+                Binding::Hotkey(Hotkey::Debugger) => {
+                    println!("Debugger: paused, type `help` for commands");
+                    debugger.pause();
+                }
             }
         }
-        cycles_elapsed_in_frame += cycles_elapsed;
-        if cycles_elapsed_in_frame >= 70224 {
-            texture
-                .update(
-                    None,
-                    &cpu.mem.gpu.canvas_buffer,
-                    (SCREEN_WIDTH * 4) as usize,
-                )
-                .unwrap();
-            canvas.clear();
-            canvas
-                .copy(
-                    &texture,
-                    None,
-                    Some(Rect::new(0, 0, WINDOW_WIDTH, WINDOW_HEIGHT)),
-                )
-                .unwrap();
-            canvas.present();
+
+        if let Some(gdb) = gdb.as_mut() {
+            gdb.poll(cpu);
+        }
+        // Paused or in the menu, the screen and the debug windows stay live
+        if paused || menu.is_some() {
+            osd.update(0, cpu.mem.gpu.frames);
+            sleep(Duration::from_millis(16));
+            now = Instant::now();
+        } else {
+            let time_delta = now.elapsed().subsec_nanos();
+            now = Instant::now();
+            let delta = time_delta as f64 / 1_000_000_000_f64;
+            let mut cycles_to_run = delta * 4190000_f64;
+            if fast_forward {
+                cycles_to_run *= FAST_FORWARD_SPEED;
+            }
+            // let cycles_to_run = delta * 8000000 as f64;
+            let mut cycles_elapsed = 0;
+            while cycles_elapsed <= cycles_to_run as usize {
+                if gdb.as_mut().is_some_and(|gdb| gdb.check(cpu)) {
+                    break;
+                }
+                if debugger.check(cpu) {
+                    match debugger.prompt(cpu, tracer) {
+                        Action::Quit => break 'running,
+                        Action::Resume => now = Instant::now(),
+                    }
+                }
+                if tracer.enabled {
+                    tracer.trace(cpu);
+                }
+                let cycles = cpu.step() as usize;
+                cycles_elapsed += cycles;
+                let frame = cpu.mem.gpu.frames;
+                if let Some(recorder) = recorder.as_mut() {
+                    recorder.update(frame, cycles, || self::frame(cpu));
+                }
+                for (key, pressed) in frame_input.update(frame, settings.bindings.turbo_frames) {
+                    set_button(cpu, movie.as_mut(), key, pressed);
+                }
+                if let Some(movie) = movie.as_mut() {
+                    movie.update(cpu);
+                }
+            }
+            osd.update(cycles_elapsed, cpu.mem.gpu.frames);
+            cycles_elapsed_in_frame += cycles_elapsed;
+            if cycles_elapsed_in_frame < 70224 {
+                sleep(Duration::from_nanos(2));
+                continue;
+            }
             cycles_elapsed_in_frame = 0;
+        }
+
+        let mut screen = frame(cpu);
+        let mut status = vec![];
+        if let Some(menu) = menu.as_ref() {
+            let value = |item| match item {
+                Item::Palette => Some(palette_name(cpu).to_string()),
+                Item::ColorCorrection => Some(cpu.mem.gpu.color_correction().name().to_string()),
+                Item::Filter => Some(settings.filters.scaler.name().to_string()),
+                Item::LcdGrid => Some(on_off(settings.filters.lcd_grid).to_string()),
+                Item::Ghosting => Some(on_off(settings.filters.ghosting).to_string()),
+                Item::ShowFps => Some(on_off(osd.show_fps).to_string()),
+                _ => None,
+            };
+            menu.draw(&mut screen, width, height, value, &settings.bindings);
         } else {
-            sleep(Duration::from_nanos(2));
+            status.extend(movie.as_ref().map(Movie::status));
+            if paused {
+                status.push("Paused".to_string());
+            }
+            if fast_forward {
+                status.push(format!("Fast-forward {:.0}%", osd.speed()));
+            }
+            if recorder.is_some() {
+                status.push("Recording".to_string());
+            }
+        }
+        osd.draw(&mut screen, width, height, &status);
+
+        let (screen, filtered_width, filtered_height) =
+            settings.filters.apply(&screen, width, height);
+        let query = texture.query();
+        if (query.width, query.height) != (filtered_width, filtered_height) {
+            let old = std::mem::replace(
+                &mut texture,
+                create_texture(filtered_width, filtered_height),
+            );
+            // Textures are freed by hand with unsafe_textures
+            unsafe { old.destroy() };
+            canvas
+                .set_logical_size(filtered_width, filtered_height)
+                .unwrap();
         }
+        texture
+            .update(None, &screen, (filtered_width * 4) as usize)
+            .unwrap();
+        canvas.clear();
+        canvas.copy(&texture, None, None).unwrap();
+        canvas.present();
+        debug_windows.render(cpu);
+    }
+
+    if let Some(mut movie) = movie {
+        movie.stop(cpu);
+    }
+    if let Some(recorder) = recorder {
+        recorder.stop();
+    }
+}
+
+// During a movie input waits for the next frame, otherwise it goes straight to the joypad
+fn set_button(cpu: &mut Cpu, movie: Option<&mut Movie>, key: Key, pressed: bool) {
+    match movie {
+        Some(movie) if movie.active() => movie.set_button(key, pressed),
+        _ if pressed => cpu.mem.joypad.push_button(key),
+        _ => cpu.mem.joypad.release_button(key),
+    }
+}
+
+fn reset(cpu: &mut Cpu, movie: Option<&mut Movie>, osd: &mut Osd) {
+    match movie {
+        Some(movie) if movie.active() => movie.reset(),
+        _ => {
+            cpu.reset();
+            osd.notify("Reset");
+        }
+    }
+}
+
+fn next_palette(cpu: &mut Cpu, custom_palette: Option<DmgPalette>, osd: &mut Osd) {
+    let gpu = &mut cpu.mem.gpu;
+    gpu.set_dmg_palette(palette::next_dmg_palette(gpu.dmg_palette(), custom_palette));
+    osd.notify(format!("DMG palette: {}", palette_name(cpu)));
+}
+
+fn palette_name(cpu: &Cpu) -> &'static str {
+    cpu.mem
+        .gpu
+        .dmg_palette()
+        .map_or("Hardware", |palette| palette.name())
+}
+
+fn next_color_correction(cpu: &mut Cpu, osd: &mut Osd) {
+    let gpu = &mut cpu.mem.gpu;
+    gpu.set_color_correction(gpu.color_correction().next());
+    osd.notify(format!(
+        "Colour correction: {}",
+        gpu.color_correction().name()
+    ));
+}
+
+// A setting changed from the menu
+fn change_setting(item: Item, cpu: &mut Cpu, settings: &mut Settings, osd: &mut Osd) {
+    let filters = &mut settings.filters;
+    match item {
+        Item::Palette => next_palette(cpu, settings.custom_palette, osd),
+        Item::ColorCorrection => next_color_correction(cpu, osd),
+        Item::Filter => {
+            filters.scaler = filters.scaler.next();
+            osd.notify(format!("Filter: {}", filters.scaler.name()));
+        }
+        Item::LcdGrid => {
+            filters.lcd_grid = !filters.lcd_grid;
+            osd.notify(format!("LCD grid: {}", on_off(filters.lcd_grid)));
+        }
+        Item::Ghosting => {
+            filters.ghosting = !filters.ghosting;
+            osd.notify(format!("Ghosting: {}", on_off(filters.ghosting)));
+        }
+        Item::ShowFps => osd.show_fps = !osd.show_fps,
+        _ => {}
+    }
+}
+
+// The menu's next key or controller button is bound to the action it's waiting on, Escape
+// cancels. One that's taken keeps it waiting. Returns whether the event was used
+fn rebind(menu: &mut Menu, settings: &mut Settings, event: &Event, osd: &mut Osd) -> bool {
+    let Some(action) = menu.rebinding else {
+        return false;
+    };
+    let bindings = &mut settings.bindings;
+    let result = match event {
+        Event::KeyDown {
+            keycode: Some(Keycode::Escape),
+            ..
+        } => None,
+        Event::KeyDown {
+            keycode: Some(keycode),
+            repeat: false,
+            ..
+        } => Some(bindings.bind_key(action, *keycode)),
+        Event::ControllerButtonDown { button, .. } => Some(bindings.bind_button(action, *button)),
+        Event::KeyDown { .. } | Event::KeyUp { .. } | Event::ControllerButtonUp { .. } => {
+            return true
+        }
+        _ => return false,
+    };
+    match result {
+        Some(Err(error)) => osd.notify(error),
+        Some(Ok(())) => {
+            menu.rebinding = None;
+            bindings.save_action(&settings.bindings_path, action);
+            osd.notify(format!("Bound to {}", bindings.describe(action)));
+        }
+        None => menu.rebinding = None,
+    }
+    true
+}
+
+fn on_off(on: bool) -> &'static str {
+    if on {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+fn frame_size(cpu: &Cpu) -> (u32, u32) {
+    match cpu.mem.sgb {
+        Some(_) => (sgb::WIDTH, sgb::HEIGHT),
+        None => (SCREEN_WIDTH, SCREEN_HEIGHT),
+    }
+}
+
+// The screen as shown, inside the border on the SGB
+fn frame(cpu: &Cpu) -> Vec<u8> {
+    match cpu.mem.sgb.as_ref() {
+        Some(sgb) => sgb.render(&cpu.mem.gpu),
+        None => cpu.mem.gpu.canvas_buffer.to_vec(),
     }
 }
 
-fn key_press(cpu: &mut Cpu, key: Key) {
-    cpu.mem.joypad.push_button(key);
+fn game_name(rom_path: &Path) -> String {
+    rom_path.file_stem().unwrap().to_string_lossy().into_owned()
 }
 
-fn key_release(cpu: &mut Cpu, key: Key) {
-    cpu.mem.joypad.release_button(key);
+// Saves the screen as a PNG next to the ROM
+fn save_screenshot(cpu: &Cpu, rom_path: &Path, scale: u32) -> bool {
+    let (width, height) = frame_size(cpu);
+    let path = capture::capture_path(rom_path, "png");
+    capture::save_png(&path, &frame(cpu), width, height, scale)
 }
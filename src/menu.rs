@@ -0,0 +1,242 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::bindings::{Binding, Bindings, ACTIONS};
+use crate::font;
+use crate::joypad::Key;
+use crate::osd;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Item {
+    Resume,
+    LoadRom,
+    Palette,
+    ColorCorrection,
+    Filter,
+    LcdGrid,
+    Ghosting,
+    ShowFps,
+    Bindings,
+    Reset,
+    Quit,
+}
+
+const ITEMS: [(&str, Item); 11] = [
+    ("Resume", Item::Resume),
+    ("Load ROM", Item::LoadRom),
+    ("Palette", Item::Palette),
+    ("Colour correction", Item::ColorCorrection),
+    ("Filter", Item::Filter),
+    ("LCD grid", Item::LcdGrid),
+    ("Ghosting", Item::Ghosting),
+    ("Show FPS", Item::ShowFps),
+    ("Key bindings", Item::Bindings),
+    ("Reset", Item::Reset),
+    ("Quit", Item::Quit),
+];
+
+const SELECTED: [u8; 3] = [0xFF, 0xFF, 0xFF];
+const UNSELECTED: [u8; 3] = [0x90, 0x90, 0x90];
+const MARGIN: usize = 4;
+
+// What the emulator should do about an input
+pub enum Command {
+    None,
+    Close,
+    // Change the setting or carry out the action
+    Select(Item),
+    LoadRom(PathBuf),
+}
+
+enum Page {
+    Main,
+    // ROMs in the same directory as the one running
+    Roms(Vec<PathBuf>),
+    Bindings,
+}
+
+// The pause menu, driven by the Game Boy buttons so it works from a controller
+pub struct Menu {
+    page: Page,
+    cursor: usize,
+    // Where to go back to on the main page
+    main_cursor: usize,
+    // Waiting for the next key or controller button to bind to this action
+    pub rebinding: Option<Binding>,
+}
+
+impl Menu {
+    pub fn new() -> Menu {
+        Menu {
+            page: Page::Main,
+            cursor: 0,
+            main_cursor: 0,
+            rebinding: None,
+        }
+    }
+
+    // Up and down move, A picks, left and right change a setting too, B goes back and Start
+    // closes the menu
+    pub fn input(&mut self, key: Key, rom_path: &Path) -> Command {
+        let length = self.len();
+        match key {
+            Key::Up => self.cursor = (self.cursor + length - 1) % length,
+            Key::Down => self.cursor = (self.cursor + 1) % length,
+            Key::Start => return Command::Close,
+            Key::B => match self.page {
+                Page::Main => return Command::Close,
+                _ => self.open(Page::Main, self.main_cursor),
+            },
+            Key::A | Key::Left | Key::Right => return self.select(key, rom_path),
+            Key::Select => {}
+        }
+        Command::None
+    }
+
+    fn select(&mut self, key: Key, rom_path: &Path) -> Command {
+        match &self.page {
+            Page::Main => {
+                let item = ITEMS[self.cursor].1;
+                match item {
+                    Item::Resume if key == Key::A => Command::Close,
+                    Item::LoadRom if key == Key::A => {
+                        let roms = list_roms(rom_path);
+                        let cursor = roms
+                            .iter()
+                            .position(|rom| rom.file_name() == rom_path.file_name())
+                            .unwrap_or(0);
+                        self.main_cursor = self.cursor;
+                        self.open(Page::Roms(roms), cursor);
+                        Command::None
+                    }
+                    Item::Bindings if key == Key::A => {
+                        self.main_cursor = self.cursor;
+                        self.open(Page::Bindings, 0);
+                        Command::None
+                    }
+                    Item::Reset | Item::Quit if key == Key::A => Command::Select(item),
+                    Item::Resume | Item::LoadRom | Item::Bindings | Item::Reset | Item::Quit => {
+                        Command::None
+                    }
+                    _ => Command::Select(item),
+                }
+            }
+            Page::Roms(roms) if key == Key::A => match roms.get(self.cursor) {
+                Some(rom) => Command::LoadRom(rom.clone()),
+                None => Command::None,
+            },
+            Page::Bindings if key == Key::A => {
+                self.rebinding = Some(ACTIONS[self.cursor].1);
+                Command::None
+            }
+            _ => Command::None,
+        }
+    }
+
+    fn open(&mut self, page: Page, cursor: usize) {
+        self.page = page;
+        self.cursor = cursor;
+    }
+
+    fn len(&self) -> usize {
+        match &self.page {
+            Page::Main => ITEMS.len(),
+            // Room for the "no ROMs" line
+            Page::Roms(roms) => roms.len().max(1),
+            Page::Bindings => ACTIONS.len(),
+        }
+    }
+
+    // Over a darkened screen. `value` gives the current setting of an item, if it has one
+    pub fn draw(
+        &self,
+        screen: &mut [u8],
+        width: u32,
+        height: u32,
+        value: impl Fn(Item) -> Option<String>,
+        bindings: &Bindings,
+    ) {
+        let (width, height) = (width as usize, height as usize);
+        for pixel in screen.chunks_mut(4) {
+            for channel in pixel.iter_mut().take(3) {
+                *channel /= 3;
+            }
+        }
+
+        let (title, lines): (&str, Vec<String>) = match &self.page {
+            Page::Main => (
+                "Paused",
+                ITEMS
+                    .iter()
+                    .map(|(label, item)| match value(*item) {
+                        Some(value) => format!("{}: {}", label, value),
+                        None => label.to_string(),
+                    })
+                    .collect(),
+            ),
+            Page::Roms(roms) if roms.is_empty() => ("Load ROM", vec!["No ROMs found".into()]),
+            Page::Roms(roms) => (
+                "Load ROM",
+                roms.iter()
+                    .map(|rom| rom.file_name().unwrap().to_string_lossy().into_owned())
+                    .collect(),
+            ),
+            Page::Bindings => (
+                "Key bindings",
+                ACTIONS
+                    .iter()
+                    .map(|(name, action)| format!("{}: {}", name, bindings.describe(*action)))
+                    .collect(),
+            ),
+        };
+        osd::draw_shadowed(screen, width, MARGIN, MARGIN, title);
+
+        // Scrolls to keep the cursor on screen, leaving a line at the bottom for help
+        let top = MARGIN + font::LINE_HEIGHT * 2;
+        let rows = (height.saturating_sub(top + font::LINE_HEIGHT * 2) / font::LINE_HEIGHT).max(1);
+        let first = self.cursor.saturating_sub(rows - 1);
+        for (row, line) in lines.iter().enumerate().skip(first).take(rows) {
+            let y = top + (row - first) * font::LINE_HEIGHT;
+            let color = if row == self.cursor {
+                font::draw_text(screen, width, MARGIN, y, ">", SELECTED);
+                SELECTED
+            } else {
+                UNSELECTED
+            };
+            font::draw_text(screen, width, MARGIN + font::CHAR_WIDTH * 2, y, line, color);
+        }
+
+        let help = match (self.rebinding, &self.page) {
+            (Some(_), _) => "Press a key or button, Esc cancels",
+            (None, Page::Main) => "A: choose  B: resume",
+            (None, _) => "A: choose  B: back",
+        };
+        osd::draw_shadowed(
+            screen,
+            width,
+            MARGIN,
+            height - MARGIN - font::GLYPH_HEIGHT,
+            help,
+        );
+    }
+}
+
+// Sorted by name
+fn list_roms(rom_path: &Path) -> Vec<PathBuf> {
+    let dir = match rom_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut roms: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "gb" || extension == "gbc")
+        })
+        .collect();
+    roms.sort();
+    roms
+}
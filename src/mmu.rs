@@ -1,7 +1,15 @@
-use crate::cartridge::Cartridge;
-use crate::gpu::{stat::Mode, GameBoyMode, Gpu};
+use std::collections::BTreeMap;
+
+use crate::cartridge::{self, Cartridge};
+use crate::cheats::Cheats;
+use crate::debugger::watchpoints::Watchpoints;
+use crate::disassembler::{self, Disassembly};
+use crate::gpu::{compat, GameBoyMode, Gpu};
 use crate::interrupts::InterruptFlags;
-use crate::joypad::Joypad;
+use crate::joypad::{Joypad, Key};
+use crate::model::Model;
+use crate::sgb::Sgb;
+use crate::symbols::Symbols;
 use crate::timer::{Frequency, Timer};
 
 const ROM_BANK_0_BEGIN: usize = 0x0000;
@@ -78,45 +86,77 @@ pub struct Memory {
     dma_length: u16,
     dma_mode: DmaMode,
     serial: u8,
+    pub watchpoints: Watchpoints,
+    pub symbols: Symbols,
+    // Values written back every VBlank, keyed by address
+    pub freezes: BTreeMap<u16, u8>,
+    pub cheats: Cheats,
+    pub model: Model,
+    pub sgb: Option<Sgb>,
 }
 
 impl Memory {
-    pub fn new(cartridge: Box<dyn Cartridge>, boot: Option<Vec<u8>>) -> Memory {
+    // Without a forced model a CGB is emulated, unless the boot ROM is a 256 byte DMG one or the
+    // game is made for the SGB
+    pub fn new(
+        cartridge: Box<dyn Cartridge>,
+        boot: Option<Vec<u8>>,
+        model: Option<Model>,
+    ) -> Memory {
         let mut divider = Timer::new(Frequency::F16384);
         divider.enabled = true;
 
+        let cgb_flag = cartridge.get_cgb_flag();
+        // SGB games that don't also support the CGB get their borders and colours
+        let sgb_game = cartridge.read(0x0146) == 0x03 && cartridge.read(0x014B) == 0x33;
+        let model = model.unwrap_or(match &boot {
+            Some(boot) if boot.len() == 0x100 => Model::Dmg,
+            None if sgb_game && cgb_flag & 0x80 == 0 => Model::Sgb,
+            _ => Model::Cgb,
+        });
+
         let boot_active;
         let boot_rom;
 
         let gb_mode;
+        let mut key0 = 0;
 
         match boot {
             Some(boot) => {
                 boot_rom = boot;
                 boot_active = true;
-                gb_mode = GameBoyMode::Cgb;
+                // The CGB boot ROM picks the mode itself through KEY0
+                gb_mode = if model.is_cgb() {
+                    GameBoyMode::Cgb
+                } else {
+                    GameBoyMode::Dmg
+                };
             }
             None => {
                 boot_rom = vec![];
                 boot_active = false;
-                gb_mode = match cartridge.get_cgb_flag() {
-                    0x80 | 0xC0 => GameBoyMode::Cgb,
-                    _ => GameBoyMode::Dmg,
-                };
+                gb_mode = model.mode(cgb_flag);
+                if model.is_cgb() {
+                    key0 = match gb_mode {
+                        GameBoyMode::Cgb => cgb_flag,
+                        GameBoyMode::Dmg => 0x04,
+                    };
+                }
             }
         }
 
-        Memory {
+        let mut memory = Memory {
             wram: [0; WRAM_BANK_SIZE * 8],
             hram: [0; HIGH_RAM_SIZE],
             interrupt_enable: InterruptFlags::new(),
             interrupt_flags: InterruptFlags::new(),
             timer: Timer::new(Frequency::F4096),
             divider,
-            gpu: Gpu::new(gb_mode, boot_active),
+            // Only the CGB boot ROM loads compatibility palettes for DMG games
+            gpu: Gpu::new(gb_mode, boot_active && model.is_cgb()),
             cartridge,
             joypad: Joypad::new(),
-            key0: 0,
+            key0,
             wram_bank: 1,
             boot_rom,
             boot_active,
@@ -125,6 +165,76 @@ impl Memory {
             dma_length: 0,
             dma_mode: DmaMode::Gdma,
             serial: 0,
+            watchpoints: Watchpoints::new(),
+            symbols: Symbols::default(),
+            freezes: BTreeMap::new(),
+            cheats: Cheats::new(),
+            model,
+            sgb: None,
+        };
+        if model.is_sgb() {
+            memory.sgb = Some(Sgb::new(&memory.header()));
+        }
+
+        if !memory.boot_active {
+            // What the boot ROM leaves behind in the I/O registers
+            let divider = model.boot_divider();
+            memory.divider.counter = (divider >> 8) as u8;
+            memory.divider.cycles = (divider & 0xFF) as usize;
+            memory.interrupt_flags.vblank = true;
+            memory.gpu.set_bg_palette(0xFC);
+        }
+
+        if !memory.boot_active && model.is_cgb() && memory.gpu.gb_mode == GameBoyMode::Dmg {
+            // Without a boot ROM, do what the CGB boot ROM does for DMG cartridges
+            let header = memory.header();
+            memory
+                .gpu
+                .load_compat_palette(compat::header_palette(&header));
+            memory.gpu.opri = 0x01;
+        }
+
+        memory
+    }
+
+    // The cartridge header from 0x0100 to 0x014F
+    pub fn header(&self) -> Vec<u8> {
+        (0x0100..0x0150)
+            .map(|address| self.cartridge.read(address))
+            .collect()
+    }
+
+    // Power cycles everything but the cartridge, keeping the debugger, cheat and display settings
+    pub fn reset(&mut self) {
+        let mut cartridge = std::mem::replace(&mut self.cartridge, cartridge::empty());
+        cartridge.reset();
+        let boot = (!self.boot_rom.is_empty()).then(|| std::mem::take(&mut self.boot_rom));
+        let old = std::mem::replace(self, Memory::new(cartridge, boot, Some(self.model)));
+
+        self.watchpoints = old.watchpoints;
+        self.symbols = old.symbols;
+        self.freezes = old.freezes;
+        self.cheats = old.cheats;
+        self.gpu.set_dmg_palette(old.gpu.dmg_palette());
+        self.gpu.set_color_correction(old.gpu.color_correction());
+    }
+
+    pub fn select_compat_palette(&mut self) {
+        if self.boot_active || !self.model.is_cgb() || self.gpu.gb_mode != GameBoyMode::Dmg {
+            return;
+        }
+
+        let direction = [Key::Up, Key::Down, Key::Left, Key::Right]
+            .into_iter()
+            .find(|key| self.joypad.is_pressed(key));
+        let modifier = [Key::A, Key::B]
+            .into_iter()
+            .find(|key| self.joypad.is_pressed(key));
+
+        if let Some(direction) = direction {
+            if let Some(palette) = compat::button_palette(&direction, modifier.as_ref()) {
+                self.gpu.load_compat_palette(palette);
+            }
         }
     }
 
@@ -140,6 +250,9 @@ impl Memory {
             self.interrupt_flags.timer = true;
         }
         self.divider.step(cycles);
+        if self.joypad.take_interrupt() {
+            self.interrupt_flags.joypad = true;
+        }
         // let (vblank, lcd) = match self.gpu.step(cycles) {
         //     InterruptRequest::None => (false, false),
         //     InterruptRequest::VBlank => (true, false),
@@ -156,6 +269,8 @@ impl Memory {
 
         if vblank {
             self.interrupt_flags.vblank = true;
+            self.apply_freezes();
+            self.apply_cheats();
         }
 
         if lcd_stat {
@@ -210,6 +325,36 @@ impl Memory {
         self.dma_length = 0;
     }
 
+    fn apply_freezes(&mut self) {
+        let freezes = std::mem::take(&mut self.freezes);
+        for (&address, &value) in freezes.iter() {
+            self.poke_byte(address, value);
+        }
+        self.freezes = freezes;
+    }
+
+    // GameShark codes with a bank write straight into that SRAM or WRAM bank
+    fn apply_cheats(&mut self) {
+        let writes: Vec<_> = self.cheats.writes().collect();
+        for (bank, address, value) in writes {
+            let address = address as usize;
+            match (bank, address) {
+                (Some(bank), EXTERNAL_RAM_BEGIN..=EXTERNAL_RAM_END) => {
+                    let offset = bank as usize * 0x2000 + address - EXTERNAL_RAM_BEGIN;
+                    if let Some(byte) = self.cartridge.ram_mut().get_mut(offset) {
+                        *byte = value;
+                    }
+                }
+                // Types 0x80-0x8F can name banks past the 8 there are
+                (Some(bank), WRAM_BANK_N_BEGIN..=WRAM_BANK_N_END) => {
+                    let bank = (bank & 0x07).max(1) as usize;
+                    self.wram[bank * WRAM_BANK_SIZE + address - WRAM_BANK_N_BEGIN] = value;
+                }
+                _ => self.poke_byte(address as u16, value),
+            }
+        }
+    }
+
     pub fn interrupt_called(&mut self) -> bool {
         (self.interrupt_enable.joypad && self.interrupt_flags.joypad)
             || (self.interrupt_enable.lcd_stat && self.interrupt_flags.lcd_stat)
@@ -218,19 +363,100 @@ impl Memory {
             || (self.interrupt_enable.vblank && self.interrupt_flags.vblank)
     }
 
+    // ROM bank mapped at the address, None outside of ROM
+    pub fn rom_bank_at(&self, address: u16) -> Option<u16> {
+        match address as usize {
+            ROM_BANK_0_BEGIN..=ROM_BANK_0_END => Some(0),
+            ROM_BANK_N_BEGIN..=ROM_BANK_N_END => Some(self.cartridge.rom_bank()),
+            _ => None,
+        }
+    }
+
+    // Bank currently mapped at the address, as used by .sym files
+    pub fn bank_at(&self, address: u16) -> u16 {
+        match address as usize {
+            ROM_BANK_N_BEGIN..=ROM_BANK_N_END => self.cartridge.rom_bank(),
+            VRAM_BEGIN..=VRAM_END => self.gpu.vram_bank as u16,
+            WRAM_BANK_N_BEGIN..=WRAM_BANK_N_END => self.wram_bank as u16,
+            _ => 0,
+        }
+    }
+
+    // Storage behind the banked regions, whatever banks are currently mapped
+    pub fn rom(&self) -> &[u8] {
+        self.cartridge.rom()
+    }
+
+    pub fn sram(&self) -> &[u8] {
+        self.cartridge.ram()
+    }
+
+    pub fn sram_mut(&mut self) -> &mut [u8] {
+        self.cartridge.ram_mut()
+    }
+
+    pub fn wram(&self) -> &[u8] {
+        &self.wram
+    }
+
+    pub fn wram_mut(&mut self) -> &mut [u8] {
+        &mut self.wram
+    }
+
+    // Disassembles through the current memory map
+    pub fn disassemble(&self, address: u16) -> Disassembly {
+        let mut disassembly = disassembler::disassemble(|address| self.peek_byte(address), address);
+        disassembly.symbolize(|operand| {
+            self.symbols
+                .label(self.bank_at(operand), operand)
+                .map(str::to_string)
+        });
+        disassembly
+    }
+
+    // Disassembles ROM as if `bank` were mapped at 0x4000-0x7FFF
+    pub fn disassemble_bank(&self, bank: u16, address: u16) -> Disassembly {
+        let rom = self.cartridge.rom();
+        let mut disassembly = disassembler::disassemble(
+            |address| disassembler::read_rom(rom, bank, address),
+            address,
+        );
+        disassembly.symbolize(|operand| {
+            let operand_bank = match operand as usize {
+                ROM_BANK_N_BEGIN..=ROM_BANK_N_END => bank,
+                _ => self.bank_at(operand),
+            };
+            self.symbols
+                .label(operand_bank, operand)
+                .map(str::to_string)
+        });
+        disassembly
+    }
+
     pub fn read_byte(&self, address: u16) -> u8 {
+        let value = self.peek_byte(address);
+        self.watchpoints.check_read(address, value);
+        value
+    }
+
+    // Reads without triggering watchpoints, for the debugger and instruction fetches
+    pub fn peek_byte(&self, address: u16) -> u8 {
         let address = address as usize;
         match address {
             ROM_BANK_0_BEGIN..=ROM_BANK_0_END => {
                 if self.boot_active && address <= 0xFF {
                     return self.boot_rom[address];
                 }
-                if self.boot_active && (0x200..=0x8FF).contains(&address) {
+                if self.boot_active && (0x200..self.boot_rom.len()).contains(&address) {
                     return self.boot_rom[address];
                 }
-                self.cartridge.read(address as u16)
+                let value = self.cartridge.read(address as u16);
+                self.cheats.patch(address as u16, value)
+            }
+            ROM_BANK_N_BEGIN..=ROM_BANK_N_END => {
+                let value = self.cartridge.read(address as u16);
+                self.cheats.patch(address as u16, value)
             }
-            ROM_BANK_N_BEGIN..=ROM_BANK_N_END => self.cartridge.read(address as u16),
             VRAM_BEGIN..=VRAM_END => self.gpu.read_vram(address - VRAM_BEGIN),
             EXTERNAL_RAM_BEGIN..=EXTERNAL_RAM_END => self.cartridge.read_ram(address as u16),
             WRAM_BANK_0_BEGIN..=WRAM_BANK_0_END => self.wram[address - WRAM_BANK_0_BEGIN],
@@ -253,6 +479,12 @@ impl Memory {
     }
 
     pub fn write_byte(&mut self, address: u16, value: u8) {
+        self.watchpoints.check_write(address, value);
+        self.poke_byte(address, value);
+    }
+
+    // Writes without triggering watchpoints, for edits made from the debug windows
+    pub fn poke_byte(&mut self, address: u16, value: u8) {
         let address = address as usize;
         match address {
             ROM_BANK_0_BEGIN..=ROM_BANK_0_END => {
@@ -288,7 +520,6 @@ impl Memory {
                 self.hram[address - HIGH_RAM_BEGIN] = value;
             }
             INTERRUPT_ENABLE => {
-                println!("Interrupt Enable: {:#04x}", value);
                 self.interrupt_enable.write(value);
             }
             _ => {
@@ -330,23 +561,14 @@ impl Memory {
             0xFF43 => self.gpu.scroll_x,
             0xFF44 => self.gpu.line,
             0xFF45 => self.gpu.line_check,
-            0xFF46 => {
-                println!("DMA Source: {:#06x}", self.dma_source);
-                0
-            }
+            0xFF46 => 0,
             0xFF47 => self.gpu.palettes[0],
             0xFF48 => self.gpu.palettes[1],
             0xFF49 => self.gpu.palettes[2],
             0xFF4A => self.gpu.window_y,
             0xFF4B => self.gpu.window_x,
-            0xFF4C => {
-                println!("Key0 Read: {:#04x}", self.key0);
-                self.key0
-            }
-            0xFF4D => {
-                println!("Speed: {:#04x}", self.gpu.speed);
-                self.gpu.speed
-            }
+            0xFF4C => self.key0,
+            0xFF4D => self.gpu.speed,
             0xFF4F => {
                 // println!("VRAM Bank: {:#04x}", self.gpu.vram_bank);
                 self.gpu.vram_bank | 0xFE
@@ -363,10 +585,7 @@ impl Memory {
                         0x00
                     }
             }
-            0xFF69 => {
-                println!("BGPD: {:#04x}", self.gpu.bgpi);
-                self.gpu.bg_palette[self.gpu.bgpi as usize]
-            }
+            0xFF69 => self.gpu.bg_palette[self.gpu.bgpi as usize],
             0xFF6A => {
                 self.gpu.obpi
                     | if self.gpu.auto_increment_object {
@@ -375,14 +594,8 @@ impl Memory {
                         0x00
                     }
             }
-            0xFF6B => {
-                println!("OBPI: {:#04x}", self.gpu.obpi);
-                println!(
-                    "Palette: {:#04x}",
-                    self.gpu.object_palette[self.gpu.obpi as usize]
-                );
-                self.gpu.object_palette[self.gpu.obpi as usize]
-            }
+            0xFF6B => self.gpu.object_palette[self.gpu.obpi as usize],
+            0xFF6C => self.gpu.opri | 0xFE,
             0xFF70 => self.wram_bank,
             0xFF7E => 0xFF,
             0xFF7F => 0xFF,
@@ -394,7 +607,12 @@ impl Memory {
 
     fn write_io(&mut self, address: usize, value: u8) {
         match address {
-            0xFF00 => self.joypad.write(value),
+            0xFF00 => {
+                self.joypad.write(value);
+                if let Some(sgb) = self.sgb.as_mut() {
+                    sgb.write_joypad(value & 0x30, &mut self.joypad, &self.gpu);
+                }
+            }
             0xFF01 => {
                 self.serial = value;
             }
@@ -426,15 +644,7 @@ impl Memory {
                 // Sound registers
             }
             0xFF40 => {
-                self.gpu.lcdc.write(value);
-                println!("LCDC: {:#04x}", value);
-                if value & 0x80 == 0 {
-                    self.gpu.stat.mode = Mode::HorizontalBlank;
-                    // self.gpu.stat.reset();
-                    // self.gpu.line = 0;
-                    // self.gpu.cycles = 0;
-                    // self.gpu.wly = 0;
-                }
+                self.gpu.write_lcdc(value);
             }
             LCD_STAT => {
                 // println!("LCD Stat: {:#04x}", value);
@@ -469,7 +679,8 @@ impl Memory {
             0xFF4B => {
                 self.gpu.window_x = value;
             }
-            0xFF4C => {
+            // Only the CGB has KEY0
+            0xFF4C if self.model.is_cgb() => {
                 println!("Key0 Write: {:#04x}", value);
                 println!("mode: {:?}", self.gpu.gb_mode);
                 self.gpu.gb_mode = if value == 0x80 || value == 0xC0 {
@@ -486,9 +697,9 @@ impl Memory {
                 self.gpu.vram_bank = value & 0x01;
                 // println!("VRAM Bank: {:#04x}", value);
             }
-            0xFF4D => {
-                // self.gpu.speed = value;
-                println!("Speed: {:#04x}", value)
+            // KEY1, bit 0 arms a speed switch for the next STOP
+            0xFF4D if self.model.is_cgb() => {
+                self.gpu.speed = (self.gpu.speed & 0x80) | (value & 0x01);
             }
             0xFF50 => {
                 // println!("Boot ROM disabled");
@@ -544,7 +755,9 @@ impl Memory {
                 self.gpu.set_cgb_object_palette(value);
                 // println!("OBPD: {:#04x}", value)
             }
-            0xFF6C => {}
+            0xFF6C if self.boot_active => {
+                self.gpu.opri = value & 0x01;
+            }
             0xFF70 => {
                 self.wram_bank = match value & 0x07 {
                     0x01..=0x07 => value & 0x07,
@@ -559,3 +772,17 @@ impl Memory {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpu::palette::DmgPalette;
+
+    #[test]
+    fn dmg_boot_rom_uses_bgp() {
+        let mut memory = Memory::new(cartridge::empty(), Some(vec![0; 0x100]), None);
+        assert_eq!(memory.model, Model::Dmg);
+        memory.write_byte(0xFF47, 0xE4);
+        assert_eq!(memory.gpu.dmg_colors(0), DmgPalette::Grey.shades());
+    }
+}
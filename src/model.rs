@@ -0,0 +1,133 @@
+use crate::gpu::GameBoyMode;
+
+// The console being emulated. Each one leaves different values behind when its boot ROM finishes
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Model {
+    Dmg0,
+    Dmg,
+    Mgb,
+    Sgb,
+    Sgb2,
+    Cgb,
+    Agb,
+}
+
+const NAMES: [(&str, Model); 7] = [
+    ("dmg0", Model::Dmg0),
+    ("dmg", Model::Dmg),
+    ("mgb", Model::Mgb),
+    ("sgb", Model::Sgb),
+    ("sgb2", Model::Sgb2),
+    ("cgb", Model::Cgb),
+    ("agb", Model::Agb),
+];
+
+// Flags in the F register
+const ZERO: u8 = 0x80;
+const HALF_CARRY: u8 = 0x20;
+const CARRY: u8 = 0x10;
+
+pub struct BootRegisters {
+    pub af: u16,
+    pub bc: u16,
+    pub de: u16,
+    pub hl: u16,
+}
+
+impl Model {
+    pub fn from_name(name: &str) -> Option<Model> {
+        NAMES
+            .iter()
+            .find(|(model_name, _)| model_name.eq_ignore_ascii_case(name))
+            .map(|(_, model)| *model)
+    }
+
+    pub fn is_cgb(&self) -> bool {
+        matches!(self, Model::Cgb | Model::Agb)
+    }
+
+    pub fn is_sgb(&self) -> bool {
+        matches!(self, Model::Sgb | Model::Sgb2)
+    }
+
+    // CGB cartridges run in CGB mode on a CGB, everything else in DMG mode
+    pub fn mode(&self, cgb_flag: u8) -> GameBoyMode {
+        if self.is_cgb() && cgb_flag & 0x80 != 0 {
+            GameBoyMode::Cgb
+        } else {
+            GameBoyMode::Dmg
+        }
+    }
+
+    // What the boot ROM leaves in the registers, some of it worked out from the cartridge header
+    pub fn boot_registers(&self, header: &[u8]) -> BootRegisters {
+        // The DMG boot ROM's header check leaves the flags set unless the checksum is 0
+        let checksum_flags = if header[0x4D] == 0 {
+            ZERO
+        } else {
+            ZERO | HALF_CARRY | CARRY
+        };
+        let (a, f, bc, de, hl) = match self {
+            Model::Dmg0 => (0x01, 0x00, 0xFF13, 0x00C1, 0x8403),
+            Model::Dmg => (0x01, checksum_flags, 0x0013, 0x00D8, 0x014D),
+            Model::Mgb => (0xFF, checksum_flags, 0x0013, 0x00D8, 0x014D),
+            Model::Sgb => (0x01, 0x00, 0x0014, 0x0000, 0xC060),
+            Model::Sgb2 => (0xFF, 0x00, 0x0014, 0x0000, 0xC060),
+            Model::Cgb | Model::Agb => {
+                let (b, de, hl) = match self.mode(header[0x43]) {
+                    GameBoyMode::Cgb => (0x00, 0xFF56, 0x000D),
+                    GameBoyMode::Dmg => {
+                        let b = compat_checksum(header);
+                        let hl = if b == 0x43 || b == 0x58 {
+                            0x991A
+                        } else {
+                            0x007C
+                        };
+                        (b, 0x0008, hl)
+                    }
+                };
+                if *self == Model::Cgb {
+                    (0x11, ZERO, (b as u16) << 8, de, hl)
+                } else {
+                    // The AGB boot ROM ends with an extra `inc b`
+                    let b = b.wrapping_add(1);
+                    let mut f = 0;
+                    if b == 0 {
+                        f |= ZERO;
+                    }
+                    if b & 0x0F == 0 {
+                        f |= HALF_CARRY;
+                    }
+                    (0x11, f, (b as u16) << 8, de, hl)
+                }
+            }
+        };
+        BootRegisters {
+            af: (a as u16) << 8 | f as u16,
+            bc,
+            de,
+            hl,
+        }
+    }
+
+    // The divider's internal counter, only known for the models with a fixed length boot. The
+    // SGB and CGB boot ROMs take a variable time so those start from 0
+    pub fn boot_divider(&self) -> u16 {
+        match self {
+            Model::Dmg0 => 0x1830,
+            Model::Dmg | Model::Mgb => 0xABCC,
+            _ => 0x0000,
+        }
+    }
+}
+
+// Sum of the title bytes for Nintendo's own games, which the CGB boot ROM uses to pick a palette
+fn compat_checksum(header: &[u8]) -> u8 {
+    let nintendo = header[0x4B] == 0x01 || (header[0x4B] == 0x33 && &header[0x44..0x46] == b"01");
+    if !nintendo {
+        return 0;
+    }
+    header[0x34..0x44]
+        .iter()
+        .fold(0, |sum: u8, byte| sum.wrapping_add(*byte))
+}
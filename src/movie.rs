@@ -0,0 +1,437 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+
+use crate::cpu::Cpu;
+use crate::joypad::Key;
+
+// Buttons in BizHawk's Game Boy input log order, with their mnemonics. Power is the reset
+const BUTTONS: [(Option<Key>, char); 9] = [
+    (Some(Key::Up), 'U'),
+    (Some(Key::Down), 'D'),
+    (Some(Key::Left), 'L'),
+    (Some(Key::Right), 'R'),
+    (Some(Key::Start), 'S'),
+    (Some(Key::Select), 's'),
+    (Some(Key::B), 'B'),
+    (Some(Key::A), 'A'),
+    (None, 'P'),
+];
+const POWER: u16 = 1 << 8;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Mode {
+    Recording,
+    // Read-write playback hands over to recording as soon as the player presses anything
+    Playing { read_only: bool },
+    Finished,
+}
+
+// Joypad input for every frame from power on, with the SRAM the game started with
+pub struct Movie {
+    pub mode: Mode,
+    path: PathBuf,
+    game: String,
+    rom_sha1: String,
+    boot_rom: bool,
+    sram: Vec<u8>,
+    // One entry per frame, a bit per BUTTONS entry
+    frames: Vec<u16>,
+    pub frame: usize,
+    pub rerecords: u32,
+    // What the player is holding, applied at the next frame
+    held: u16,
+    last_frame: u64,
+    // The player's own save, put back when the movie stops
+    saved_sram: Vec<u8>,
+}
+
+impl Movie {
+    // Power cycles the console and records from the first frame
+    pub fn record(cpu: &mut Cpu, path: &Path, game: &str) -> Movie {
+        let sram = cpu.mem.sram().to_vec();
+        let mut movie = Movie {
+            mode: Mode::Recording,
+            path: path.to_path_buf(),
+            game: game.to_string(),
+            rom_sha1: rom_sha1(cpu),
+            boot_rom: false,
+            saved_sram: sram.clone(),
+            sram,
+            frames: vec![],
+            frame: 0,
+            rerecords: 0,
+            held: 0,
+            last_frame: 0,
+        };
+        movie.power_on(cpu);
+        println!("Movie: recording to {}", path.display());
+        movie
+    }
+
+    // Loads a movie, restores its SRAM and power cycles the console to play it
+    pub fn play(cpu: &mut Cpu, path: &Path, read_only: bool) -> Option<Movie> {
+        let mut movie = match Movie::load(path) {
+            Ok(movie) => movie,
+            Err(error) => {
+                println!("Movie: failed to load {}: {}", path.display(), error);
+                return None;
+            }
+        };
+
+        if rom_sha1(cpu) != movie.rom_sha1.to_uppercase() {
+            println!("Movie: recorded with a different ROM, it will likely desync");
+        }
+
+        movie.mode = Mode::Playing { read_only };
+        movie.saved_sram = cpu.mem.sram().to_vec();
+        let sram = cpu.mem.sram_mut();
+        let length = sram.len().min(movie.sram.len());
+        sram[..length].copy_from_slice(&movie.sram[..length]);
+        movie.power_on(cpu);
+
+        if movie.boot_rom != cpu.mem.boot_active {
+            println!("Movie: recorded with a boot ROM setting that differs, it will likely desync");
+        }
+        println!(
+            "Movie: playing {} frames from {}",
+            movie.frames.len(),
+            path.display()
+        );
+        Some(movie)
+    }
+
+    fn power_on(&mut self, cpu: &mut Cpu) {
+        cpu.reset();
+        self.boot_rom = cpu.mem.boot_active;
+        self.last_frame = cpu.mem.gpu.frames;
+        self.apply(cpu, 0);
+    }
+
+    // True while the movie rather than the player drives the joypad
+    pub fn active(&self) -> bool {
+        self.mode != Mode::Finished
+    }
+
+    pub fn set_button(&mut self, key: Key, pressed: bool) {
+        let bit = 1
+            << BUTTONS
+                .iter()
+                .position(|(button, _)| *button == Some(key))
+                .unwrap();
+        if pressed {
+            self.held |= bit;
+        } else {
+            self.held &= !bit;
+        }
+    }
+
+    // The console is reset at the start of the next frame so the movie can replay it
+    pub fn reset(&mut self) {
+        if self.mode != (Mode::Playing { read_only: true }) {
+            self.held |= POWER;
+        }
+    }
+
+    pub fn toggle_read_only(&mut self) {
+        if let Mode::Playing { read_only } = self.mode {
+            self.mode = Mode::Playing {
+                read_only: !read_only,
+            };
+        }
+    }
+
+    // Input only changes between frames, so playback sees it at the same instruction
+    pub fn update(&mut self, cpu: &mut Cpu) {
+        if cpu.mem.gpu.frames == self.last_frame || !self.active() {
+            return;
+        }
+        self.last_frame = cpu.mem.gpu.frames;
+
+        let input = match self.mode {
+            Mode::Playing { read_only: false } if self.held != 0 => {
+                self.frames.truncate(self.frame);
+                self.rerecords += 1;
+                self.mode = Mode::Recording;
+                println!("Movie: recording from frame {}", self.frame);
+                self.held
+            }
+            Mode::Playing { .. } => match self.frames.get(self.frame) {
+                Some(&input) => input,
+                None => {
+                    self.mode = Mode::Finished;
+                    self.apply(cpu, self.held);
+                    println!("Movie: finished after {} frames", self.frames.len());
+                    return;
+                }
+            },
+            _ => self.held,
+        };
+        if self.mode == Mode::Recording {
+            self.frames.push(input);
+            self.held &= !POWER;
+        }
+        self.frame += 1;
+
+        if input & POWER != 0 {
+            cpu.reset();
+            self.last_frame = cpu.mem.gpu.frames;
+        }
+        self.apply(cpu, input);
+    }
+
+    fn apply(&self, cpu: &mut Cpu, input: u16) {
+        for (index, (key, _)) in BUTTONS.iter().enumerate() {
+            let Some(key) = *key else {
+                continue;
+            };
+            if input & 1 << index != 0 {
+                cpu.mem.joypad.push_button(key);
+            } else {
+                cpu.mem.joypad.release_button(key);
+            }
+        }
+    }
+
+    // Saves a recording and gives the player their own SRAM and controls back
+    pub fn stop(&mut self, cpu: &mut Cpu) {
+        if self.mode == Mode::Recording {
+            match self.save() {
+                Ok(()) => println!(
+                    "Movie: saved {} frames to {}",
+                    self.frames.len(),
+                    self.path.display()
+                ),
+                Err(error) => println!("Movie: failed to save {}: {}", self.path.display(), error),
+            }
+        }
+        self.mode = Mode::Finished;
+
+        let sram = cpu.mem.sram_mut();
+        if sram.len() == self.saved_sram.len() {
+            sram.copy_from_slice(&self.saved_sram);
+        }
+        self.apply(cpu, self.held);
+    }
+
+    // Frame counter for the screen overlay
+    pub fn status(&self) -> String {
+        match self.mode {
+            Mode::Recording => format!("REC {}", self.frame),
+            Mode::Playing { read_only } => format!(
+                "PLAY {}/{} {}",
+                self.frame,
+                self.frames.len(),
+                if read_only { "R/O" } else { "R/W" }
+            ),
+            Mode::Finished => format!("END {}", self.frames.len()),
+        }
+    }
+
+    // A header of `key value` lines, then one `|UDLRSsBAP|` line per frame like BizHawk's input log
+    fn save(&self) -> std::io::Result<()> {
+        let mut contents = String::from("# gameboy-emulator movie\n");
+        writeln!(contents, "game {}", self.game).unwrap();
+        writeln!(contents, "sha1 {}", self.rom_sha1).unwrap();
+        writeln!(contents, "boot_rom {}", self.boot_rom).unwrap();
+        writeln!(contents, "rerecords {}", self.rerecords).unwrap();
+        if !self.sram.is_empty() {
+            let hex: String = self
+                .sram
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect();
+            writeln!(contents, "sram {}", hex).unwrap();
+        }
+        contents.push_str("input\n");
+        for &input in self.frames.iter() {
+            writeln!(contents, "|{}|", input_line(input)).unwrap();
+        }
+        fs::write(&self.path, contents)
+    }
+
+    fn load(path: &Path) -> Result<Movie, String> {
+        let contents = fs::read_to_string(path).map_err(|error| error.to_string())?;
+        let mut movie = Movie {
+            mode: Mode::Finished,
+            path: path.to_path_buf(),
+            game: String::new(),
+            rom_sha1: String::new(),
+            boot_rom: false,
+            sram: vec![],
+            frames: vec![],
+            frame: 0,
+            rerecords: 0,
+            held: 0,
+            last_frame: 0,
+            saved_sram: vec![],
+        };
+
+        let mut lines = contents.lines().filter(|line| !line.starts_with('#'));
+        for line in lines.by_ref() {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "game" => movie.game = value.to_string(),
+                "sha1" => movie.rom_sha1 = value.to_string(),
+                "boot_rom" => movie.boot_rom = value == "true",
+                "rerecords" => movie.rerecords = value.parse().map_err(|_| "invalid rerecords")?,
+                "sram" => {
+                    if value.len() % 2 != 0 || !value.is_ascii() {
+                        return Err("invalid sram".into());
+                    }
+                    movie.sram = (0..value.len() / 2)
+                        .map(|index| u8::from_str_radix(&value[index * 2..index * 2 + 2], 16))
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| "invalid sram")?;
+                }
+                "input" => break,
+                _ => return Err(format!("unknown header `{}`", key)),
+            }
+        }
+        for line in lines {
+            let buttons = line.trim().trim_matches('|');
+            if buttons.chars().count() != BUTTONS.len() {
+                return Err(format!("invalid input line `{}`", line));
+            }
+            let input = buttons
+                .chars()
+                .enumerate()
+                .filter(|&(_, c)| c != '.')
+                .fold(0, |input, (index, _)| input | 1 << index);
+            movie.frames.push(input);
+        }
+        Ok(movie)
+    }
+}
+
+// Upper case hex, as BizHawk writes it
+fn rom_sha1(cpu: &Cpu) -> String {
+    sha1_smol::Sha1::from(cpu.mem.rom())
+        .digest()
+        .to_string()
+        .to_uppercase()
+}
+
+fn input_line(input: u16) -> String {
+    BUTTONS
+        .iter()
+        .enumerate()
+        .map(|(index, (_, mnemonic))| {
+            if input & 1 << index != 0 {
+                *mnemonic
+            } else {
+                '.'
+            }
+        })
+        .collect()
+}
+
+// Converts a movie to a BizHawk .bk2 for its Gambatte core. Frames line up only as far as both
+// emulators agree on when a frame starts, so long movies may need touching up
+pub fn export_bk2(path: &Path, bk2_path: &Path) {
+    let movie = match Movie::load(path) {
+        Ok(movie) => movie,
+        Err(error) => {
+            println!("Movie: failed to load {}: {}", path.display(), error);
+            return;
+        }
+    };
+
+    let mut header = String::new();
+    writeln!(header, "MovieVersion BizHawk v2.0.0").unwrap();
+    writeln!(header, "Platform GB").unwrap();
+    writeln!(header, "GameName {}", movie.game).unwrap();
+    writeln!(header, "SHA1 {}", movie.rom_sha1).unwrap();
+    writeln!(header, "Core Gambatte").unwrap();
+    writeln!(header, "rerecordCount {}", movie.rerecords).unwrap();
+    if !movie.sram.is_empty() {
+        writeln!(header, "StartsFromSaveRam True").unwrap();
+    }
+
+    let mut input = String::from("[Input]\nLogKey:#Up|Down|Left|Right|Start|Select|B|A|Power|\n");
+    for &frame in movie.frames.iter() {
+        writeln!(input, "|{}|", input_line(frame)).unwrap();
+    }
+    input.push_str("[/Input]\n");
+
+    let mut files = vec![
+        ("Header.txt", header.into_bytes()),
+        ("Input Log.txt", input.into_bytes()),
+        ("Comments.txt", vec![]),
+        ("Subtitles.txt", vec![]),
+    ];
+    if !movie.sram.is_empty() {
+        files.push(("SaveRam", movie.sram.clone()));
+    }
+
+    let result = fs::File::create(bk2_path).and_then(|file| {
+        let mut zip = zip::ZipWriter::new(file);
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        for (name, contents) in files {
+            zip.start_file(name, options)?;
+            zip.write_all(&contents)?;
+        }
+        zip.finish()?;
+        Ok(())
+    });
+    match result {
+        Ok(()) => println!(
+            "Exported {} frames to {}",
+            movie.frames.len(),
+            bk2_path.display()
+        ),
+        Err(error) => println!("Failed to write {}: {}", bk2_path.display(), error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn input_lines() {
+        assert_eq!(input_line(0), ".........");
+        assert_eq!(input_line(1 | 1 << 7), "U......A.");
+        assert_eq!(input_line(POWER), "........P");
+        assert_eq!(input_line(0x1FF), "UDLRSsBAP");
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path = std::env::temp_dir().join("movie-test.gbmv");
+        let movie = Movie {
+            mode: Mode::Recording,
+            path: path.clone(),
+            game: "TEST GAME".into(),
+            rom_sha1: "0123456789ABCDEF0123456789ABCDEF01234567".into(),
+            boot_rom: true,
+            sram: vec![0x00, 0x7F, 0xFF],
+            frames: vec![0, 1 << 7, POWER, 0x1FF],
+            frame: 4,
+            rerecords: 3,
+            held: 0,
+            last_frame: 0,
+            saved_sram: vec![],
+        };
+        movie.save().unwrap();
+
+        let loaded = Movie::load(&path).unwrap();
+        assert_eq!(loaded.game, movie.game);
+        assert_eq!(loaded.rom_sha1, movie.rom_sha1);
+        assert!(loaded.boot_rom);
+        assert_eq!(loaded.rerecords, 3);
+        assert_eq!(loaded.sram, movie.sram);
+        assert_eq!(loaded.frames, movie.frames);
+    }
+
+    #[test]
+    fn invalid_sram() {
+        let path = std::env::temp_dir().join("movie-invalid-sram.gbmv");
+        for sram in ["ABC", "\u{e9}", "ZZ"] {
+            fs::write(&path, format!("sram {}\ninput\n", sram)).unwrap();
+            assert!(Movie::load(&path).is_err(), "{}", sram);
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use std::time::{Duration, Instant};
+
+use crate::font;
+
+const MESSAGE_TIME: Duration = Duration::from_secs(2);
+const MAX_MESSAGES: usize = 4;
+const CYCLES_PER_SECOND: f64 = 4194304.0;
+
+// Text drawn over the screen: notifications that fade after a moment, status that stays while it
+// applies, and the frame rate
+pub struct Osd {
+    messages: Vec<(String, Instant)>,
+    pub show_fps: bool,
+    // Counted over a second at a time
+    started: Instant,
+    cycles: usize,
+    frames: u64,
+    last_frame: u64,
+    fps: f64,
+    speed: f64,
+}
+
+impl Osd {
+    pub fn new(show_fps: bool) -> Osd {
+        Osd {
+            messages: vec![],
+            show_fps,
+            started: Instant::now(),
+            cycles: 0,
+            frames: 0,
+            last_frame: 0,
+            fps: 0.0,
+            speed: 0.0,
+        }
+    }
+
+    // Also printed, so the terminal keeps a log
+    pub fn notify(&mut self, text: impl Into<String>) {
+        let text = text.into();
+        println!("{}", text);
+        self.messages.push((text, Instant::now() + MESSAGE_TIME));
+        if self.messages.len() > MAX_MESSAGES {
+            self.messages.remove(0);
+        }
+    }
+
+    // Emulated cycles run and the GPU's frame counter, the game's frame rate rather than the
+    // window's
+    pub fn update(&mut self, cycles: usize, frame: u64) {
+        self.cycles += cycles;
+        // The counter starts again when a ROM is loaded
+        self.frames += frame.saturating_sub(self.last_frame);
+        self.last_frame = frame;
+
+        let elapsed = self.started.elapsed().as_secs_f64();
+        if elapsed >= 1.0 {
+            self.fps = self.frames as f64 / elapsed;
+            self.speed = self.cycles as f64 / elapsed / CYCLES_PER_SECOND * 100.0;
+            self.started = Instant::now();
+            self.cycles = 0;
+            self.frames = 0;
+        }
+    }
+
+    // Percent of the console's speed
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    // Status lines in the top left, the frame rate in the top right, notifications in the bottom
+    // left
+    pub fn draw(&mut self, screen: &mut [u8], width: u32, height: u32, status: &[String]) {
+        let (width, height) = (width as usize, height as usize);
+        for (line, text) in status.iter().enumerate() {
+            draw_shadowed(screen, width, 2, 2 + line * font::LINE_HEIGHT, text);
+        }
+
+        if self.show_fps {
+            let text = format!("{:.0} FPS {:.0}%", self.fps, self.speed);
+            let x = width.saturating_sub(font::text_width(&text) + 1);
+            draw_shadowed(screen, width, x, 2, &text);
+        }
+
+        let now = Instant::now();
+        self.messages.retain(|(_, expires)| *expires > now);
+        let top = height.saturating_sub(self.messages.len() * font::LINE_HEIGHT + 1);
+        for (line, (text, _)) in self.messages.iter().enumerate() {
+            draw_shadowed(screen, width, 2, top + line * font::LINE_HEIGHT, text);
+        }
+    }
+}
+
+// White text with a shadow to keep it readable on any background
+pub fn draw_shadowed(screen: &mut [u8], width: usize, x: usize, y: usize, text: &str) {
+    font::draw_text(screen, width, x + 1, y + 1, text, [0x00, 0x00, 0x00]);
+    font::draw_text(screen, width, x, y, text, [0xFF, 0xFF, 0xFF]);
+}
@@ -0,0 +1,141 @@
+use crate::mmu::Memory;
+
+// Searched as mapped on the bus: SRAM, WRAM and HRAM
+const RANGES: [(u16, u16); 3] = [(0xA000, 0xBFFF), (0xC000, 0xDFFF), (0xFF80, 0xFFFE)];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Size {
+    Byte,
+    Word,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Relation {
+    Unchanged,
+    Changed,
+    Increased,
+    Decreased,
+    EqualTo(i32),
+}
+
+impl Relation {
+    fn holds(&self, current: i32, previous: i32) -> bool {
+        match *self {
+            Relation::Unchanged => current == previous,
+            Relation::Changed => current != previous,
+            Relation::Increased => current > previous,
+            Relation::Decreased => current < previous,
+            Relation::EqualTo(value) => current == value,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Candidate {
+    pub address: u16,
+    // Raw value at the last snapshot
+    pub previous: u16,
+}
+
+// Narrows down the addresses holding a value by comparing successive snapshots
+pub struct RamSearch {
+    size: Size,
+    pub signed: bool,
+    candidates: Vec<Candidate>,
+    pub searches: usize,
+}
+
+impl RamSearch {
+    pub fn new() -> RamSearch {
+        RamSearch {
+            size: Size::Byte,
+            signed: false,
+            candidates: vec![],
+            searches: 0,
+        }
+    }
+
+    // Takes a fresh snapshot with every address as a candidate
+    pub fn reset(&mut self, memory: &Memory) {
+        let width = self.width();
+        self.candidates = RANGES
+            .iter()
+            .filter(|&&(start, _)| start != 0xA000 || !memory.sram().is_empty())
+            .flat_map(|&(start, end)| start..=end + 1 - width)
+            .map(|address| Candidate {
+                address,
+                previous: self.raw(memory, address),
+            })
+            .collect();
+        self.searches = 0;
+    }
+
+    // Keeps the candidates whose current value relates to the last snapshot, then snapshots again
+    pub fn filter(&mut self, memory: &Memory, relation: Relation) {
+        let candidates = std::mem::take(&mut self.candidates);
+        self.candidates = candidates
+            .into_iter()
+            .filter_map(|candidate| {
+                let current = self.raw(memory, candidate.address);
+                relation
+                    .holds(self.value(current), self.value(candidate.previous))
+                    .then_some(Candidate {
+                        address: candidate.address,
+                        previous: current,
+                    })
+            })
+            .collect();
+        self.searches += 1;
+    }
+
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    // Drops the candidates, their values were read at the old width. A reset starts again
+    pub fn set_size(&mut self, size: Size) {
+        if size != self.size {
+            self.size = size;
+            self.candidates.clear();
+            self.searches = 0;
+        }
+    }
+
+    pub fn candidates(&self) -> &[Candidate] {
+        &self.candidates
+    }
+
+    fn width(&self) -> u16 {
+        match self.size {
+            Size::Byte => 1,
+            Size::Word => 2,
+        }
+    }
+
+    // Words are little endian, like the CPU's 16-bit loads
+    pub fn raw(&self, memory: &Memory, address: u16) -> u16 {
+        let low = memory.peek_byte(address) as u16;
+        match self.size {
+            Size::Byte => low,
+            Size::Word => low | (memory.peek_byte(address + 1) as u16) << 8,
+        }
+    }
+
+    pub fn value(&self, raw: u16) -> i32 {
+        match (self.size, self.signed) {
+            (Size::Byte, false) => raw as u8 as i32,
+            (Size::Byte, true) => raw as u8 as i8 as i32,
+            (Size::Word, false) => raw as i32,
+            (Size::Word, true) => raw as i16 as i32,
+        }
+    }
+
+    // Freezes the candidate at its current value, both bytes for words
+    pub fn freeze(&self, memory: &mut Memory, address: u16) {
+        let raw = self.raw(memory, address);
+        memory.freezes.insert(address, raw as u8);
+        if self.size == Size::Word {
+            memory.freezes.insert(address + 1, (raw >> 8) as u8);
+        }
+    }
+}
@@ -0,0 +1,365 @@
+mod border;
+
+use border::Border;
+
+use crate::gpu::Gpu;
+use crate::joypad::Joypad;
+
+// The SNES picture, with the Game Boy screen in the middle
+pub const WIDTH: u32 = 256;
+pub const HEIGHT: u32 = 224;
+const SCREEN_X: usize = 48;
+const SCREEN_Y: usize = 40;
+const SCREEN_WIDTH: usize = 160;
+const SCREEN_HEIGHT: usize = 144;
+
+const PACKET_SIZE: usize = 16;
+// 8x8 cells on the Game Boy screen, each with its own palette
+const COLUMNS: usize = 20;
+const ROWS: usize = 18;
+const CELLS: usize = COLUMNS * ROWS;
+
+// Grey until the game sends its own colours
+const DEFAULT_PALETTE: [u16; 4] = [0x7FFF, 0x56B5, 0x294A, 0x0000];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Mask {
+    None,
+    // Keeps showing the picture from when the mask was set
+    Freeze,
+    Black,
+    Color0,
+}
+
+pub struct Sgb {
+    // The SGB only listens to cartridges that ask for it in their header
+    enabled: bool,
+    // Packets arrive one bit per P1 pulse after a reset pulse
+    receiving: bool,
+    last_select: u8,
+    bit: usize,
+    packet: [u8; PACKET_SIZE],
+    // Packets of the command so far, the first byte says how many there are
+    command: Vec<u8>,
+    // Colours are BGR555 like the CGB's, colour 0 is shared
+    palettes: [[u16; 4]; 4],
+    // 512 palettes sent with PAL_TRN for PAL_SET
+    system_palettes: Vec<[u16; 4]>,
+    attributes: [u8; CELLS],
+    // 45 attribute files sent with ATTR_TRN
+    attribute_files: Vec<[u8; CELLS]>,
+    mask: Mask,
+    frozen: Vec<u8>,
+    border: Border,
+}
+
+impl Sgb {
+    pub fn new(header: &[u8]) -> Sgb {
+        Sgb {
+            enabled: header[0x46] == 0x03 && header[0x4B] == 0x33,
+            receiving: false,
+            last_select: 0x30,
+            bit: 0,
+            packet: [0; PACKET_SIZE],
+            command: vec![],
+            palettes: [DEFAULT_PALETTE; 4],
+            system_palettes: vec![[0; 4]; 512],
+            attributes: [0; CELLS],
+            attribute_files: vec![[0; CELLS]; 45],
+            mask: Mask::None,
+            frozen: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 4],
+            border: Border::new(),
+        }
+    }
+
+    // Both lines low starts a packet, then P14 low sends a 0 and P15 low a 1, each followed by
+    // both high. 128 bits make a packet and a 0 ends it
+    pub fn write_joypad(&mut self, select: u8, joypad: &mut Joypad, gpu: &Gpu) {
+        let last_select = std::mem::replace(&mut self.last_select, select);
+        if !self.enabled {
+            return;
+        }
+        if select == 0x00 {
+            self.receiving = true;
+            self.bit = 0;
+            self.packet = [0; PACKET_SIZE];
+            return;
+        }
+        if !self.receiving || last_select != 0x30 || select == 0x30 {
+            return;
+        }
+
+        let value = select == 0x10;
+        if self.bit == PACKET_SIZE * 8 {
+            self.receiving = false;
+            if !value {
+                self.receive_packet(joypad, gpu);
+            }
+            return;
+        }
+        if value {
+            self.packet[self.bit / 8] |= 1 << (self.bit % 8);
+        }
+        self.bit += 1;
+    }
+
+    fn receive_packet(&mut self, joypad: &mut Joypad, gpu: &Gpu) {
+        if self.command.is_empty() && self.packet[0] & 0x07 == 0 {
+            return;
+        }
+        self.command.extend_from_slice(&self.packet);
+        let packets = (self.command[0] & 0x07) as usize;
+        if self.command.len() >= packets * PACKET_SIZE {
+            let command = std::mem::take(&mut self.command);
+            self.execute(&command, joypad, gpu);
+        }
+    }
+
+    fn execute(&mut self, data: &[u8], joypad: &mut Joypad, gpu: &Gpu) {
+        match data[0] >> 3 {
+            0x00 => self.set_palettes(data, 0, 1),
+            0x01 => self.set_palettes(data, 2, 3),
+            0x02 => self.set_palettes(data, 0, 3),
+            0x03 => self.set_palettes(data, 1, 2),
+            0x04 => self.attribute_block(data),
+            0x05 => self.attribute_lines(data),
+            0x06 => self.attribute_divide(data),
+            0x07 => self.attribute_cells(data),
+            0x0A => self.palette_set(data),
+            0x0B => {
+                for (palette, colors) in gpu.transfer_data().chunks(8).enumerate() {
+                    self.system_palettes[palette] = [0, 1, 2, 3].map(|color| {
+                        u16::from_le_bytes([colors[color * 2], colors[color * 2 + 1]])
+                    });
+                }
+            }
+            // MLT_REQ: 1, 2 or 4 joypads, read one after another through P1
+            0x11 => {
+                joypad.players = match data[1] & 0x03 {
+                    1 => 2,
+                    3 => 4,
+                    _ => 1,
+                };
+                joypad.player = 0;
+            }
+            0x13 => self
+                .border
+                .load_tiles(&gpu.transfer_data(), data[1] & 0x01 != 0),
+            0x14 => self.border.load_map(&gpu.transfer_data()),
+            0x15 => {
+                let data = gpu.transfer_data();
+                for (file, bytes) in self.attribute_files.iter_mut().zip(data.chunks(90)) {
+                    *file = decode_attribute_file(bytes);
+                }
+            }
+            0x16 => {
+                self.attributes = self.attribute_files[(data[1] & 0x3F).min(44) as usize];
+                if data[1] & 0x40 != 0 {
+                    self.mask = Mask::None;
+                }
+            }
+            0x17 => {
+                self.mask = match data[1] & 0x03 {
+                    0 => Mask::None,
+                    1 => Mask::Freeze,
+                    2 => Mask::Black,
+                    _ => Mask::Color0,
+                };
+                if self.mask == Mask::Freeze {
+                    self.frozen = self.colorize(gpu);
+                }
+            }
+            // Sound, SNES memory and the rest only matter to the SNES side
+            _ => {}
+        }
+    }
+
+    // PAL01, PAL23, PAL03 and PAL12: colour 0 for every palette, then 3 colours for each
+    fn set_palettes(&mut self, data: &[u8], first: usize, second: usize) {
+        let color = |index: usize| u16::from_le_bytes([data[1 + index * 2], data[2 + index * 2]]);
+        for palette in self.palettes.iter_mut() {
+            palette[0] = color(0);
+        }
+        for index in 1..4 {
+            self.palettes[first][index] = color(index);
+            self.palettes[second][index] = color(index + 3);
+        }
+    }
+
+    // PAL_SET: four palettes from the ones sent with PAL_TRN, and optionally an attribute file
+    fn palette_set(&mut self, data: &[u8]) {
+        for palette in 0..4 {
+            let number = u16::from_le_bytes([data[1 + palette * 2], data[2 + palette * 2]]);
+            self.palettes[palette] = self.system_palettes[(number & 0x1FF) as usize];
+        }
+        let color0 = self.palettes[0][0];
+        for palette in self.palettes.iter_mut() {
+            palette[0] = color0;
+        }
+        if data[9] & 0x80 != 0 {
+            self.attributes = self.attribute_files[(data[9] & 0x3F).min(44) as usize];
+        }
+        if data[9] & 0x40 != 0 {
+            self.mask = Mask::None;
+        }
+    }
+
+    // ATTR_BLK: rectangles with a palette inside, on the edge and outside
+    fn attribute_block(&mut self, data: &[u8]) {
+        let count = (data[1] & 0x1F) as usize;
+        for block in data[2..].chunks(6).take(count) {
+            if block.len() < 6 {
+                break;
+            }
+            let control = block[0] & 0x07;
+            let inside = block[1] & 0x03;
+            let mut edge = (block[1] >> 2) & 0x03;
+            let outside = (block[1] >> 4) & 0x03;
+            // With only the inside or the outside set, the edge goes with it
+            match control {
+                0x01 => edge = inside,
+                0x04 => edge = outside,
+                _ => {}
+            }
+            let (left, top, right, bottom) = (block[2], block[3], block[4], block[5]);
+
+            for y in 0..ROWS as u8 {
+                for x in 0..COLUMNS as u8 {
+                    let within = (left..=right).contains(&x) && (top..=bottom).contains(&y);
+                    let palette = if x > left && x < right && y > top && y < bottom {
+                        (control & 0x01 != 0).then_some(inside)
+                    } else if within {
+                        (control & 0x02 != 0 || control == 0x01 || control == 0x04).then_some(edge)
+                    } else {
+                        (control & 0x04 != 0).then_some(outside)
+                    };
+                    if let Some(palette) = palette {
+                        self.attributes[y as usize * COLUMNS + x as usize] = palette;
+                    }
+                }
+            }
+        }
+    }
+
+    // ATTR_LIN: whole rows or columns
+    fn attribute_lines(&mut self, data: &[u8]) {
+        let count = data[1] as usize;
+        for &line in data[2..].iter().take(count) {
+            let number = (line & 0x1F) as usize;
+            let palette = (line >> 5) & 0x03;
+            if line & 0x80 != 0 {
+                if number < ROWS {
+                    self.attributes[number * COLUMNS..(number + 1) * COLUMNS].fill(palette);
+                }
+            } else if number < COLUMNS {
+                for row in 0..ROWS {
+                    self.attributes[row * COLUMNS + number] = palette;
+                }
+            }
+        }
+    }
+
+    // ATTR_DIV: splits the screen left and right or top and bottom of a line
+    fn attribute_divide(&mut self, data: &[u8]) {
+        let after = data[1] & 0x03;
+        let before = (data[1] >> 2) & 0x03;
+        let on_line = (data[1] >> 4) & 0x03;
+        let horizontal = data[1] & 0x40 != 0;
+        let line = data[2] as usize;
+
+        for y in 0..ROWS {
+            for x in 0..COLUMNS {
+                let position = if horizontal { y } else { x };
+                self.attributes[y * COLUMNS + x] = match position.cmp(&line) {
+                    std::cmp::Ordering::Less => before,
+                    std::cmp::Ordering::Equal => on_line,
+                    std::cmp::Ordering::Greater => after,
+                };
+            }
+        }
+    }
+
+    // ATTR_CHR: a palette per cell, 4 to a byte, going across or down from a starting cell
+    fn attribute_cells(&mut self, data: &[u8]) {
+        let (mut x, mut y) = (data[1] as usize, data[2] as usize);
+        let count = u16::from_le_bytes([data[3], data[4]]) as usize;
+        let vertical = data[5] & 0x01 != 0;
+
+        for index in 0..count.min(CELLS) {
+            let Some(&byte) = data.get(6 + index / 4) else {
+                break;
+            };
+            if x >= COLUMNS || y >= ROWS {
+                break;
+            }
+            self.attributes[y * COLUMNS + x] = (byte >> (6 - (index % 4) * 2)) & 0x03;
+
+            if vertical {
+                y += 1;
+                if y == ROWS {
+                    y = 0;
+                    x += 1;
+                }
+            } else {
+                x += 1;
+                if x == COLUMNS {
+                    x = 0;
+                    y += 1;
+                }
+            }
+        }
+    }
+
+    // The Game Boy screen in the cells' palettes
+    fn colorize(&self, gpu: &Gpu) -> Vec<u8> {
+        let mut buffer = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 4];
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                let color = match self.mask {
+                    Mask::Black => 0x0000,
+                    Mask::Color0 => self.palettes[0][0],
+                    _ => {
+                        let palette = self.attributes[y / 8 * COLUMNS + x / 8] as usize;
+                        self.palettes[palette][gpu.shades[y * SCREEN_WIDTH + x] as usize]
+                    }
+                };
+                let offset = (y * SCREEN_WIDTH + x) * 4;
+                buffer[offset..offset + 3].copy_from_slice(&color_rgb(color));
+                buffer[offset + 3] = 255;
+            }
+        }
+        buffer
+    }
+
+    // The border with the screen in the middle, RGBA like the GPU's canvas
+    pub fn render(&self, gpu: &Gpu) -> Vec<u8> {
+        let mut buffer = vec![0; (WIDTH * HEIGHT * 4) as usize];
+        self.border.draw(&mut buffer, self.palettes[0][0]);
+
+        let screen = match self.mask {
+            Mask::Freeze => self.frozen.clone(),
+            _ => self.colorize(gpu),
+        };
+        for (y, row) in screen.chunks(SCREEN_WIDTH * 4).enumerate() {
+            let offset = ((SCREEN_Y + y) * WIDTH as usize + SCREEN_X) * 4;
+            buffer[offset..offset + row.len()].copy_from_slice(row);
+        }
+        buffer
+    }
+}
+
+// 2 bits per cell, 4 cells to a byte with the first in the top bits
+fn decode_attribute_file(bytes: &[u8]) -> [u8; CELLS] {
+    let mut attributes = [0; CELLS];
+    for (cell, attribute) in attributes.iter_mut().enumerate() {
+        *attribute = (bytes[cell / 4] >> (6 - (cell % 4) * 2)) & 0x03;
+    }
+    attributes
+}
+
+fn color_rgb(color: u16) -> [u8; 3] {
+    [0, 5, 10].map(|shift| {
+        let value = ((color >> shift) & 0x1F) as u8;
+        (value << 3) | (value >> 2)
+    })
+}
@@ -0,0 +1,81 @@
+use super::{color_rgb, HEIGHT, WIDTH};
+
+const TILE_SIZE: usize = 32;
+const MAP_WIDTH: usize = 32;
+
+// The SNES picture around the Game Boy screen, sent with CHR_TRN and PCT_TRN
+pub struct Border {
+    // 256 SNES 4bpp tiles
+    tiles: Vec<u8>,
+    // Tile number, palette and flips for each 8x8 cell
+    map: Vec<u16>,
+    // Palettes 4-7, colour 0 is transparent
+    palettes: [[u16; 16]; 4],
+}
+
+impl Border {
+    pub fn new() -> Border {
+        Border {
+            tiles: vec![0; 256 * TILE_SIZE],
+            map: vec![0; MAP_WIDTH * MAP_WIDTH],
+            palettes: [[0; 16]; 4],
+        }
+    }
+
+    // CHR_TRN sends half of the tiles at a time
+    pub fn load_tiles(&mut self, data: &[u8], upper: bool) {
+        let start = if upper { 128 * TILE_SIZE } else { 0 };
+        self.tiles[start..start + 128 * TILE_SIZE].copy_from_slice(&data[..128 * TILE_SIZE]);
+    }
+
+    // PCT_TRN sends the map followed by the palettes
+    pub fn load_map(&mut self, data: &[u8]) {
+        for (index, entry) in self.map.iter_mut().enumerate() {
+            *entry = u16::from_le_bytes([data[index * 2], data[index * 2 + 1]]);
+        }
+        for (index, palette) in self.palettes.iter_mut().enumerate() {
+            for (color, value) in palette.iter_mut().enumerate() {
+                let offset = 0x800 + index * 32 + color * 2;
+                *value = u16::from_le_bytes([data[offset], data[offset + 1]]);
+            }
+        }
+    }
+
+    // Transparent pixels show the backdrop, which is SGB colour 0
+    pub fn draw(&self, buffer: &mut [u8], backdrop: u16) {
+        for y in 0..HEIGHT as usize {
+            for x in 0..WIDTH as usize {
+                let entry = self.map[y / 8 * MAP_WIDTH + x / 8];
+                let tile = (entry & 0xFF) as usize * TILE_SIZE;
+                let column = if entry & 0x4000 != 0 {
+                    x % 8
+                } else {
+                    7 - x % 8
+                };
+                let row = if entry & 0x8000 != 0 {
+                    7 - y % 8
+                } else {
+                    y % 8
+                };
+
+                let planes = [
+                    self.tiles[tile + row * 2],
+                    self.tiles[tile + row * 2 + 1],
+                    self.tiles[tile + 16 + row * 2],
+                    self.tiles[tile + 16 + row * 2 + 1],
+                ];
+                let color = planes.iter().enumerate().fold(0, |color, (plane, bits)| {
+                    color | ((bits >> column) & 0x01) << plane
+                });
+
+                let value = match color {
+                    0 => backdrop,
+                    _ => self.palettes[((entry >> 10) & 0x03) as usize][color as usize],
+                };
+                let offset = (y * WIDTH as usize + x) * 4;
+                buffer[offset..offset + 3].copy_from_slice(&color_rgb(value));
+                buffer[offset + 3] = 255;
+            }
+        }
+    }
+}
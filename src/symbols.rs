@@ -0,0 +1,134 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+// Labels from an RGBDS .sym file, keyed by bank and address
+#[derive(Default)]
+pub struct Symbols {
+    labels: BTreeMap<(u16, u16), String>,
+    addresses: HashMap<String, (u16, u16)>,
+}
+
+impl Symbols {
+    // Lines look like "01:4000 Main.loop", comments start with ';'
+    pub fn load(path: &Path) -> Option<Symbols> {
+        let contents = fs::read_to_string(path).ok()?;
+        let symbols = Symbols::parse(&contents);
+        println!(
+            "Loaded {} symbols from {}",
+            symbols.addresses.len(),
+            path.display()
+        );
+        Some(symbols)
+    }
+
+    fn parse(contents: &str) -> Symbols {
+        let mut symbols = Symbols::default();
+
+        for line in contents.lines() {
+            let line = line.split(';').next().unwrap_or("").trim();
+            let Some((location, name)) = line.split_once(char::is_whitespace) else {
+                continue;
+            };
+            let Some((bank, address)) = location.split_once(':') else {
+                continue;
+            };
+            let (Ok(bank), Ok(address)) = (
+                u16::from_str_radix(bank, 16),
+                u16::from_str_radix(address, 16),
+            ) else {
+                continue;
+            };
+
+            let name = name.trim().to_string();
+            // Keep the first label when several share an address
+            symbols
+                .labels
+                .entry((bank, address))
+                .or_insert_with(|| name.clone());
+            symbols.addresses.insert(name, (bank, address));
+        }
+        symbols
+    }
+
+    pub fn label(&self, bank: u16, address: u16) -> Option<&str> {
+        self.labels.get(&(bank, address)).map(String::as_str)
+    }
+
+    // Nearest label at or before the address in the same bank and memory region, e.g.
+    // "Main.loop+3". Bank 0 holds ROM0, WRAM0 and HRAM labels alike
+    pub fn describe(&self, bank: u16, address: u16) -> Option<String> {
+        let ((_, label_address), name) = self
+            .labels
+            .range((bank, region_start(address))..=(bank, address))
+            .next_back()?;
+        match address - label_address {
+            0 => Some(name.clone()),
+            offset => Some(format!("{}+{}", name, offset)),
+        }
+    }
+
+    pub fn address(&self, name: &str) -> Option<(u16, u16)> {
+        self.addresses.get(name).copied()
+    }
+}
+
+fn region_start(address: u16) -> u16 {
+    match address {
+        0x0000..=0x3FFF => 0x0000,
+        0x4000..=0x7FFF => 0x4000,
+        0x8000..=0x9FFF => 0x8000,
+        0xA000..=0xBFFF => 0xA000,
+        0xC000..=0xCFFF => 0xC000,
+        0xD000..=0xDFFF => 0xD000,
+        0xE000..=0xFDFF => 0xE000,
+        0xFE00..=0xFEFF => 0xFE00,
+        0xFF00..=0xFF7F => 0xFF00,
+        0xFF80..=0xFFFE => 0xFF80,
+        0xFFFF => 0xFFFF,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SYM: &str = "; File generated by rgblink
+00:0150 Start
+00:0150 Init ; a second label at the same address
+00:0200 Main.loop
+01:4000 Bank1Code
+00:c000 wVariable
+00:ff80 hOAMDMA
+";
+
+    #[test]
+    fn parse() {
+        let symbols = Symbols::parse(SYM);
+        assert_eq!(symbols.label(0x00, 0x0150), Some("Start"));
+        assert_eq!(symbols.address("Init"), Some((0x00, 0x0150)));
+        assert_eq!(symbols.address("Main.loop"), Some((0x00, 0x0200)));
+        assert_eq!(symbols.address("hOAMDMA"), Some((0x00, 0xFF80)));
+        assert_eq!(symbols.address("Missing"), None);
+    }
+
+    #[test]
+    fn describe() {
+        let symbols = Symbols::parse(SYM);
+        assert_eq!(symbols.describe(0x00, 0x0200).as_deref(), Some("Main.loop"));
+        assert_eq!(
+            symbols.describe(0x00, 0x0203).as_deref(),
+            Some("Main.loop+3")
+        );
+        assert_eq!(
+            symbols.describe(0x01, 0x4010).as_deref(),
+            Some("Bank1Code+16")
+        );
+        assert_eq!(symbols.describe(0x00, 0xFF84).as_deref(), Some("hOAMDMA+4"));
+        // Nothing before the address in its own bank or region
+        assert_eq!(symbols.describe(0x00, 0x0100), None);
+        assert_eq!(symbols.describe(0x02, 0x4010), None);
+        assert_eq!(symbols.describe(0x00, 0xFF00), None);
+        assert_eq!(symbols.describe(0x00, 0x4000), None);
+    }
+}
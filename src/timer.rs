@@ -21,7 +21,7 @@ pub struct Timer {
     pub counter: u8,
     pub modulo: u8,
     pub enabled: bool,
-    cycles: usize,
+    pub cycles: usize,
     has_overflowed: bool,
 }
 
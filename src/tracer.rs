@@ -0,0 +1,240 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::thread;
+
+use crate::cpu::Cpu;
+use crate::symbols::Symbols;
+
+pub const DEFAULT_PATH: &str = "trace.txt";
+const CRASH_PATH: &str = "crash-trace.txt";
+
+#[derive(Copy, Clone, Debug)]
+pub enum Trigger {
+    // Inclusive PC range
+    Address(u16, u16),
+    Frame(u64),
+}
+
+impl Trigger {
+    fn fired(&self, cpu: &Cpu) -> bool {
+        match *self {
+            Trigger::Address(start, end) => (start..=end).contains(&cpu.pc()),
+            Trigger::Frame(frame) => cpu.mem.gpu.frames >= frame,
+        }
+    }
+}
+
+// Writes one Gameboy Doctor line per instruction, either to a file or to a ring buffer
+pub struct Tracer {
+    pub enabled: bool,
+    // Set once the start trigger fires
+    active: bool,
+    output: Option<BufWriter<File>>,
+    pub start: Option<Trigger>,
+    pub stop: Option<Trigger>,
+    pub bank: Option<u16>,
+    // Formatted only when written out, tracing runs every instruction
+    ring: VecDeque<Snapshot>,
+    ring_size: usize,
+}
+
+impl Tracer {
+    pub fn new() -> Tracer {
+        Tracer {
+            enabled: false,
+            active: false,
+            output: None,
+            start: None,
+            stop: None,
+            bank: None,
+            ring: VecDeque::new(),
+            ring_size: 0,
+        }
+    }
+
+    // Traces to a file, replacing any ring buffer
+    pub fn start_file(&mut self, path: &Path) {
+        match File::create(path) {
+            Ok(file) => {
+                self.output = Some(BufWriter::new(file));
+                self.ring_size = 0;
+                self.ring = VecDeque::new();
+                self.enable();
+                println!("Trace: writing to {}", path.display());
+            }
+            Err(error) => println!("Trace: failed to create {}: {}", path.display(), error),
+        }
+    }
+
+    // Keeps only the last `size` instructions in memory
+    pub fn start_ring(&mut self, size: usize) {
+        self.finish_file();
+        self.ring_size = size.max(1);
+        self.ring = VecDeque::with_capacity(self.ring_size);
+        self.enable();
+        println!("Trace: keeping the last {} instructions", self.ring_size);
+    }
+
+    fn enable(&mut self) {
+        self.enabled = true;
+        self.active = false;
+    }
+
+    pub fn disable(&mut self) {
+        self.enabled = false;
+        self.active = false;
+        self.finish_file();
+        println!("Trace: stopped");
+    }
+
+    fn finish_file(&mut self) {
+        if let Some(mut output) = self.output.take() {
+            output.flush().expect("failed to write trace");
+        }
+    }
+
+    pub fn dump(&self, path: &Path, symbols: &Symbols) {
+        match self.write_ring(path, Some(symbols)) {
+            Ok(()) => println!(
+                "Trace: {} instructions written to {}",
+                self.ring.len(),
+                path.display()
+            ),
+            Err(error) => println!("Trace: failed to write {}: {}", path.display(), error),
+        }
+    }
+
+    fn write_ring(&self, path: &Path, symbols: Option<&Symbols>) -> std::io::Result<()> {
+        let mut output = BufWriter::new(File::create(path)?);
+        for snapshot in self.ring.iter() {
+            output.write_all(snapshot.line(symbols).as_bytes())?;
+            output.write_all(b"\n")?;
+        }
+        output.flush()
+    }
+
+    pub fn describe(&self) -> String {
+        let mode = if !self.enabled {
+            "off".to_string()
+        } else if self.output.is_some() {
+            "file".to_string()
+        } else {
+            format!("ring of {}", self.ring_size)
+        };
+        format!(
+            "trace {}, start {:?}, stop {:?}, bank {:?}",
+            mode, self.start, self.stop, self.bank
+        )
+    }
+
+    // Called before every instruction while enabled
+    pub fn trace(&mut self, cpu: &Cpu) {
+        if !self.active {
+            match self.start {
+                Some(trigger) if !trigger.fired(cpu) => return,
+                _ => self.active = true,
+            }
+        }
+        if self.stop.is_some_and(|trigger| trigger.fired(cpu)) {
+            println!("Trace: stop trigger reached at {:04X}", cpu.pc());
+            self.disable();
+            return;
+        }
+        if self.bank.is_some() && self.bank != cpu.mem.rom_bank_at(cpu.pc()) {
+            return;
+        }
+
+        let snapshot = Snapshot::new(cpu);
+        match self.output.as_mut() {
+            Some(output) => {
+                output
+                    .write_all(snapshot.line(Some(&cpu.mem.symbols)).as_bytes())
+                    .expect("failed to write trace");
+                output.write_all(b"\n").expect("failed to write trace");
+            }
+            None => {
+                if self.ring.len() >= self.ring_size {
+                    self.ring.pop_front();
+                }
+                self.ring.push_back(snapshot);
+            }
+        }
+    }
+}
+
+// Writes out the last instructions if the emulator panics. The symbols go down with the console,
+// so these lines have no labels
+impl Drop for Tracer {
+    fn drop(&mut self) {
+        if !thread::panicking() || self.ring.is_empty() {
+            return;
+        }
+        match self.write_ring(Path::new(CRASH_PATH), None) {
+            Ok(()) => eprintln!(
+                "Trace: last {} instructions written to {}",
+                self.ring.len(),
+                CRASH_PATH
+            ),
+            Err(error) => eprintln!("Trace: failed to write {}: {}", CRASH_PATH, error),
+        }
+    }
+}
+
+// The state before an instruction
+#[derive(Copy, Clone)]
+struct Snapshot {
+    af: u16,
+    bc: u16,
+    de: u16,
+    hl: u16,
+    sp: u16,
+    pc: u16,
+    bank: u16,
+    pcmem: [u8; 4],
+}
+
+impl Snapshot {
+    fn new(cpu: &Cpu) -> Snapshot {
+        let registers = cpu.registers();
+        let pc = cpu.pc();
+        Snapshot {
+            af: registers.get_af(),
+            bc: registers.get_bc(),
+            de: registers.get_de(),
+            hl: registers.get_hl(),
+            sp: cpu.sp(),
+            pc,
+            bank: cpu.mem.bank_at(pc),
+            pcmem: [0, 1, 2, 3].map(|offset| cpu.mem.peek_byte(pc.wrapping_add(offset))),
+        }
+    }
+
+    // A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02
+    fn line(&self, symbols: Option<&Symbols>) -> String {
+        let mut line = format!(
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+            self.af >> 8,
+            self.af & 0xFF,
+            self.bc >> 8,
+            self.bc & 0xFF,
+            self.de >> 8,
+            self.de & 0xFF,
+            self.hl >> 8,
+            self.hl & 0xFF,
+            self.sp,
+            self.pc,
+            self.pcmem[0],
+            self.pcmem[1],
+            self.pcmem[2],
+            self.pcmem[3],
+        );
+        // Only with a .sym file loaded, so plain traces stay comparable with Gameboy Doctor
+        if let Some(label) = symbols.and_then(|symbols| symbols.describe(self.bank, self.pc)) {
+            line.push_str(" ; ");
+            line.push_str(&label);
+        }
+        line
+    }
+}